@@ -0,0 +1,136 @@
+use adventofcode2019::expanse::{Expanse, TwoVec};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// The layout Expanse used before chunked storage, a column of rows which all grow together.
+struct Columns<T> {
+    grid: TwoVec<TwoVec<Option<T>>>,
+}
+
+impl<T> Columns<T> {
+    fn new() -> Columns<T> {
+        Columns {
+            grid: TwoVec::new(),
+        }
+    }
+
+    fn read(&self, x: i32, y: i32) -> Option<&T> {
+        if self.grid.index_range().contains(&x) && self.grid[x].index_range().contains(&y) {
+            self.grid[x][y].as_ref()
+        } else {
+            None
+        }
+    }
+
+    fn write(&mut self, x: i32, y: i32, item: T) {
+        self.grid.expand_to_contain(x, TwoVec::new);
+        for i in self.grid.index_range() {
+            self.grid[i].expand_to_contain(y, || None);
+        }
+        self.grid[x][y] = Some(item);
+    }
+}
+
+// A square spiral out from the origin, like the robots on days 11 and 15 wander.
+fn spiral(count: usize) -> Vec<(i32, i32)> {
+    let mut result = Vec::with_capacity(count);
+    let (mut x, mut y) = (0i32, 0i32);
+    let (mut dx, mut dy) = (1i32, 0i32);
+    let mut leg = 1;
+
+    while result.len() < count {
+        for _ in 0..2 {
+            for _ in 0..leg {
+                result.push((x, y));
+                x += dx;
+                y += dy;
+            }
+            let turned = (-dy, dx);
+            dx = turned.0;
+            dy = turned.1;
+        }
+        leg += 1;
+    }
+
+    result.truncate(count);
+    result
+}
+
+fn bench_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write spiral");
+
+    for &count in &[1_000usize, 10_000, 40_000] {
+        let points = spiral(count);
+
+        group.bench_with_input(BenchmarkId::new("chunked", count), &points, |b, points| {
+            b.iter(|| {
+                let mut expanse = Expanse::<i32>::new();
+                for &(x, y) in points {
+                    expanse.write(x, y, x ^ y);
+                }
+                expanse
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("columns", count), &points, |b, points| {
+            b.iter(|| {
+                let mut columns = Columns::<i32>::new();
+                for &(x, y) in points {
+                    columns.write(x, y, x ^ y);
+                }
+                columns
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_reads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read area");
+    let points = spiral(40_000);
+
+    let mut expanse = Expanse::<i32>::new();
+    let mut columns = Columns::<i32>::new();
+    for &(x, y) in &points {
+        expanse.write(x, y, x ^ y);
+        columns.write(x, y, x ^ y);
+    }
+
+    group.bench_function("chunked", |b| {
+        b.iter(|| {
+            let mut sum = 0i64;
+            for x in expanse.x_range() {
+                for y in expanse.y_range() {
+                    sum += *expanse.read(x, y).unwrap_or(&0) as i64;
+                }
+            }
+            sum
+        })
+    });
+    group.bench_function("columns", |b| {
+        b.iter(|| {
+            let mut sum = 0i64;
+            for x in expanse.x_range() {
+                for y in expanse.y_range() {
+                    sum += *columns.read(x, y).unwrap_or(&0) as i64;
+                }
+            }
+            sum
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_find(c: &mut Criterion) {
+    let mut expanse = Expanse::<i32>::new();
+    for &(x, y) in &spiral(40_000) {
+        expanse.write(x, y, x ^ y);
+    }
+
+    c.bench_function("find_many", |b| {
+        b.iter(|| expanse.find_many(|&v| v % 7 == 0))
+    });
+}
+
+criterion_group!(benches, bench_writes, bench_reads, bench_find);
+criterion_main!(benches);
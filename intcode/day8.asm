@@ -1,6 +1,10 @@
 ;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
 ;; Day 8
 ;; 
+;; Layer 2 has the fewest zeros, and layer 1 is opaque so it's the whole image.
+;! test inputs=0*100,1*50,0*5,1*45,2*100,99 expect=4500,0*100,1*50
+;; Transparent pixels show whatever is underneath.
+;! test inputs=2*150,1*75,0*75,99 expect=0,1*75,0*75
 
 readLoop:
     ; Read a digit, and if we're at the end of the input break the read loop.
@@ -11,15 +15,15 @@ readLoop:
     ; Increment the appropriate digit counter.
         cmp digit, 0, compare
         jz compare, &notZero
-        add 1, curZeroCount, curZeroCount
-        jz 0, &doneDigitCount
+        inc curZeroCount
+        jmp &doneDigitCount
     notZero:
         cmp digit, 1, compare
         jz compare, &notOne
-        add 1, curOneCount, curOneCount
-        jz 0, &doneDigitCount
+        inc curOneCount
+        jmp &doneDigitCount
     notOne:
-        add 1, curTwoCount, curTwoCount
+        inc curTwoCount
     doneDigitCount:
 
     ; Paint the current pixel value to the imageBuffer if it's still transparent
@@ -27,12 +31,12 @@ readLoop:
         add curDigitCount, &imageBuffer, pxSet
         less $pxGet, 2, compare
         jnz compare, &alreadyPainted
-        add digit, 0, $pxSet
+        mov digit, $pxSet
     alreadyPainted:
 
     ; Increment the overall counter for the layer, and if we're not done with
     ; this layer then keep reading digits.  150 = width(25) * height(6)
-        add 1, curDigitCount, curDigitCount
+        inc curDigitCount
         cmp curDigitCount, 150, compare
         jz compare, &readLoop
 
@@ -40,29 +44,29 @@ readLoop:
     ; If this layer's zero count is better than the best layer's, compute new product.
         less curZeroCount, bestZeroCount, compare
         jz compare, &thisLayerNotBetter
-        add 0, curLayer, bestLayer
-        add 0, curZeroCount, bestZeroCount
+        mov curLayer, bestLayer
+        mov curZeroCount, bestZeroCount
         mul curOneCount, curTwoCount, bestProduct
     thisLayerNotBetter:
 
     ; Reset the counters, increment the layer index, and restart the digit counting loop.
-        add 1, curLayer, curLayer
-        mul 0, curDigitCount, curDigitCount
-        mul 0, curZeroCount, curZeroCount
-        mul 0, curOneCount, curOneCount
-        mul 0, curTwoCount, curTwoCount
-        jz 0, &readLoop
+        inc curLayer
+        mov 0, curDigitCount
+        mov 0, curZeroCount
+        mov 0, curOneCount
+        mov 0, curTwoCount
+        jmp &readLoop
 
 done:
     ; Output the answer to part 1
         out bestProduct
 
     ; Loop over the image buffer and output the 150 pixel values for part 2
-        add 0, 0, curDigitCount
+        mov 0, curDigitCount
     outPart2Loop:
         add curDigitCount, &imageBuffer, pxOut
         out $pxOut
-        add 1, curDigitCount, curDigitCount
+        inc curDigitCount
         less curDigitCount, 150, compare
         jnz compare, &outPart2Loop
         
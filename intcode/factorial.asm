@@ -0,0 +1,35 @@
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+;; Read n and print n! using a recursive routine on the stack
+;; 
+;! test inputs=0 expect=1
+;! test inputs=5 expect=120
+;! test inputs=10 expect=3628800
+
+        rba &stack
+        in n
+        push n
+        call factorial
+        pop n
+        out n
+        halt
+
+;; factorial(x) overwrites its argument slot with x!
+;; Frame layout is x at ^-4, return address at ^-3, local tmp at ^-2
+factorial:
+        enter 1
+        jnz ^-4, &recurse
+        add 1, 0, ^-4
+        jz 0, &done
+    recurse:
+    ; tmp = factorial(x - 1), everything shifts down by one while the argument is pushed
+        add ^-4, -1, ^-2
+        push ^-2
+        call factorial
+        pop ^-3
+        mul ^-4, ^-2, ^-4
+    done:
+        leave
+        ret
+
+n:     dd 0
+stack: dd 0
@@ -1,6 +1,7 @@
 ;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
 ;; Print the first 25 numbers in the Fibonacci sequence
 ;; 
+;! test expect=1,1,2,3,5,8,13,21,34,55,89,144,233,377,610,987,1597,2584,4181,6765,10946,17711,28657,46368,75025
 
 loop:
         out val0
@@ -9,7 +10,7 @@ loop:
         add 0, tmp, val1
         add 1, counter, counter
         cmp counter, 25, tmp
-        jz tmp, loop
+        jz tmp, &loop
         halt
 
 val0:    dd 1
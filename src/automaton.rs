@@ -0,0 +1,283 @@
+use crate::expanse::{Expanse, NEIGHBORS4, NEIGHBORS8};
+use std::collections::HashSet;
+use std::ops::Range;
+
+// Life-like cellular automata, where each cell is alive or not and the next generation only
+// depends on how many of a cell's neighbors are alive. Conway's Life is
+// Automaton::new(cells, Neighborhood::Moore, Rules::parse("B3/S23")?, Boundary::Infinite).
+
+pub type NeighborFn = Box<dyn Fn(i32, i32) -> Vec<(i32, i32)>>;
+
+pub enum Neighborhood {
+    VonNeumann,
+    Moore,
+    // Must be symmetric, since only the neighbors of live cells are checked for births.
+    Custom(NeighborFn),
+}
+
+pub enum Boundary {
+    // Nothing lives outside the ranges.
+    Bounded(Range<i32>, Range<i32>),
+    // Neighbors off one edge are found on the opposite one.
+    Toroidal(Range<i32>, Range<i32>),
+    Infinite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    pub birth: Vec<usize>,
+    pub survival: Vec<usize>,
+}
+
+pub struct Automaton {
+    cells: Expanse<()>,
+    buffer: Expanse<()>,
+    neighborhood: Neighborhood,
+    rules: Rules,
+    boundary: Boundary,
+    generation: usize,
+}
+
+impl Rules {
+    // Reads the usual "B3/S23" notation, giving the neighbor counts for which a dead cell
+    // comes alive and a live one stays alive.
+    pub fn parse(text: &str) -> Result<Rules, String> {
+        let mut birth = None;
+        let mut survival = None;
+
+        for part in text.trim().split('/') {
+            let counts = |digits: &str| -> Result<Vec<usize>, String> {
+                digits
+                    .chars()
+                    .map(|x| match x.to_digit(10) {
+                        Some(n) => Ok(n as usize),
+                        None => Err(format!("'{}' is not a neighbor count", x)),
+                    })
+                    .collect()
+            };
+
+            if let Some(digits) = part.strip_prefix('B') {
+                birth = Some(counts(digits)?);
+            } else if let Some(digits) = part.strip_prefix('S') {
+                survival = Some(counts(digits)?);
+            } else {
+                return Err(format!("expected B or S at the start of '{}'", part));
+            }
+        }
+
+        match (birth, survival) {
+            (Some(birth), Some(survival)) => Ok(Rules { birth, survival }),
+            _ => Err(format!("'{}' needs both a B and an S part", text)),
+        }
+    }
+
+    pub fn is_alive_next(&self, alive: bool, neighbors: usize) -> bool {
+        if alive {
+            self.survival.contains(&neighbors)
+        } else {
+            self.birth.contains(&neighbors)
+        }
+    }
+}
+
+impl Boundary {
+    // Where a neighbor at (x, y) actually is, or None if it's off the edge.
+    fn resolve(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        match self {
+            Boundary::Bounded(xs, ys) => {
+                if xs.contains(&x) && ys.contains(&y) {
+                    Some((x, y))
+                } else {
+                    None
+                }
+            }
+            Boundary::Toroidal(xs, ys) if xs.is_empty() || ys.is_empty() => None,
+            Boundary::Toroidal(xs, ys) => Some((
+                xs.start + (x - xs.start).rem_euclid(xs.end - xs.start),
+                ys.start + (y - ys.start).rem_euclid(ys.end - ys.start),
+            )),
+            Boundary::Infinite => Some((x, y)),
+        }
+    }
+}
+
+impl Automaton {
+    pub fn new(
+        cells: Expanse<()>,
+        neighborhood: Neighborhood,
+        rules: Rules,
+        boundary: Boundary,
+    ) -> Automaton {
+        Automaton {
+            cells,
+            buffer: Expanse::new(),
+            neighborhood,
+            rules,
+            boundary,
+            generation: 0,
+        }
+    }
+
+    pub fn cells(&self) -> &Expanse<()> {
+        &self.cells
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    fn neighbors(&self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        let around: Vec<(i32, i32)> = match &self.neighborhood {
+            Neighborhood::VonNeumann => NEIGHBORS4
+                .iter()
+                .map(|&(dx, dy)| (x + dx, y + dy))
+                .collect(),
+            Neighborhood::Moore => NEIGHBORS8
+                .iter()
+                .map(|&(dx, dy)| (x + dx, y + dy))
+                .collect(),
+            Neighborhood::Custom(f) => f(x, y),
+        };
+
+        around
+            .into_iter()
+            .filter_map(|(x, y)| self.boundary.resolve(x, y))
+            .collect()
+    }
+
+    // The next generation is built in a second buffer, then the two are swapped.
+    pub fn step(&mut self) {
+        let mut candidates = HashSet::<(i32, i32)>::new();
+        for ((x, y), _) in self.cells.iter() {
+            candidates.insert((x, y));
+            candidates.extend(self.neighbors(x, y));
+        }
+
+        let mut next = std::mem::take(&mut self.buffer);
+        next.retain(|_, _| false);
+
+        for &(x, y) in &candidates {
+            let alive = self.cells.read(x, y).is_some();
+            let count = self
+                .neighbors(x, y)
+                .iter()
+                .filter(|&&(nx, ny)| self.cells.read(nx, ny).is_some())
+                .count();
+
+            if self.rules.is_alive_next(alive, count) {
+                next.write(x, y, ());
+            }
+        }
+
+        self.buffer = std::mem::replace(&mut self.cells, next);
+        self.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Expanse<()> {
+        Expanse::from_ascii(text, false, |x| if x == '#' { Some(()) } else { None })
+    }
+
+    fn alive(automaton: &Automaton) -> Vec<(i32, i32)> {
+        automaton.cells().cells().map(|(pos, _)| pos).collect()
+    }
+
+    fn life(cells: Expanse<()>, boundary: Boundary) -> Automaton {
+        let rules = Rules::parse("B3/S23").unwrap();
+        Automaton::new(cells, Neighborhood::Moore, rules, boundary)
+    }
+
+    #[test]
+    fn parses_rules() {
+        let conway = Rules {
+            birth: vec![3],
+            survival: vec![2, 3],
+        };
+        assert_eq!(Rules::parse("B3/S23"), Ok(conway.clone()));
+        assert_eq!(Rules::parse(" S23/B3\n"), Ok(conway));
+        assert_eq!(
+            Rules::parse("B/S012"),
+            Ok(Rules {
+                birth: vec![],
+                survival: vec![0, 1, 2],
+            })
+        );
+
+        assert_eq!(
+            Rules::parse("B3"),
+            Err(String::from("'B3' needs both a B and an S part"))
+        );
+        assert_eq!(
+            Rules::parse("B3/X23"),
+            Err(String::from("expected B or S at the start of 'X23'"))
+        );
+        assert_eq!(
+            Rules::parse("B3/S2a"),
+            Err(String::from("'a' is not a neighbor count"))
+        );
+    }
+
+    #[test]
+    fn rules_decide_births_and_survival() {
+        let rules = Rules::parse("B36/S23").unwrap();
+        assert!(rules.is_alive_next(false, 3));
+        assert!(rules.is_alive_next(false, 6));
+        assert!(!rules.is_alive_next(false, 2));
+        assert!(rules.is_alive_next(true, 2));
+        assert!(!rules.is_alive_next(true, 6));
+    }
+
+    #[test]
+    fn blinker_oscillates() {
+        let mut automaton = life(parse("...\n###\n..."), Boundary::Infinite);
+        let horizontal = alive(&automaton);
+
+        automaton.step();
+        assert_eq!(alive(&automaton), vec![(1, 0), (1, 1), (1, 2)]);
+        automaton.step();
+        assert_eq!(alive(&automaton), horizontal);
+        assert_eq!(automaton.generation(), 2);
+    }
+
+    #[test]
+    fn bounded_edges_hold_nothing() {
+        // The blinker's vertical phase would stick out of the single row.
+        let mut automaton = life(parse("###"), Boundary::Bounded(0..3, 0..1));
+
+        automaton.step();
+        assert_eq!(alive(&automaton), vec![(1, 0)]);
+        automaton.step();
+        assert!(alive(&automaton).is_empty());
+    }
+
+    #[test]
+    fn toroidal_edges_wrap_around() {
+        // A blinker split across the left and right edges of a 5x5 torus.
+        let mut automaton = life(parse(".....\n##..#\n....."), Boundary::Toroidal(0..5, 0..5));
+
+        automaton.step();
+        assert_eq!(alive(&automaton), vec![(0, 0), (0, 1), (0, 2)]);
+        automaton.step();
+        assert_eq!(alive(&automaton), vec![(0, 1), (1, 1), (4, 1)]);
+
+        // And one split across the top and bottom.
+        let mut cells = Expanse::new();
+        for &y in &[4, 0, 1] {
+            cells.write(2, y, ());
+        }
+        let mut automaton = life(cells, Boundary::Toroidal(0..5, 0..5));
+        automaton.step();
+        assert_eq!(alive(&automaton), vec![(1, 0), (2, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn empty_torus_has_no_neighbors() {
+        let mut automaton = life(parse("#"), Boundary::Toroidal(0..0, 0..5));
+        automaton.step();
+        assert!(alive(&automaton).is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+use adventofcode2019::intcode::assembler::{
+    assemble_program, format_symbols, parse_test_directives, AssembleError, Program, TestCase,
+};
+use adventofcode2019::intcode::vm::{IntCodeMachine, RunResult};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+const USAGE: &str = "usage: intcode-asm [-O] [--listing] [--symbols] [-o OUTPUT] INPUT.asm...
+       intcode-asm [-O] --test INPUT.asm...
+
+Assembles each input into the comma separated tape format, written next to the
+input as INPUT.txt unless -o is given. --listing also writes INPUT.lst and
+--symbols writes INPUT.sym alongside the tape.
+
+--test writes nothing, and instead runs every ';! test inputs=... expect=...'
+directive in the inputs and reports which ones fail. A case which hasn't halted after
+10000000 instructions fails, unless it gives its own limit with steps=N.";
+
+const DEFAULT_TEST_STEPS: usize = 10_000_000;
+
+struct Options {
+    test: bool,
+    optimize: bool,
+    listing: bool,
+    symbols: bool,
+    output: Option<PathBuf>,
+    inputs: Vec<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        test: false,
+        optimize: false,
+        listing: false,
+        symbols: false,
+        output: None,
+        inputs: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-t" | "--test" => options.test = true,
+            "-O" | "--optimize" => options.optimize = true,
+            "-l" | "--listing" => options.listing = true,
+            "-s" | "--symbols" => options.symbols = true,
+            "-o" | "--output" => match args.next() {
+                Some(x) => options.output = Some(PathBuf::from(x)),
+                None => return Err(format!("{} needs a file name", arg)),
+            },
+            "-h" | "--help" => return Err(String::new()),
+            x if x.starts_with('-') => return Err(format!("unknown option '{}'", x)),
+            x => options.inputs.push(PathBuf::from(x)),
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err(String::from("no input files"));
+    }
+    if options.output.is_some() && options.inputs.len() > 1 {
+        return Err(String::from("-o can only be used with a single input"));
+    }
+
+    Ok(options)
+}
+
+fn render_listing(source: &str, program: &Program) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut result = String::new();
+    let mut last_line = 0usize;
+
+    for entry in &program.listing {
+        let words: Vec<String> = entry.words.iter().map(|x| x.to_string()).collect();
+        let source_text = if entry.line != last_line {
+            lines[entry.line - 1].trim_end()
+        } else {
+            ""
+        };
+        last_line = entry.line;
+
+        let row = format!(
+            "{:>5}  {:<28} {:>4}  {}",
+            entry.address,
+            words.join(","),
+            entry.line,
+            source_text
+        );
+        result.push_str(row.trim_end());
+        result.push('\n');
+    }
+
+    result
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    std::fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn format_errors(input: &Path, errors: &[AssembleError]) -> String {
+    let messages: Vec<String> = errors
+        .iter()
+        .map(|x| format!("{}:{}: error: {}", input.display(), x.line, x.message))
+        .collect();
+    messages.join("\n")
+}
+
+fn join_values(values: &[i64]) -> String {
+    let strings: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+    strings.join(",")
+}
+
+// Shows a few values either side of the first mismatch rather than whole outputs.
+fn describe_difference(expect: &[i64], actual: &[i64]) -> String {
+    let first = (0..expect.len().max(actual.len()))
+        .find(|&i| expect.get(i) != actual.get(i))
+        .unwrap();
+    let start = first.saturating_sub(5);
+    let window = |values: &[i64]| {
+        let end = values.len().min(first + 6);
+        let prefix = if start > 0 { "..." } else { "" };
+        let suffix = if end < values.len() { "..." } else { "" };
+        format!(
+            "{}{}{}",
+            prefix,
+            join_values(&values[start.min(end)..end]),
+            suffix
+        )
+    };
+
+    format!(
+        "    first difference at output {}, expected {} outputs and got {}\n    expect: {}\n    actual: {}",
+        first,
+        expect.len(),
+        actual.len(),
+        window(expect),
+        window(actual)
+    )
+}
+
+// As IntCodeMachine::run_all, but giving up once the case has run out of steps.
+fn run_with_budget(tape: &[i64], inputs: &[i64], budget: usize) -> Result<Vec<i64>, String> {
+    let mut vm = IntCodeMachine::new(tape);
+    let mut inputs = inputs.iter();
+    let mut outputs = Vec::<i64>::new();
+
+    for _ in 0..budget {
+        match vm.step() {
+            Some(RunResult::Halted) => return Ok(outputs),
+            Some(RunResult::ProvidingOutput(x)) => outputs.push(x),
+            Some(RunResult::RequiresInput) => match inputs.next() {
+                Some(&x) => vm.provide_input(x),
+                None => return Err(String::from("    ran out of inputs")),
+            },
+            None => (),
+        }
+    }
+
+    Err(format!(
+        "    still running after {} steps, with {} outputs so far",
+        budget,
+        outputs.len()
+    ))
+}
+
+fn run_test_case(tape: &[i64], case: &TestCase) -> Result<(), String> {
+    let budget = case.steps.unwrap_or(DEFAULT_TEST_STEPS);
+    let result = panic::catch_unwind(|| run_with_budget(tape, &case.inputs, budget));
+
+    match result {
+        Ok(Ok(outputs)) if outputs == case.expect => Ok(()),
+        Ok(Ok(outputs)) => Err(describe_difference(&case.expect, &outputs)),
+        Ok(Err(message)) => Err(message),
+        Err(payload) => {
+            let message = match payload.downcast_ref::<String>() {
+                Some(x) => x.clone(),
+                None => String::from(*payload.downcast_ref::<&str>().unwrap_or(&"?")),
+            };
+            Err(format!("    machine panicked: {}", message))
+        }
+    }
+}
+
+fn test_file(input: &Path, options: &Options) -> Result<(), String> {
+    let source =
+        std::fs::read_to_string(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    let cases = parse_test_directives(&source).map_err(|e| format_errors(input, &e))?;
+    let program =
+        assemble_program(&source, options.optimize).map_err(|e| format_errors(input, &e))?;
+
+    let mut failures = 0usize;
+
+    for case in &cases {
+        match run_test_case(&program.tape, case) {
+            Ok(()) => println!("PASS {}:{}", input.display(), case.line),
+            Err(details) => {
+                println!("FAIL {}:{}\n{}", input.display(), case.line, details);
+                failures += 1;
+            }
+        }
+    }
+
+    println!(
+        "{}: {} passed, {} failed",
+        input.display(),
+        cases.len() - failures,
+        failures
+    );
+
+    if failures > 0 {
+        Err(format!("{}: {} tests failed", input.display(), failures))
+    } else {
+        Ok(())
+    }
+}
+
+fn assemble_file(input: &Path, options: &Options) -> Result<(), String> {
+    let source =
+        std::fs::read_to_string(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    let program =
+        assemble_program(&source, options.optimize).map_err(|e| format_errors(input, &e))?;
+
+    let output = match &options.output {
+        Some(x) => x.clone(),
+        None => input.with_extension("txt"),
+    };
+
+    let tape: Vec<String> = program.tape.iter().map(|x| x.to_string()).collect();
+    write_file(&output, &(tape.join(",") + "\n"))?;
+
+    if options.listing {
+        write_file(
+            &output.with_extension("lst"),
+            &render_listing(&source, &program),
+        )?;
+    }
+    if options.symbols {
+        write_file(
+            &output.with_extension("sym"),
+            &format_symbols(&program.symbols),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(x) => x,
+        Err(message) if message.is_empty() => {
+            println!("{}", USAGE);
+            exit(0);
+        }
+        Err(message) => {
+            eprintln!("intcode-asm: {}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+
+    if options.test {
+        panic::set_hook(Box::new(|_| {}));
+    }
+
+    let mut failed = false;
+
+    for input in &options.inputs {
+        let result = if options.test {
+            test_file(input, &options)
+        } else {
+            assemble_file(input, &options)
+        };
+
+        if let Err(message) = result {
+            eprintln!("{}", message);
+            failed = true;
+        }
+    }
+
+    if failed {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(source: &str) -> TestCase {
+        parse_test_directives(source).unwrap().remove(0)
+    }
+
+    #[test]
+    fn endless_loops_run_out_of_steps() {
+        let tape = assemble_program("loop: jnz 1, &loop", false).unwrap().tape;
+
+        assert_eq!(
+            run_test_case(&tape, &case(";! test steps=1000")),
+            Err(String::from(
+                "    still running after 1000 steps, with 0 outputs so far"
+            ))
+        );
+    }
+
+    #[test]
+    fn step_budget_is_per_case() {
+        let source = std::fs::read_to_string("intcode/fib.asm").unwrap();
+        let tape = assemble_program(&source, false).unwrap().tape;
+        let mut fib = parse_test_directives(&source).unwrap().remove(0);
+
+        assert_eq!(run_test_case(&tape, &fib), Ok(()));
+
+        fib.steps = Some(50);
+        assert_eq!(
+            run_test_case(&tape, &fib),
+            Err(String::from(
+                "    still running after 50 steps, with 8 outputs so far"
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_inputs_fail() {
+        let tape = assemble_program("in x\nhalt\nx: dd 0", false).unwrap().tape;
+
+        assert_eq!(
+            run_test_case(&tape, &case(";! test expect=")),
+            Err(String::from("    ran out of inputs"))
+        );
+    }
+}
@@ -0,0 +1,879 @@
+use adventofcode2019::framing::{read_message, write_message};
+use adventofcode2019::intcode::assembler::{assemble_program, parse_symbols, Program};
+use adventofcode2019::intcode::defs::*;
+use adventofcode2019::intcode::vm::{IntCodeMachine, RunResult};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufReader};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+// A debug adapter for assembled Intcode programs, spoken over stdio. Everything is mapped
+// back to the .asm source through the assembler's listing, and call frames are tracked by
+// watching for the push/rba/jz sequence of the stack calling convention and its matching
+// rba/jz return. A tape written by intcode-asm can be debugged too, named through the
+// symbol file written alongside it, but without a listing it's stepped an instruction at a
+// time and has no source to show.
+
+const THREAD_ID: i64 = 1;
+
+// Instructions executed between checks for a pause request while running.
+const RUN_CHUNK: usize = 10000;
+
+const REGISTERS_REFERENCE: i64 = 1;
+const LABELS_REFERENCE: i64 = 2;
+const FIRST_ARRAY_REFERENCE: i64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RunMode {
+    resume: Resume,
+    line: Option<usize>,
+    depth: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    entry: usize,
+    call_site: usize,
+    return_address: usize,
+    base: i64,
+}
+
+#[derive(Debug, Clone)]
+struct DataLabel {
+    name: String,
+    address: usize,
+    length: usize,
+}
+
+struct Session {
+    path: PathBuf,
+    program: Program,
+    vm: IntCodeMachine,
+    lines: HashMap<usize, usize>,
+    functions: HashMap<usize, String>,
+    data_labels: Vec<DataLabel>,
+    breakpoints: HashSet<usize>,
+    frames: Vec<Frame>,
+    inputs: VecDeque<i64>,
+    ascii_output: bool,
+    stop_on_entry: bool,
+    mode: Option<RunMode>,
+    halted: bool,
+    fault: Option<String>,
+}
+
+struct Adapter {
+    session: Option<Session>,
+    lines_start_at_1: bool,
+    outbox: Vec<Value>,
+}
+
+fn event(name: &str, body: Value) -> Value {
+    json!({ "type": "event", "event": name, "body": body })
+}
+
+fn output_event(category: &str, text: String) -> Value {
+    event("output", json!({ "category": category, "output": text }))
+}
+
+// Console input is either a list of numbers, or a quoted line of text which is sent as
+// ASCII codes followed by a newline.
+fn parse_console_input(text: &str) -> Result<Vec<i64>, String> {
+    let text = text.trim();
+
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        let mut values: Vec<i64> = text[1..text.len() - 1].chars().map(|x| x as i64).collect();
+        values.push(10);
+        return Ok(values);
+    }
+
+    text.split(|x: char| x == ',' || x.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse::<i64>()
+                .map_err(|_| String::from("expected numbers or a quoted string"))
+        })
+        .collect()
+}
+
+fn find_data_labels(program: &Program) -> Vec<DataLabel> {
+    let labelled: HashMap<usize, &String> = program
+        .symbols
+        .iter()
+        .map(|(name, address)| (*address as usize, name))
+        .collect();
+    let data: Vec<_> = program.listing.iter().filter(|x| x.data).collect();
+
+    let mut result = Vec::<DataLabel>::new();
+
+    for (i, entry) in data.iter().enumerate() {
+        let name = match labelled.get(&entry.address) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let mut length = entry.words.len();
+        for next in &data[i + 1..] {
+            if next.address != entry.address + length || labelled.contains_key(&next.address) {
+                break;
+            }
+            length += next.words.len();
+        }
+
+        result.push(DataLabel {
+            name: name.to_string(),
+            address: entry.address,
+            length,
+        });
+    }
+
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    result
+}
+
+// Labels come from the .sym file next to the tape if there is one.
+fn load_assembled(path: &Path, text: &str) -> Result<Program, String> {
+    let tape = text
+        .trim()
+        .split(',')
+        .map(|x| {
+            x.trim()
+                .parse::<i64>()
+                .map_err(|_| format!("{}: bad tape value '{}'", path.display(), x.trim()))
+        })
+        .collect::<Result<Vec<i64>, String>>()?;
+
+    let symbols_path = path.with_extension("sym");
+    let symbols = match std::fs::read_to_string(&symbols_path) {
+        Ok(x) => parse_symbols(&x).map_err(|e| format!("{}: {}", symbols_path.display(), e))?,
+        Err(_) => HashMap::new(),
+    };
+
+    Ok(Program {
+        tape,
+        symbols,
+        listing: Vec::new(),
+    })
+}
+
+impl Session {
+    fn launch(arguments: &Value) -> Result<Session, String> {
+        let path = match arguments["program"].as_str() {
+            Some(x) => PathBuf::from(x),
+            None => return Err(String::from("launch needs a 'program' to debug")),
+        };
+        let source =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let optimize = arguments["optimize"].as_bool().unwrap_or(false);
+        let program = if path.extension().and_then(|x| x.to_str()) == Some("asm") {
+            assemble_program(&source, optimize).map_err(|errors| {
+                let messages: Vec<String> = errors
+                    .iter()
+                    .map(|x| format!("{}:{}: error: {}", path.display(), x.line, x.message))
+                    .collect();
+                messages.join("\n")
+            })?
+        } else {
+            load_assembled(&path, &source)?
+        };
+
+        let inputs = match &arguments["input"] {
+            Value::Null => Vec::new(),
+            Value::String(x) => parse_console_input(x)?,
+            Value::Array(x) => x.iter().filter_map(|x| x.as_i64()).collect(),
+            _ => {
+                return Err(String::from(
+                    "'input' should be a string or a list of numbers",
+                ))
+            }
+        };
+
+        let lines = program
+            .listing
+            .iter()
+            .filter(|x| !x.data)
+            .map(|x| (x.address, x.line))
+            .collect();
+
+        let mut functions = HashMap::<usize, String>::new();
+        for (name, address) in &program.symbols {
+            if name.starts_with("__") {
+                continue;
+            }
+            let current = functions
+                .entry(*address as usize)
+                .or_insert_with(|| name.clone());
+            if name < current {
+                *current = name.clone();
+            }
+        }
+
+        Ok(Session {
+            path,
+            vm: IntCodeMachine::new(&program.tape),
+            data_labels: find_data_labels(&program),
+            program,
+            lines,
+            functions,
+            breakpoints: HashSet::new(),
+            frames: Vec::new(),
+            inputs: inputs.into_iter().collect(),
+            ascii_output: arguments["asciiOutput"].as_bool().unwrap_or(false),
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+            mode: None,
+            halted: false,
+            fault: None,
+        })
+    }
+
+    fn same_source(&self, path: &str) -> bool {
+        match (Path::new(path).canonicalize(), self.path.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => Path::new(path) == self.path,
+        }
+    }
+
+    // Breakpoints on lines without code slide forward to the next instruction.
+    fn set_breakpoints(&mut self, lines: &[usize]) -> Vec<Option<usize>> {
+        self.breakpoints.clear();
+
+        lines
+            .iter()
+            .map(|&line| {
+                let entry = self
+                    .program
+                    .listing
+                    .iter()
+                    .find(|x| !x.data && x.line >= line)?;
+                self.breakpoints.insert(entry.address);
+                Some(entry.line)
+            })
+            .collect()
+    }
+
+    fn function_name(&self, entry: Option<usize>) -> String {
+        match entry {
+            None => String::from("(top level)"),
+            Some(x) => match self.functions.get(&x) {
+                Some(name) => name.clone(),
+                None => format!("({})", x),
+            },
+        }
+    }
+
+    // Innermost first, as (function name, address) pairs.
+    fn stack(&self) -> Vec<(String, usize)> {
+        let mut result = Vec::<(String, usize)>::new();
+        let mut address = self.vm.ip();
+
+        for frame in self.frames.iter().rev() {
+            result.push((self.function_name(Some(frame.entry)), address));
+            address = frame.call_site;
+        }
+        result.push((self.function_name(None), address));
+
+        result
+    }
+
+    // The line of the instruction containing the address, if it's in the listing at all.
+    fn line_at(&self, address: usize) -> Option<usize> {
+        self.program
+            .listing
+            .iter()
+            .take_while(|x| x.address <= address)
+            .last()
+            .filter(|x| address < x.address + x.words.len())
+            .map(|x| x.line)
+    }
+
+    fn label_value(&self, label: &DataLabel) -> String {
+        let values: Vec<String> = (0..label.length.min(8))
+            .map(|i| self.vm.peek(label.address + i).to_string())
+            .collect();
+
+        if label.length == 1 {
+            values[0].clone()
+        } else if label.length > 8 {
+            format!("[{}, ...]", values.join(", "))
+        } else {
+            format!("[{}]", values.join(", "))
+        }
+    }
+
+    fn variables(&self, reference: i64) -> Vec<Value> {
+        match reference {
+            REGISTERS_REFERENCE => vec![
+                json!({ "name": "ip", "value": self.vm.ip().to_string(), "variablesReference": 0 }),
+                json!({
+                    "name": "relative_base",
+                    "value": self.vm.relative_base().to_string(),
+                    "variablesReference": 0,
+                }),
+            ],
+            LABELS_REFERENCE => self
+                .data_labels
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    json!({
+                        "name": x.name,
+                        "value": self.label_value(x),
+                        "variablesReference":
+                            if x.length > 1 { FIRST_ARRAY_REFERENCE + i as i64 } else { 0 },
+                        "memoryReference": x.address.to_string(),
+                    })
+                })
+                .collect(),
+            _ => match self
+                .data_labels
+                .get((reference - FIRST_ARRAY_REFERENCE) as usize)
+            {
+                Some(label) => (0..label.length)
+                    .map(|i| {
+                        json!({
+                            "name": format!("[{}]", i),
+                            "value": self.vm.peek(label.address + i).to_string(),
+                            "variablesReference": 0,
+                        })
+                    })
+                    .collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    // Anything that isn't a label or an address is queued as input, but only when typed
+    // into the debug console. Hovers and watches must never change what the program does.
+    fn evaluate(&mut self, expression: &str, context: &str) -> Result<String, String> {
+        let expression = expression.trim();
+
+        if let Some(label) = self.data_labels.iter().find(|x| x.name == expression) {
+            return Ok(self.label_value(label));
+        }
+        if let Some(address) = self.program.symbols.get(expression) {
+            return Ok(format!("&{} = {}", expression, address));
+        }
+        if expression.starts_with('[') && expression.ends_with(']') {
+            let address = expression[1..expression.len() - 1]
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| String::from("expected [address]"))?;
+            return Ok(self.vm.peek(address).to_string());
+        }
+
+        if context != "repl" {
+            return Err(format!("'{}' is not a label or [address]", expression));
+        }
+
+        let values = parse_console_input(expression)?;
+        self.inputs.extend(values.iter());
+        Ok(format!(
+            "queued {} inputs, {} waiting",
+            values.len(),
+            self.inputs.len()
+        ))
+    }
+
+    // Executes one instruction, keeping the frame stack up to date.
+    fn step(&mut self, outbox: &mut Vec<Value>) -> Result<(), String> {
+        let ip = self.vm.ip();
+        let opcode = self.vm.peek(ip) % 100;
+
+        let vm = &mut self.vm;
+        let result =
+            panic::catch_unwind(AssertUnwindSafe(|| vm.step())).map_err(|payload| match payload
+                .downcast_ref::<String>(
+            ) {
+                Some(x) => x.clone(),
+                None => String::from(*payload.downcast_ref::<&str>().unwrap_or(&"?")),
+            })?;
+
+        match result {
+            Some(RunResult::RequiresInput) => {
+                let input = self.inputs.pop_front().unwrap();
+                self.vm.provide_input(input);
+            }
+            Some(RunResult::ProvidingOutput(x)) => {
+                let text = if self.ascii_output && (0..128).contains(&x) {
+                    (x as u8 as char).to_string()
+                } else {
+                    format!("{}\n", x)
+                };
+                outbox.push(output_event("stdout", text));
+            }
+            Some(RunResult::Halted) => self.halted = true,
+            None => (),
+        }
+
+        let new_ip = self.vm.ip();
+        let base = self.vm.relative_base();
+
+        let returned = match self.frames.last() {
+            Some(x) => new_ip == x.return_address && base == x.base - 1,
+            None => false,
+        };
+
+        if returned {
+            self.frames.pop();
+        } else if (opcode == I_JZ || opcode == I_JNZ)
+            && new_ip != ip + 3
+            && base > 0
+            && self.vm.peek((base - 1) as usize) == (ip + 3) as i64
+        {
+            self.frames.push(Frame {
+                entry: new_ip,
+                call_site: ip,
+                return_address: ip + 3,
+                base,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Where a step can stop: at the start of a source line, or at any instruction of a
+    // tape without a listing.
+    fn step_line(&self, address: usize) -> Option<usize> {
+        if self.program.listing.is_empty() {
+            Some(address)
+        } else {
+            self.lines.get(&address).copied()
+        }
+    }
+
+    fn step_complete(&self, mode: &RunMode) -> bool {
+        let depth = self.frames.len();
+        let line = self.step_line(self.vm.ip());
+
+        match mode.resume {
+            Resume::Continue => false,
+            Resume::StepIn => line.is_some() && (line != mode.line || depth != mode.depth),
+            Resume::StepOver => {
+                line.is_some() && (depth < mode.depth || (depth == mode.depth && line != mode.line))
+            }
+            Resume::StepOut => depth < mode.depth,
+        }
+    }
+
+    fn resume(&mut self, resume: Resume, outbox: &mut Vec<Value>) {
+        if let Some(message) = &self.fault {
+            outbox.push(stopped_event("exception", Some(message.clone())));
+            return;
+        }
+        if self.halted {
+            return;
+        }
+
+        self.mode = Some(RunMode {
+            resume,
+            line: self.line_at(self.vm.ip()).or(self.step_line(self.vm.ip())),
+            depth: self.frames.len(),
+        });
+    }
+
+    fn stop(&mut self, reason: &str, description: Option<String>, outbox: &mut Vec<Value>) {
+        self.mode = None;
+        outbox.push(stopped_event(reason, description));
+    }
+
+    // Runs at most `budget` instructions of the current resume request.
+    fn advance(&mut self, budget: usize, outbox: &mut Vec<Value>) {
+        for _ in 0..budget {
+            let mode = match self.mode {
+                Some(x) => x,
+                None => return,
+            };
+
+            if self.vm.peek(self.vm.ip()) % 100 == I_IN && self.inputs.is_empty() {
+                outbox.push(output_event(
+                    "console",
+                    String::from("Waiting for input, type numbers or a \"quoted line\" into the debug console.\n"),
+                ));
+                self.stop("pause", Some(String::from("Waiting for input")), outbox);
+                return;
+            }
+
+            if let Err(message) = self.step(outbox) {
+                outbox.push(output_event("stderr", format!("{}\n", message)));
+                self.fault = Some(message.clone());
+                self.stop("exception", Some(message), outbox);
+                return;
+            }
+
+            if self.halted {
+                self.mode = None;
+                outbox.push(event("exited", json!({ "exitCode": 0 })));
+                outbox.push(event("terminated", json!({})));
+                return;
+            }
+
+            if self.breakpoints.contains(&self.vm.ip()) {
+                self.stop("breakpoint", None, outbox);
+                return;
+            }
+
+            if self.step_complete(&mode) {
+                self.stop("step", None, outbox);
+                return;
+            }
+        }
+    }
+}
+
+fn stopped_event(reason: &str, description: Option<String>) -> Value {
+    let mut body = json!({
+        "reason": reason,
+        "threadId": THREAD_ID,
+        "allThreadsStopped": true,
+    });
+    if let Some(x) = description {
+        body["description"] = json!(x.clone());
+        body["text"] = json!(x);
+    }
+    event("stopped", body)
+}
+
+impl Adapter {
+    fn session(&mut self) -> Result<&mut Session, String> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| String::from("no program has been launched"))
+    }
+
+    fn client_line(&self, line: usize) -> usize {
+        if self.lines_start_at_1 {
+            line
+        } else {
+            line - 1
+        }
+    }
+
+    fn source_line(&self, line: usize) -> usize {
+        if self.lines_start_at_1 {
+            line
+        } else {
+            line + 1
+        }
+    }
+
+    fn source(&self, session: &Session) -> Value {
+        let name = session
+            .path
+            .file_name()
+            .map_or(String::new(), |x| x.to_string_lossy().to_string());
+        let path = session
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| session.path.clone());
+
+        json!({ "name": name, "path": path.to_string_lossy() })
+    }
+
+    fn initialize(&mut self, arguments: &Value) -> Value {
+        self.lines_start_at_1 = arguments["linesStartAt1"].as_bool().unwrap_or(true);
+
+        json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsEvaluateForHovers": true,
+        })
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        self.session = Some(Session::launch(arguments)?);
+        self.outbox.push(event("initialized", json!({})));
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let lines: Vec<usize> = arguments["breakpoints"].as_array().map_or(Vec::new(), |x| {
+            x.iter()
+                .map(|x| self.source_line(x["line"].as_u64().unwrap_or(0) as usize))
+                .collect()
+        });
+        let path = String::from(arguments["source"]["path"].as_str().unwrap_or(""));
+
+        let session = self.session()?;
+        if !session.same_source(&path) {
+            let unverified: Vec<Value> = lines
+                .iter()
+                .map(|_| json!({ "verified": false, "message": "not the launched program" }))
+                .collect();
+            return Ok(json!({ "breakpoints": unverified }));
+        }
+
+        let placed = session.set_breakpoints(&lines);
+        let breakpoints: Vec<Value> = placed
+            .iter()
+            .map(|x| match x {
+                Some(line) => json!({ "verified": true, "line": self.client_line(*line) }),
+                None => json!({ "verified": false, "message": "no code on or after this line" }),
+            })
+            .collect();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        let mut outbox = Vec::<Value>::new();
+        let session = self.session()?;
+
+        // Later continues step off the breakpoint they stopped at before checking, so one on
+        // the very first instruction has to be caught here.
+        if session.stop_on_entry {
+            session.stop("entry", None, &mut outbox);
+        } else if session.breakpoints.contains(&session.vm.ip()) {
+            session.stop("breakpoint", None, &mut outbox);
+        } else {
+            session.resume(Resume::Continue, &mut outbox);
+        }
+
+        self.outbox.extend(outbox);
+        Ok(Value::Null)
+    }
+
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let session = self
+            .session
+            .as_ref()
+            .ok_or("no program has been launched")?;
+        let source = self.source(session);
+
+        let frames: Vec<Value> = session
+            .stack()
+            .iter()
+            .enumerate()
+            .map(|(i, (name, address))| {
+                let mut frame = json!({
+                    "id": i,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": address.to_string(),
+                });
+                if let Some(line) = session.line_at(*address) {
+                    frame["line"] = json!(self.client_line(line));
+                    frame["column"] = json!(if self.lines_start_at_1 { 1 } else { 0 });
+                    frame["source"] = source.clone();
+                }
+                frame
+            })
+            .collect();
+
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn resume(&mut self, resume: Resume) -> Result<Value, String> {
+        let mut outbox = Vec::<Value>::new();
+        self.session()?.resume(resume, &mut outbox);
+        self.outbox.extend(outbox);
+        Ok(json!({ "allThreadsContinued": true }))
+    }
+
+    fn pause(&mut self) -> Result<Value, String> {
+        let mut outbox = Vec::<Value>::new();
+        let session = self.session()?;
+        if session.mode.is_some() {
+            session.stop("pause", None, &mut outbox);
+        }
+        self.outbox.extend(outbox);
+        Ok(Value::Null)
+    }
+
+    fn handle_request(&mut self, command: &str, arguments: &Value) -> Result<Value, String> {
+        match command {
+            "initialize" => Ok(self.initialize(arguments)),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "intcode" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Labels", "variablesReference": LABELS_REFERENCE, "expensive": false },
+                ],
+            })),
+            "variables" => {
+                let reference = arguments["variablesReference"].as_i64().unwrap_or(0);
+                let variables = self.session()?.variables(reference);
+                Ok(json!({ "variables": variables }))
+            }
+            "evaluate" => {
+                let expression = arguments["expression"].as_str().unwrap_or("");
+                let context = arguments["context"].as_str().unwrap_or("");
+                let result = self.session()?.evaluate(expression, context)?;
+                Ok(json!({ "result": result, "variablesReference": 0 }))
+            }
+            "continue" => self.resume(Resume::Continue),
+            "next" => self.resume(Resume::StepOver),
+            "stepIn" => self.resume(Resume::StepIn),
+            "stepOut" => self.resume(Resume::StepOut),
+            "pause" => self.pause(),
+            "disconnect" => Ok(Value::Null),
+            _ => Err(format!("unsupported request {}", command)),
+        }
+    }
+
+    fn running(&self) -> bool {
+        matches!(&self.session, Some(x) if x.mode.is_some())
+    }
+
+    fn advance(&mut self) {
+        let mut outbox = Vec::<Value>::new();
+        if let Some(session) = self.session.as_mut() {
+            session.advance(RUN_CHUNK, &mut outbox);
+        }
+        self.outbox.extend(outbox);
+    }
+}
+
+struct Connection {
+    stdout: io::Stdout,
+    seq: i64,
+}
+
+impl Connection {
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.stdout, &message)
+    }
+}
+
+fn main() -> io::Result<()> {
+    panic::set_hook(Box::new(|_| {}));
+
+    // Requests are read on their own thread so a pause can arrive while the program runs.
+    let (sender, receiver) = mpsc::channel::<Value>();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(io::stdin());
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut connection = Connection {
+        stdout: io::stdout(),
+        seq: 0,
+    };
+    let mut adapter = Adapter {
+        session: None,
+        lines_start_at_1: true,
+        outbox: Vec::new(),
+    };
+
+    loop {
+        let message = if adapter.running() {
+            match receiver.try_recv() {
+                Ok(x) => Some(x),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(x) => Some(x),
+                Err(_) => break,
+            }
+        };
+
+        if let Some(request) = message.filter(|x| x["type"] == "request") {
+            let command = request["command"].as_str().unwrap_or("");
+            let mut response = json!({
+                "type": "response",
+                "request_seq": request["seq"],
+                "command": command,
+            });
+
+            match adapter.handle_request(command, &request["arguments"]) {
+                Ok(body) => {
+                    response["success"] = json!(true);
+                    if !body.is_null() {
+                        response["body"] = body;
+                    }
+                }
+                Err(message) => {
+                    response["success"] = json!(false);
+                    response["message"] = json!(message);
+                }
+            }
+            connection.send(response)?;
+
+            if command == "disconnect" {
+                exit(0);
+            }
+        }
+
+        adapter.advance();
+        for message in adapter.outbox.drain(..) {
+            connection.send(message)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factorial() -> Session {
+        Session::launch(&json!({ "program": "intcode/factorial.asm" })).unwrap()
+    }
+
+    #[test]
+    fn console_input_is_queued_from_the_repl() {
+        let mut session = factorial();
+
+        assert_eq!(
+            session.evaluate("3, 4", "repl"),
+            Ok(String::from("queued 2 inputs, 2 waiting"))
+        );
+        assert_eq!(
+            session.evaluate("\"ab\"", "repl"),
+            Ok(String::from("queued 3 inputs, 5 waiting"))
+        );
+        assert_eq!(session.inputs, vec![3, 4, 97, 98, 10]);
+    }
+
+    #[test]
+    fn hovers_and_watches_never_queue_input() {
+        for context in &["hover", "watch", "clipboard", ""] {
+            let mut session = factorial();
+
+            assert!(session.evaluate("3", context).is_err());
+            assert!(session.evaluate("\"ab\"", context).is_err());
+            assert!(session.inputs.is_empty());
+        }
+    }
+
+    #[test]
+    fn labels_and_addresses_evaluate_in_every_context() {
+        for context in &["repl", "hover", "watch"] {
+            let mut session = factorial();
+
+            assert_eq!(session.evaluate("n", context), Ok(String::from("0")));
+            assert_eq!(
+                session.evaluate("factorial", context),
+                Ok(String::from("&factorial = 28"))
+            );
+            assert_eq!(session.evaluate("[0]", context), Ok(String::from("109")));
+            assert!(session.inputs.is_empty());
+        }
+    }
+}
@@ -0,0 +1,786 @@
+use adventofcode2019::intcode::assembler::{assemble_program, parse_symbols};
+use adventofcode2019::intcode::defs::*;
+use adventofcode2019::intcode::vm::{IntCodeMachine, RunResult};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process::exit;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+// A GDB remote serial protocol stub around IntCodeMachine. gdb sees memory as bytes, so
+// cell n is presented as the eight little endian bytes at address 8*n, and the ip register
+// is reported the same way so that $pc lines up with memory and breakpoint addresses.
+// relative_base is a plain number of cells.
+
+const USAGE: &str = "usage: intcode-gdbstub [--port PORT | --pipe] [--input VALUES] PROGRAM
+
+Serves PROGRAM, either an assembler .asm file or a comma separated tape, to gdb.
+With --port it listens on 127.0.0.1:PORT for one connection (target remote :PORT),
+and with --pipe it talks over stdin and stdout (target remote | intcode-gdbstub
+--pipe PROGRAM). --input gives comma separated values to feed the machine, and more
+can be queued from gdb with 'monitor input VALUES'. A tape's labels are read from the
+.sym file intcode-asm writes next to it, and 'monitor symbols' lists their addresses.";
+
+const CELL_BYTES: usize = 8;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGTTIN: u8 = 21;
+
+// The most memory one m or M packet can move, which is what fits in PacketSize as hex.
+const MAX_TRANSFER: usize = 0x2000;
+
+// Instructions executed between checks for an interrupt from gdb while continuing.
+const RUN_CHUNK: usize = 10000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.intcode.core">
+    <reg name="ip" bitsize="64" type="code_ptr" regnum="0"/>
+    <reg name="relative_base" bitsize="64" type="int64" regnum="1"/>
+  </feature>
+</target>
+"#;
+
+enum Incoming {
+    Packet(String),
+    BadChecksum,
+    Interrupt,
+}
+
+enum Stop {
+    Signal(u8),
+    Breakpoint,
+    Exited,
+}
+
+struct Options {
+    port: Option<u16>,
+    program: String,
+    inputs: Vec<i64>,
+}
+
+struct Stub {
+    vm: IntCodeMachine,
+    symbols: HashMap<String, i64>,
+    breakpoints: HashSet<usize>,
+    inputs: VecDeque<i64>,
+    outputs: Vec<i64>,
+    halted: bool,
+    fault: bool,
+}
+
+fn parse_values(text: &str) -> Result<Vec<i64>, String> {
+    text.split(|x: char| x == ',' || x.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse::<i64>().map_err(|_| format!("bad value '{}'", x)))
+        .collect()
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut port = None;
+    let mut pipe = false;
+    let mut inputs = Vec::new();
+    let mut program = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" | "--port" => match args.next().map(|x| x.parse::<u16>()) {
+                Some(Ok(x)) => port = Some(x),
+                _ => return Err(format!("{} needs a port number", arg)),
+            },
+            "--pipe" => pipe = true,
+            "-i" | "--input" => match args.next() {
+                Some(x) => inputs.extend(parse_values(x)?),
+                None => return Err(format!("{} needs some values", arg)),
+            },
+            "-h" | "--help" => return Err(String::new()),
+            x if x.starts_with('-') => return Err(format!("unknown option '{}'", x)),
+            x if program.is_none() => program = Some(String::from(x)),
+            _ => return Err(String::from("only one program can be served")),
+        }
+    }
+
+    if port.is_some() == pipe {
+        return Err(String::from("exactly one of --port or --pipe is needed"));
+    }
+
+    match program {
+        Some(program) => Ok(Options {
+            port,
+            program,
+            inputs,
+        }),
+        None => Err(String::from("no program given")),
+    }
+}
+
+// The tape along with its labels, which for an assembled tape come from the .sym file next
+// to it if there is one.
+fn load_program(path: &str) -> Result<(Vec<i64>, HashMap<String, i64>), String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    if !path.ends_with(".asm") {
+        let tape = source
+            .trim()
+            .split(',')
+            .map(|x| {
+                x.trim()
+                    .parse::<i64>()
+                    .map_err(|_| format!("{}: bad tape value '{}'", path, x.trim()))
+            })
+            .collect::<Result<Vec<i64>, String>>()?;
+
+        let symbols_path = Path::new(path).with_extension("sym");
+        let symbols = match std::fs::read_to_string(&symbols_path) {
+            Ok(x) => parse_symbols(&x).map_err(|e| format!("{}: {}", symbols_path.display(), e))?,
+            Err(_) => HashMap::new(),
+        };
+
+        return Ok((tape, symbols));
+    }
+
+    match assemble_program(&source, false) {
+        Ok(program) => Ok((program.tape, program.symbols)),
+        Err(errors) => {
+            let messages: Vec<String> = errors
+                .iter()
+                .map(|x| format!("{}:{}: error: {}", path, x.line, x.message))
+                .collect();
+            Err(messages.join("\n"))
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 == 1 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex_usize(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn register_hex(value: i64) -> String {
+    to_hex(&value.to_le_bytes())
+}
+
+fn parse_register(text: &str) -> Option<i64> {
+    let bytes = from_hex(text)?;
+    let mut word = [0u8; 8];
+    if bytes.len() != word.len() {
+        return None;
+    }
+    word.copy_from_slice(&bytes);
+    Some(i64::from_le_bytes(word))
+}
+
+// Splits "addr,length" with an optional ":data" tail, all in hex.
+fn parse_range(text: &str) -> Option<(usize, usize, &str)> {
+    let mut parts = text.splitn(2, ':');
+    let range = parts.next()?;
+    let data = parts.next().unwrap_or("");
+    let mut range = range.splitn(2, ',');
+    let address = parse_hex_usize(range.next()?)?;
+    let length = parse_hex_usize(range.next()?)?;
+    Some((address, length, data))
+}
+
+// Reads packets on a separate thread, so that an interrupt can arrive while the machine
+// is running.
+fn spawn_reader(input: Box<dyn Read + Send>, sender: Sender<Incoming>) {
+    thread::spawn(move || {
+        let mut bytes = BufReader::new(input).bytes().map_while(Result::ok);
+
+        while let Some(byte) = bytes.next() {
+            let incoming = match byte {
+                0x03 => Incoming::Interrupt,
+                b'$' => {
+                    let body: Vec<u8> = bytes.by_ref().take_while(|&x| x != b'#').collect();
+                    let checksum: Vec<u8> = bytes.by_ref().take(2).collect();
+                    let expected = body.iter().fold(0u8, |sum, x| sum.wrapping_add(*x));
+
+                    match std::str::from_utf8(&checksum)
+                        .ok()
+                        .and_then(|x| u8::from_str_radix(x, 16).ok())
+                    {
+                        Some(x) if x == expected => {
+                            Incoming::Packet(String::from_utf8_lossy(&body).to_string())
+                        }
+                        _ => Incoming::BadChecksum,
+                    }
+                }
+                _ => continue,
+            };
+
+            if sender.send(incoming).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+struct Connection {
+    output: Box<dyn Write>,
+    acks: bool,
+}
+
+impl Connection {
+    fn send(&mut self, body: &str) -> io::Result<()> {
+        let checksum = body.bytes().fold(0u8, |sum, x| sum.wrapping_add(x));
+        write!(self.output, "${}#{:02x}", body, checksum)?;
+        self.output.flush()
+    }
+
+    fn ack(&mut self, ok: bool) -> io::Result<()> {
+        if self.acks {
+            self.output.write_all(if ok { b"+" } else { b"-" })?;
+            self.output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Stub {
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Exited => String::from("W00"),
+            Stop::Breakpoint => format!("T{:02x}swbreak:;", SIGTRAP),
+            Stop::Signal(x) => format!("S{:02x}", x),
+        }
+    }
+
+    // None if the range is too long or runs off the end of the address space.
+    fn read_memory(&self, address: usize, length: usize) -> Option<Vec<u8>> {
+        if length > MAX_TRANSFER {
+            return None;
+        }
+        let end = address.checked_add(length)?;
+
+        Some(
+            (address..end)
+                .map(|x| self.vm.peek(x / CELL_BYTES).to_le_bytes()[x % CELL_BYTES])
+                .collect(),
+        )
+    }
+
+    fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Option<()> {
+        if bytes.len() > MAX_TRANSFER {
+            return None;
+        }
+        let end = address.checked_add(bytes.len())?;
+
+        for (x, byte) in (address..end).zip(bytes) {
+            let cell = x / CELL_BYTES;
+            let mut word = self.vm.peek(cell).to_le_bytes();
+            word[x % CELL_BYTES] = *byte;
+            self.vm.poke(cell, i64::from_le_bytes(word));
+        }
+        Some(())
+    }
+
+    // Executes a single instruction, or says why it can't.
+    fn step(&mut self) -> Option<Stop> {
+        if self.halted {
+            return Some(Stop::Exited);
+        }
+        if self.fault {
+            return Some(Stop::Signal(SIGILL));
+        }
+        if self.vm.peek(self.vm.ip()) % 100 == I_IN && self.inputs.is_empty() {
+            return Some(Stop::Signal(SIGTTIN));
+        }
+
+        let vm = &mut self.vm;
+        match panic::catch_unwind(AssertUnwindSafe(|| vm.step())) {
+            Err(_) => {
+                self.fault = true;
+                Some(Stop::Signal(SIGILL))
+            }
+            Ok(Some(RunResult::Halted)) => {
+                self.halted = true;
+                Some(Stop::Exited)
+            }
+            Ok(Some(RunResult::RequiresInput)) => {
+                let input = self.inputs.pop_front().unwrap();
+                self.vm.provide_input(input);
+                None
+            }
+            Ok(Some(RunResult::ProvidingOutput(x))) => {
+                self.outputs.push(x);
+                None
+            }
+            Ok(None) => None,
+        }
+    }
+
+    fn run_chunk(&mut self) -> Option<Stop> {
+        for _ in 0..RUN_CHUNK {
+            if let Some(stop) = self.step() {
+                return Some(stop);
+            }
+            if self.breakpoints.contains(&self.vm.ip()) {
+                return Some(Stop::Breakpoint);
+            }
+        }
+        None
+    }
+
+    fn monitor(&mut self, command: &str) -> String {
+        let mut words = command.trim().splitn(2, ' ');
+
+        match (words.next(), words.next()) {
+            (Some("input"), Some(values)) => match parse_values(values) {
+                Ok(values) => {
+                    self.inputs.extend(values.iter());
+                    format!("{} inputs waiting\n", self.inputs.len())
+                }
+                Err(message) => format!("{}\n", message),
+            },
+            (Some("input"), None) => {
+                let waiting: Vec<String> = self.inputs.iter().map(|x| x.to_string()).collect();
+                format!("waiting: {}\n", waiting.join(","))
+            }
+            (Some("symbols"), None) => {
+                let mut sorted: Vec<(&String, &i64)> = self.symbols.iter().collect();
+                sorted.sort_by_key(|x| (*x.1, x.0));
+                sorted
+                    .iter()
+                    .map(|(name, address)| {
+                        format!("{:#x} {}\n", **address as usize * CELL_BYTES, name)
+                    })
+                    .collect()
+            }
+            _ => String::from(
+                "monitor input VALUES  queue comma separated input values\n\
+                 monitor input         show the queued input values\n\
+                 monitor symbols       list the program's labels by address\n",
+            ),
+        }
+    }
+
+    // Replies to every packet except continue, which the main loop handles itself.
+    fn handle_packet(&mut self, packet: &str) -> String {
+        let (command, rest) = packet.split_at(packet.chars().next().map_or(0, |x| x.len_utf8()));
+
+        match command {
+            "?" => self.stop_reply(Stop::Signal(SIGTRAP)),
+            "g" => {
+                let ip = (self.vm.ip() * CELL_BYTES) as i64;
+                register_hex(ip) + &register_hex(self.vm.relative_base())
+            }
+            "G" => match (
+                rest.get(..16).and_then(parse_register),
+                rest.get(16..32).and_then(parse_register),
+            ) {
+                (Some(ip), Some(base)) => {
+                    self.vm.set_ip(ip as usize / CELL_BYTES);
+                    self.vm.set_relative_base(base);
+                    String::from("OK")
+                }
+                _ => String::from("E01"),
+            },
+            "p" => match parse_hex_usize(rest) {
+                Some(0) => register_hex((self.vm.ip() * CELL_BYTES) as i64),
+                Some(1) => register_hex(self.vm.relative_base()),
+                _ => String::from("E01"),
+            },
+            "P" => {
+                let mut parts = rest.splitn(2, '=');
+                let register = parts.next().and_then(parse_hex_usize);
+                let value = parts.next().and_then(parse_register);
+                match (register, value) {
+                    (Some(0), Some(x)) => self.vm.set_ip(x as usize / CELL_BYTES),
+                    (Some(1), Some(x)) => self.vm.set_relative_base(x),
+                    _ => return String::from("E01"),
+                }
+                String::from("OK")
+            }
+            "m" => match parse_range(rest).and_then(|(x, length, _)| self.read_memory(x, length)) {
+                Some(bytes) => to_hex(&bytes),
+                None => String::from("E01"),
+            },
+            "M" => match parse_range(rest) {
+                Some((address, length, data)) => match from_hex(data) {
+                    Some(bytes) if bytes.len() == length => {
+                        match self.write_memory(address, &bytes) {
+                            Some(()) => String::from("OK"),
+                            None => String::from("E01"),
+                        }
+                    }
+                    _ => String::from("E01"),
+                },
+                None => String::from("E01"),
+            },
+            "s" => {
+                if let Some(x) = parse_hex_usize(rest) {
+                    self.vm.set_ip(x / CELL_BYTES);
+                }
+                let stop = self.step().unwrap_or(Stop::Signal(SIGTRAP));
+                self.stop_reply(stop)
+            }
+            "Z" | "z" => {
+                let mut parts = rest.split(',');
+                let kind = parts.next();
+                let address = parts.next().and_then(parse_hex_usize);
+                match (kind, address) {
+                    (Some("0"), Some(x)) | (Some("1"), Some(x)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(x / CELL_BYTES);
+                        } else {
+                            self.breakpoints.remove(&(x / CELL_BYTES));
+                        }
+                        String::from("OK")
+                    }
+                    _ => String::new(),
+                }
+            }
+            "H" | "T" => String::from("OK"),
+            "q" if rest.starts_with("Supported") => {
+                String::from("PacketSize=4000;qXfer:features:read+;swbreak+;QStartNoAckMode+")
+            }
+            "q" if rest.starts_with("Xfer:features:read:target.xml:") => {
+                match parse_range(&rest["Xfer:features:read:target.xml:".len()..]) {
+                    Some((offset, length, _)) => {
+                        let start = offset.min(TARGET_XML.len());
+                        let end = (offset + length).min(TARGET_XML.len());
+                        let marker = if end == TARGET_XML.len() { "l" } else { "m" };
+                        format!("{}{}", marker, &TARGET_XML[start..end])
+                    }
+                    None => String::from("E01"),
+                }
+            }
+            "q" if rest.starts_with("Rcmd,") => match from_hex(&rest["Rcmd,".len()..]) {
+                Some(bytes) => to_hex(self.monitor(&String::from_utf8_lossy(&bytes)).as_bytes()),
+                None => String::from("E01"),
+            },
+            "q" if rest == "Attached" => String::from("1"),
+            "q" if rest == "C" => String::from("QC1"),
+            "q" if rest == "fThreadInfo" => String::from("m1"),
+            "q" if rest == "sThreadInfo" => String::from("l"),
+            _ => String::new(),
+        }
+    }
+}
+
+fn serve(
+    stub: &mut Stub,
+    receiver: Receiver<Incoming>,
+    mut connection: Connection,
+) -> io::Result<()> {
+    let mut running = false;
+
+    loop {
+        let incoming = if running {
+            match receiver.try_recv() {
+                Ok(x) => Some(x),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match receiver.recv() {
+                Ok(x) => Some(x),
+                Err(_) => return Ok(()),
+            }
+        };
+
+        match incoming {
+            Some(Incoming::BadChecksum) => connection.ack(false)?,
+            Some(Incoming::Interrupt) if running => {
+                running = false;
+                connection.send(&stub.stop_reply(Stop::Signal(SIGINT)))?;
+            }
+            Some(Incoming::Interrupt) => (),
+            Some(Incoming::Packet(packet)) => {
+                connection.ack(true)?;
+
+                if packet == "QStartNoAckMode" {
+                    connection.send("OK")?;
+                    connection.acks = false;
+                } else if let Some(address) = packet.strip_prefix('c') {
+                    if let Some(x) = parse_hex_usize(address) {
+                        stub.vm.set_ip(x / CELL_BYTES);
+                    }
+                    running = true;
+                } else if packet == "k" {
+                    return Ok(());
+                } else if packet.starts_with('D') {
+                    connection.send("OK")?;
+                    return Ok(());
+                } else {
+                    let reply = stub.handle_packet(&packet);
+                    connection.send(&reply)?;
+                }
+            }
+            None => (),
+        }
+
+        if running {
+            let stop = stub.run_chunk();
+            for x in stub.outputs.drain(..) {
+                connection.send(&format!("O{}", to_hex(format!("{}\n", x).as_bytes())))?;
+            }
+            if let Some(stop) = stop {
+                running = false;
+                connection.send(&stub.stop_reply(stop))?;
+            }
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(x) => x,
+        Err(message) if message.is_empty() => {
+            println!("{}", USAGE);
+            exit(0);
+        }
+        Err(message) => {
+            eprintln!("intcode-gdbstub: {}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+
+    let (tape, symbols) = match load_program(&options.program) {
+        Ok(x) => x,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(1);
+        }
+    };
+
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut stub = Stub {
+        vm: IntCodeMachine::new(&tape),
+        symbols,
+        breakpoints: HashSet::new(),
+        inputs: options.inputs.into_iter().collect(),
+        outputs: Vec::new(),
+        halted: false,
+        fault: false,
+    };
+
+    let (sender, receiver) = mpsc::channel::<Incoming>();
+
+    let connection = match options.port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            eprintln!("intcode-gdbstub: listening on 127.0.0.1:{}", port);
+            let (stream, _) = listener.accept()?;
+            stream.set_nodelay(true)?;
+            spawn_reader(Box::new(stream.try_clone()?), sender);
+            Connection {
+                output: Box::new(stream),
+                acks: true,
+            }
+        }
+        None => {
+            spawn_reader(Box::new(io::stdin()), sender);
+            Connection {
+                output: Box::new(io::stdout()),
+                acks: true,
+            }
+        }
+    };
+
+    serve(&mut stub, receiver, connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Collects what the stub sends, so that it can be looked at after serve returns.
+    struct Sent(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Sent {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn factorial(inputs: &[i64]) -> Stub {
+        let (tape, symbols) = load_program("intcode/factorial.asm").unwrap();
+        Stub {
+            vm: IntCodeMachine::new(&tape),
+            symbols,
+            breakpoints: HashSet::new(),
+            inputs: inputs.iter().copied().collect(),
+            outputs: Vec::new(),
+            halted: false,
+            fault: false,
+        }
+    }
+
+    fn packet(body: &str) -> String {
+        let checksum = body.bytes().fold(0u8, |sum, x| sum.wrapping_add(x));
+        format!("${}#{:02x}", body, checksum)
+    }
+
+    // Feeds raw bytes through the packet reader and the main loop, giving back everything
+    // written to gdb.
+    fn serve_bytes(stub: &mut Stub, bytes: &str) -> String {
+        let (sender, receiver) = mpsc::channel::<Incoming>();
+        spawn_reader(Box::new(io::Cursor::new(bytes.to_string())), sender);
+
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let connection = Connection {
+            output: Box::new(Sent(sent.clone())),
+            acks: true,
+        };
+        serve(stub, receiver, connection).unwrap();
+
+        let sent = sent.borrow();
+        String::from_utf8_lossy(&sent).to_string()
+    }
+
+    fn serve_packets(stub: &mut Stub, packets: &[&str]) -> String {
+        let bytes: String = packets.iter().map(|x| packet(x)).collect();
+        serve_bytes(stub, &bytes)
+    }
+
+    #[test]
+    fn checksums_are_checked_and_sent() {
+        let mut stub = factorial(&[]);
+
+        assert_eq!(packet("OK"), "$OK#9a");
+        assert_eq!(serve_bytes(&mut stub, "$?#3f"), "+$S05#b8");
+        assert_eq!(serve_bytes(&mut stub, "$?#40"), "-");
+        assert_eq!(serve_bytes(&mut stub, "$?#zz"), "-");
+        assert_eq!(
+            serve_bytes(&mut stub, "$?#00$?#3f"),
+            "-+$S05#b8",
+            "a bad packet shouldn't swallow the next one"
+        );
+        assert_eq!(
+            serve_packets(&mut stub, &["QStartNoAckMode", "?"]),
+            format!("+{}{}", packet("OK"), packet("S05"))
+        );
+    }
+
+    #[test]
+    fn memory_reads_and_writes_whole_cells_as_bytes() {
+        let mut stub = factorial(&[]);
+        let first = stub.vm.peek(1);
+
+        // rba &stack, with the cells' little endian bytes at 8*n.
+        assert_eq!(stub.handle_packet("m0,2"), "6d00");
+        assert_eq!(stub.handle_packet("m8,8"), register_hex(first));
+
+        assert_eq!(stub.handle_packet("M8,2:3412"), "OK");
+        assert_eq!(stub.vm.peek(1), first & !0xffff | 0x1234);
+        assert_eq!(stub.handle_packet("M3f8,8:ffffffffffffffff"), "OK");
+        assert_eq!(stub.vm.peek(127), -1);
+        assert_eq!(stub.handle_packet("m3fc,4"), "ffffffff");
+    }
+
+    #[test]
+    fn bad_memory_ranges_are_refused() {
+        let mut stub = factorial(&[]);
+        let length = stub.vm.peek(0);
+
+        for packet in &[
+            "mffffffffffffffff,10",
+            "mfffffffffffffff8,8",
+            "m0,2001",
+            "m0",
+            "mzz,8",
+            "Mffffffffffffffff,2:0000",
+            "M0,2:00",
+            "M0,1:0g",
+        ] {
+            assert_eq!(stub.handle_packet(packet), "E01", "{}", packet);
+        }
+        assert_eq!(stub.handle_packet("m0,2000").len(), 0x4000);
+
+        let big = format!("M0,2001:{}", "00".repeat(0x2001));
+        assert_eq!(stub.handle_packet(&big), "E01");
+        assert_eq!(stub.vm.peek(0), length);
+    }
+
+    #[test]
+    fn steps_stop_with_a_signal() {
+        let mut stub = factorial(&[5]);
+
+        assert_eq!(stub.handle_packet("s"), "S05");
+        assert_eq!(stub.vm.ip(), 2);
+        assert_eq!(stub.handle_packet("p0"), register_hex(16));
+        assert_eq!(stub.handle_packet("s"), "S05");
+        assert_eq!(stub.vm.ip(), 4);
+
+        // With nothing left to read the next in stops rather than steps.
+        let mut stub = factorial(&[]);
+        stub.handle_packet("s");
+        assert_eq!(stub.handle_packet("s"), "S15");
+        assert_eq!(stub.vm.ip(), 2);
+    }
+
+    #[test]
+    fn continue_stops_at_breakpoints_and_exits() {
+        let mut stub = factorial(&[5]);
+
+        // A breakpoint on the in instruction at cell 2, then running to the end.
+        let sent = serve_packets(&mut stub, &["QStartNoAckMode", "Z0,10,1", "c"]);
+        assert_eq!(
+            sent,
+            format!(
+                "+{}{}{}",
+                packet("OK"),
+                packet("OK"),
+                packet("T05swbreak:;")
+            )
+        );
+        assert_eq!(stub.vm.ip(), 2);
+
+        let sent = serve_packets(&mut stub, &["QStartNoAckMode", "z0,10,1", "c"]);
+        assert_eq!(
+            sent,
+            format!(
+                "+{}{}{}{}",
+                packet("OK"),
+                packet("OK"),
+                packet(&format!("O{}", to_hex(b"120\n"))),
+                packet("W00")
+            )
+        );
+        assert_eq!(stub.handle_packet("s"), "W00");
+    }
+
+    #[test]
+    fn continue_stops_for_input() {
+        let mut stub = factorial(&[]);
+
+        let sent = serve_packets(&mut stub, &["QStartNoAckMode", "c"]);
+        assert_eq!(sent, format!("+{}{}", packet("OK"), packet("S15")));
+
+        stub.inputs.push_back(3);
+        let sent = serve_packets(&mut stub, &["QStartNoAckMode", "c"]);
+        assert!(sent.ends_with(&packet("W00")), "{}", sent);
+        assert!(stub.breakpoints.is_empty());
+    }
+}
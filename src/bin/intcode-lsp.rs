@@ -0,0 +1,501 @@
+use adventofcode2019::framing::{read_message, write_message};
+use adventofcode2019::intcode::assembler::{
+    assemble_program, label_sites, mnemonics, parse_test_directives, AssembleError, LabelSite,
+    Program,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufReader};
+use std::panic;
+use std::process::exit;
+
+// A language server for the assembler dialect, spoken over stdio. Lines in the protocol
+// are 0-based where the assembler's are 1-based, and columns are treated as characters
+// which is fine for the ASCII source this is meant for.
+
+const ERROR_METHOD_NOT_FOUND: i64 = -32601;
+const ERROR_INVALID_PARAMS: i64 = -32602;
+
+const COMPLETION_KIND_KEYWORD: i64 = 14;
+const COMPLETION_KIND_REFERENCE: i64 = 18;
+
+struct Server {
+    documents: HashMap<String, String>,
+    shutdown_requested: bool,
+}
+
+fn line_range(text: &str, line: usize) -> Value {
+    let length = text.lines().nth(line - 1).map_or(0, |x| x.chars().count());
+    json!({
+        "start": { "line": line - 1, "character": 0 },
+        "end": { "line": line - 1, "character": length },
+    })
+}
+
+fn site_location(uri: &str, site: &LabelSite) -> Value {
+    json!({
+        "uri": uri,
+        "range": {
+            "start": { "line": site.line - 1, "character": site.column },
+            "end": { "line": site.line - 1, "character": site.column + site.name.len() },
+        },
+    })
+}
+
+// Half typed source is the normal case here, so a bug in the assembler turns into a
+// diagnostic on the first line rather than taking the server down with it.
+fn assemble_guarded(text: &str) -> Result<Program, Vec<AssembleError>> {
+    match panic::catch_unwind(|| assemble_program(text, false)) {
+        Ok(x) => x,
+        Err(cause) => {
+            let detail = match (cause.downcast_ref::<&str>(), cause.downcast_ref::<String>()) {
+                (Some(x), _) => String::from(*x),
+                (_, Some(x)) => x.clone(),
+                _ => String::from("unknown error"),
+            };
+            Err(vec![AssembleError {
+                line: 1,
+                message: format!("assembler crashed: {}", detail),
+            }])
+        }
+    }
+}
+
+fn collect_errors(text: &str) -> Vec<AssembleError> {
+    let mut errors = Vec::<AssembleError>::new();
+
+    if let Err(x) = parse_test_directives(text) {
+        errors.extend(x);
+    }
+    if let Err(x) = assemble_guarded(text) {
+        errors.extend(x);
+    }
+
+    errors
+}
+
+impl Server {
+    fn document(&self, params: &Value) -> Result<(String, &String), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match self.documents.get(uri) {
+            Some(text) => Ok((String::from(uri), text)),
+            None => Err((ERROR_INVALID_PARAMS, format!("unknown document {}", uri))),
+        }
+    }
+
+    fn site_at(&self, params: &Value) -> Result<Option<(String, LabelSite)>, (i64, String)> {
+        let (uri, text) = self.document(params)?;
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize + 1;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+
+        let site = label_sites(text).into_iter().find(|x| {
+            x.line == line && x.column <= character && character <= x.column + x.name.len()
+        });
+
+        Ok(site.map(|x| (uri, x)))
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = &self.documents[uri];
+        let diagnostics: Vec<Value> = collect_errors(text)
+            .iter()
+            .map(|x| {
+                json!({
+                    "range": line_range(text, x.line),
+                    "severity": 1,
+                    "source": "intcode-asm",
+                    "message": x.message,
+                })
+            })
+            .collect();
+
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    fn initialize(&self) -> Value {
+        json!({
+            "capabilities": {
+                "textDocumentSync": {
+                    "openClose": true,
+                    "change": 1,
+                    "save": { "includeText": true },
+                },
+                "definitionProvider": true,
+                "referencesProvider": true,
+                "hoverProvider": true,
+                "completionProvider": { "triggerCharacters": ["&", "$"] },
+            },
+            "serverInfo": { "name": "intcode-lsp" },
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (uri, site) = match self.site_at(params)? {
+            Some(x) => x,
+            None => return Ok(Value::Null),
+        };
+        let text = &self.documents[&uri];
+
+        let locations: Vec<Value> = label_sites(text)
+            .iter()
+            .filter(|x| x.definition && x.name == site.name)
+            .map(|x| site_location(&uri, x))
+            .collect();
+
+        Ok(json!(locations))
+    }
+
+    fn references(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (uri, site) = match self.site_at(params)? {
+            Some(x) => x,
+            None => return Ok(Value::Null),
+        };
+        let text = &self.documents[&uri];
+        let include_declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+
+        let locations: Vec<Value> = label_sites(text)
+            .iter()
+            .filter(|x| x.name == site.name && (include_declaration || !x.definition))
+            .map(|x| site_location(&uri, x))
+            .collect();
+
+        Ok(json!(locations))
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (_, text) = self.document(params)?;
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize + 1;
+        let program = assemble_guarded(text).ok();
+
+        let mut contents = Vec::<String>::new();
+
+        // Without a program to give addresses, say where the label is defined instead.
+        if let Some((_, site)) = self.site_at(params)? {
+            match program.as_ref().and_then(|x| x.symbols.get(&site.name)) {
+                Some(address) => contents.push(format!("`{}` = {}", site.name, address)),
+                None => {
+                    let lines: Vec<String> = label_sites(text)
+                        .iter()
+                        .filter(|x| x.definition && x.name == site.name)
+                        .map(|x| x.line.to_string())
+                        .collect();
+                    if !lines.is_empty() {
+                        contents.push(format!(
+                            "`{}` defined on line {}",
+                            site.name,
+                            lines.join(", ")
+                        ));
+                    }
+                }
+            }
+        }
+
+        let listing = program.iter().flat_map(|x| x.listing.iter());
+        for entry in listing.filter(|x| x.line == line) {
+            let words: Vec<String> = entry.words.iter().map(|x| x.to_string()).collect();
+            contents.push(format!(
+                "`{}`  \n{}: {}",
+                entry.text,
+                entry.address,
+                words.join(",")
+            ));
+        }
+
+        if contents.is_empty() {
+            return Ok(Value::Null);
+        }
+
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": contents.join("\n\n") },
+        }))
+    }
+
+    fn completion(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (_, text) = self.document(params)?;
+
+        let mut items: Vec<Value> = mnemonics()
+            .iter()
+            .map(|(name, operands)| {
+                json!({
+                    "label": name,
+                    "kind": COMPLETION_KIND_KEYWORD,
+                    "detail": format!("{} operands", operands),
+                })
+            })
+            .collect();
+
+        let mut labels: Vec<String> = label_sites(text)
+            .into_iter()
+            .filter(|x| x.definition)
+            .map(|x| x.name)
+            .collect();
+        labels.sort();
+        labels.dedup();
+
+        items.extend(labels.iter().map(|name| {
+            json!({
+                "label": name,
+                "kind": COMPLETION_KIND_REFERENCE,
+                "detail": "label",
+            })
+        }));
+
+        Ok(json!(items))
+    }
+
+    fn handle_request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(self.initialize()),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            _ => Err((
+                ERROR_METHOD_NOT_FOUND,
+                format!("unsupported method {}", method),
+            )),
+        }
+    }
+
+    // Returns any messages which should be sent back to the client.
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = String::from(params["textDocument"]["uri"].as_str().unwrap_or(""));
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), String::from(text));
+                vec![self.diagnostics(&uri)]
+            }
+            "textDocument/didChange" => {
+                if let Some(text) = params["contentChanges"][0]["text"].as_str() {
+                    self.documents.insert(uri, String::from(text));
+                }
+                vec![]
+            }
+            "textDocument/didSave" => {
+                if let Some(text) = params["text"].as_str() {
+                    self.documents.insert(uri.clone(), String::from(text));
+                }
+                if self.documents.contains_key(&uri) {
+                    vec![self.diagnostics(&uri)]
+                } else {
+                    vec![]
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                })]
+            }
+            "exit" => exit(if self.shutdown_requested { 0 } else { 1 }),
+            _ => vec![],
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut reader = BufReader::new(io::stdin());
+    let mut stdout = io::stdout();
+
+    let mut server = Server {
+        documents: HashMap::new(),
+        shutdown_requested: false,
+    };
+
+    while let Some(message) = read_message(&mut reader)? {
+        let method = match message["method"].as_str() {
+            Some(x) => x,
+            None => continue,
+        };
+        let params = &message["params"];
+
+        if message.get("id").is_none() {
+            for reply in server.handle_notification(method, params) {
+                write_message(&mut stdout, &reply)?;
+            }
+            continue;
+        }
+
+        let reply = match server.handle_request(method, params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }),
+            Err((code, error)) => json!({
+                "jsonrpc": "2.0",
+                "id": message["id"],
+                "error": { "code": code, "message": error },
+            }),
+        };
+        write_message(&mut stdout, &reply)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.asm";
+
+    const SOURCE: &str = "\
+start:  in n
+loop:   jz n, &done
+        out n
+        add n, -1, n
+        jz 0, &loop
+done:   halt
+n:      dd 0
+";
+
+    fn server(text: &str) -> Server {
+        let mut server = Server {
+            documents: HashMap::new(),
+            shutdown_requested: false,
+        };
+        server
+            .documents
+            .insert(String::from(URI), String::from(text));
+        server
+    }
+
+    // Lines and characters as the client sends them, from 0.
+    fn at(line: usize, character: usize) -> Value {
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    fn lines(locations: &Value) -> Vec<(u64, u64)> {
+        locations
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| {
+                let start = &x["range"]["start"];
+                (
+                    start["line"].as_u64().unwrap(),
+                    start["character"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn hover_text(server: &Server, params: &Value) -> Option<String> {
+        let hover = server.hover(params).unwrap();
+        hover["contents"]["value"].as_str().map(String::from)
+    }
+
+    #[test]
+    fn definition_jumps_to_the_label() {
+        let server = server(SOURCE);
+
+        // From the &done on line 2, from n in the middle of a line, and from a definition.
+        assert_eq!(lines(&server.definition(&at(1, 16)).unwrap()), vec![(5, 0)]);
+        assert_eq!(lines(&server.definition(&at(3, 12)).unwrap()), vec![(6, 0)]);
+        assert_eq!(lines(&server.definition(&at(1, 2)).unwrap()), vec![(1, 0)]);
+
+        // Mnemonics, numbers and blank space aren't labels.
+        assert_eq!(server.definition(&at(2, 9)).unwrap(), Value::Null);
+        assert_eq!(server.definition(&at(3, 15)).unwrap(), Value::Null);
+        assert_eq!(server.definition(&at(7, 0)).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn references_find_every_use() {
+        let server = server(SOURCE);
+
+        let mut params = at(0, 11);
+        assert_eq!(
+            lines(&server.references(&params).unwrap()),
+            vec![(0, 11), (1, 11), (2, 12), (3, 12), (3, 19), (6, 0)]
+        );
+
+        params["context"] = json!({ "includeDeclaration": false });
+        assert_eq!(lines(&server.references(&params).unwrap()).len(), 5);
+
+        assert_eq!(
+            lines(&server.references(&at(4, 16)).unwrap()),
+            vec![(1, 0), (4, 15)]
+        );
+    }
+
+    #[test]
+    fn unknown_documents_are_invalid_params() {
+        let server = server(SOURCE);
+        let mut params = at(0, 0);
+        params["textDocument"]["uri"] = json!("file:///other.asm");
+
+        assert_eq!(
+            server.definition(&params).unwrap_err().0,
+            ERROR_INVALID_PARAMS
+        );
+        assert_eq!(server.hover(&params).unwrap_err().0, ERROR_INVALID_PARAMS);
+    }
+
+    #[test]
+    fn hover_shows_addresses_and_words() {
+        let server = server(SOURCE);
+
+        let text = hover_text(&server, &at(1, 0)).unwrap();
+        assert!(text.starts_with("`loop` = 2\n\n"), "{}", text);
+        assert!(text.contains("2: 1006,"), "{}", text);
+        assert_eq!(hover_text(&server, &at(7, 0)), None);
+    }
+
+    #[test]
+    fn hover_falls_back_to_label_sites_when_assembly_fails() {
+        let broken = format!("{}        bogus n\n", SOURCE);
+        let server = server(&broken);
+
+        assert!(assemble_guarded(&broken).is_err());
+        assert_eq!(
+            hover_text(&server, &at(1, 16)),
+            Some(String::from("`done` defined on line 6"))
+        );
+        assert_eq!(
+            hover_text(&server, &at(7, 15)),
+            Some(String::from("`n` defined on line 7"))
+        );
+        assert_eq!(hover_text(&server, &at(2, 9)), None);
+    }
+
+    #[test]
+    fn assembly_errors_become_diagnostics() {
+        for (text, line, message) in &[
+            ("halt\n  bogus 1\n", 2, "unknown instruction 'bogus'"),
+            ("jz 0, &nowhere\n", 1, "undefined label 'nowhere'"),
+            ("x: x: halt\n", 1, "label 'x' is defined twice"),
+            ("add 1, 2\n", 1, "add takes 3 operands"),
+            ("ascii \"abc\n", 1, "unterminated string"),
+            ("halt\n;! test steps=lots\n", 2, "bad step count 'lots'"),
+        ] {
+            let errors = collect_errors(text);
+            assert_eq!(errors[0].line, *line, "{}", text);
+            assert_eq!(errors[0].message, *message);
+
+            let mut server = server("");
+            let open = json!({ "textDocument": { "uri": URI, "text": text } });
+            let published = server.handle_notification("textDocument/didOpen", &open);
+            let diagnostic = &published[0]["params"]["diagnostics"][0];
+            assert_eq!(diagnostic["message"], *message);
+            assert_eq!(diagnostic["range"]["start"]["line"], *line - 1);
+        }
+
+        assert!(assemble_guarded(SOURCE).is_ok());
+        assert!(collect_errors(SOURCE).is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::expanse::Expanse;
+use crate::geometry::{Direction, Point2, Turn};
 use crate::intcode::vm::IntCodeMachine;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -24,65 +25,36 @@ fn int_to_color(i: i64) -> PaintColor {
     }
 }
 
-#[derive(Eq, PartialEq, Clone, Copy, Debug)]
-enum TurnCommand {
-    TurnLeft,
-    TurnRight,
-}
-
-fn int_to_turn_cmd(i: i64) -> TurnCommand {
-    match i {
-        0 => TurnCommand::TurnLeft,
-        1 => TurnCommand::TurnRight,
-        _ => panic!(),
-    }
-}
-
 #[derive(Debug)]
 struct PaintBot {
     grid: Expanse<PaintColor>,
-    position: (i32, i32),
-    direction: (i32, i32),
+    position: Point2,
+    direction: Direction,
 }
 
 impl PaintBot {
     pub fn new() -> PaintBot {
         PaintBot {
             grid: Expanse::new(),
-            position: (0, 0),
-            direction: (0, 1),
+            position: Point2::ZERO,
+            direction: Direction::North,
         }
     }
 
-    pub fn step(&mut self, paint: PaintColor, turn: TurnCommand) -> PaintColor {
-        self.grid.write(self.position.0, self.position.1, paint);
-
-        match turn {
-            TurnCommand::TurnLeft => self.direction = (-self.direction.1, self.direction.0),
-            TurnCommand::TurnRight => self.direction = (self.direction.1, -self.direction.0),
-        };
+    pub fn step(&mut self, paint: PaintColor, turn: Turn) -> PaintColor {
+        self.grid.write(self.position.x, self.position.y, paint);
 
-        self.position.0 += self.direction.0;
-        self.position.1 += self.direction.1;
+        self.direction = self.direction.turn(turn);
+        self.position = self.direction.step(self.position, true);
 
-        match self.grid.read(self.position.0, self.position.1) {
+        match self.grid.read(self.position.x, self.position.y) {
             Some(x) => *x,
             None => PaintColor::Unpainted,
         }
     }
 
     pub fn count_painted_tiles(&self) -> u32 {
-        let mut result = 0u32;
-
-        for x in self.grid.x_range() {
-            for y in self.grid.y_range() {
-                if self.grid.read(x, y).is_some() {
-                    result += 1;
-                }
-            }
-        }
-
-        result
+        self.grid.count() as u32
     }
 
     pub fn render_image_to_string(&self) -> String {
@@ -107,7 +79,10 @@ fn run_paint_bot(brain_tape: &[i64], start_color: PaintColor) -> PaintBot {
     while let Ok(_) = (|| {
         let color_command = brain.run_and_get_output()?;
         let turn_command = brain.run_and_get_output()?;
-        let new_color = bot.step(int_to_color(color_command), int_to_turn_cmd(turn_command));
+        let new_color = bot.step(
+            int_to_color(color_command),
+            Turn::from_paint_command(turn_command).unwrap(),
+        );
         brain.run_and_provide_input(color_to_int(new_color))
     })() {}
 
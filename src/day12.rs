@@ -1,69 +1,16 @@
+use crate::geometry::Point3;
 use num::integer::lcm;
 use regex::Regex;
 use std::collections::HashSet;
-use std::ops::{Add, AddAssign, SubAssign};
 
 fn lcm3(a: usize, b: usize, c: usize) -> usize {
     lcm(lcm(a, b), lcm(b, c))
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-struct Vec3 {
-    pub x: i32,
-    pub y: i32,
-    pub z: i32,
-}
-
-impl Vec3 {
-    pub fn new() -> Self {
-        Self {
-            x: 0i32,
-            y: 0i32,
-            z: 0i32,
-        }
-    }
-
-    pub fn component_value_sum(&self) -> i32 {
-        self.x.abs() + self.y.abs() + self.z.abs()
-    }
-}
-
-impl Add<Vec3> for Vec3 {
-    type Output = Self;
-
-    fn add(self, rhs: Self) -> Self {
-        Self {
-            x: self.x + rhs.x,
-            y: self.y + rhs.y,
-            z: self.z + rhs.z,
-        }
-    }
-}
-
-impl AddAssign for Vec3 {
-    fn add_assign(&mut self, other: Self) {
-        *self = Self {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z,
-        };
-    }
-}
-
-impl SubAssign for Vec3 {
-    fn sub_assign(&mut self, other: Self) {
-        *self = Self {
-            x: self.x - other.x,
-            y: self.y - other.y,
-            z: self.z - other.z,
-        };
-    }
-}
-
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 struct Moon {
-    pos: Vec3,
-    vel: Vec3,
+    pos: Point3,
+    vel: Point3,
 }
 
 impl Moon {
@@ -73,31 +20,13 @@ impl Moon {
         let vals: Vec<i32> = csv.split(",").map(|x| x.parse::<i32>().unwrap()).collect();
 
         Self {
-            pos: Vec3 {
-                x: vals[0],
-                y: vals[1],
-                z: vals[2],
-            },
-            vel: Vec3::new(),
-        }
-    }
-
-    fn delta_v(a: i32, b: i32) -> i32 {
-        if b > a {
-            1
-        } else if b == a {
-            0
-        } else {
-            -1
+            pos: Point3::new(vals[0], vals[1], vals[2]),
+            vel: Point3::ZERO,
         }
     }
 
     pub fn apply_gravity(first: &mut Self, second: &mut Self) {
-        let dv = Vec3 {
-            x: Self::delta_v(first.pos.x, second.pos.x),
-            y: Self::delta_v(first.pos.y, second.pos.y),
-            z: Self::delta_v(first.pos.z, second.pos.z),
-        };
+        let dv = (second.pos - first.pos).signum();
 
         first.vel += dv;
         second.vel -= dv;
@@ -108,7 +37,7 @@ impl Moon {
     }
 
     pub fn total_energy(&self) -> i32 {
-        self.pos.component_value_sum() * self.vel.component_value_sum()
+        self.pos.manhattan(Point3::ZERO) * self.vel.manhattan(Point3::ZERO)
     }
 }
 
@@ -1,5 +1,7 @@
 use crate::expanse::Expanse;
+use crate::geometry::Direction;
 use crate::intcode::vm::IntCodeMachine;
+use crate::pathfind::{astar, expanse_neighbors, manhattan};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 enum MapCell {
@@ -7,54 +9,10 @@ enum MapCell {
     DustyCrumb,
     OxygenSystem,
     DroidStart,
-    Counted,
 }
 
 type ShipMap = Expanse<MapCell>;
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-enum Direction {
-    North = 1,
-    South,
-    West,
-    East,
-}
-
-impl Direction {
-    pub fn to_command(&self) -> i64 {
-        *self as i64
-    }
-
-    pub fn move_point(&self, pos: (i32, i32)) -> (i32, i32) {
-        let mut result = pos;
-        match self {
-            Direction::North => result.1 += 1,
-            Direction::South => result.1 -= 1,
-            Direction::West => result.0 += 1,
-            Direction::East => result.0 -= 1,
-        };
-        result
-    }
-
-    pub fn relative_right(&self) -> Direction {
-        match self {
-            Direction::North => Direction::East,
-            Direction::East => Direction::South,
-            Direction::South => Direction::West,
-            Direction::West => Direction::North,
-        }
-    }
-
-    pub fn relative_left(&self) -> Direction {
-        match self {
-            Direction::North => Direction::West,
-            Direction::West => Direction::South,
-            Direction::South => Direction::East,
-            Direction::East => Direction::North,
-        }
-    }
-}
-
 const STATUS_DROID_MOVED: i64 = 1;
 const STATUS_DROID_FOUND_OXYGEN: i64 = 2;
 
@@ -74,7 +32,9 @@ impl Droid {
     }
 
     fn brain_step(&mut self, dir: Direction) -> i64 {
-        self.brain.run_and_provide_input(dir.to_command()).unwrap();
+        self.brain
+            .run_and_provide_input(dir.to_movement_command())
+            .unwrap();
         self.brain.run_and_get_output().unwrap()
     }
 
@@ -89,14 +49,14 @@ impl Droid {
     }
 
     pub fn step_and_mark_map(&mut self, map: &mut ShipMap) -> bool {
-        let right_dir = self.direction.relative_right();
+        let right_dir = self.direction.turn_right();
 
         let step_result = self.brain_step(right_dir);
 
         if step_result == STATUS_DROID_MOVED || step_result == STATUS_DROID_FOUND_OXYGEN {
             self.direction = right_dir;
             self.leave_crumb_at_pos(map);
-            self.pos = self.direction.move_point(self.pos);
+            self.pos = self.direction.step(self.pos.into(), true).into();
 
             if step_result == STATUS_DROID_FOUND_OXYGEN {
                 map.write(self.pos.0, self.pos.1, MapCell::OxygenSystem);
@@ -110,7 +70,7 @@ impl Droid {
 
         if step_result == STATUS_DROID_MOVED || step_result == STATUS_DROID_FOUND_OXYGEN {
             self.leave_crumb_at_pos(map);
-            self.pos = self.direction.move_point(self.pos);
+            self.pos = self.direction.step(self.pos.into(), true).into();
 
             if step_result == STATUS_DROID_FOUND_OXYGEN {
                 map.write(self.pos.0, self.pos.1, MapCell::OxygenSystem);
@@ -120,45 +80,12 @@ impl Droid {
             }
         }
 
-        self.direction = self.direction.relative_left();
+        self.direction = self.direction.turn_left();
 
         false
     }
 }
 
-fn walk_crumby_map_and_count_steps(map: &mut ShipMap) -> u32 {
-    let mut pos = (0i32, 0i32);
-    let mut dir = Direction::West;
-    let mut steps = 0u32;
-
-    loop {
-        let next_pos = dir.move_point(pos);
-
-        if let None = map.read(next_pos.0, next_pos.1) {
-            let next_pos = dir.relative_left().move_point(pos);
-            if let Some(MapCell::TastyCrumb) = map.read(next_pos.0, next_pos.1) {
-                dir = dir.relative_left();
-                pos = next_pos;
-            } else {
-                dir = dir.relative_right();
-                pos = dir.move_point(pos);
-            }
-        } else {
-            pos = next_pos;
-        }
-
-        steps += 1;
-
-        if let Some(MapCell::OxygenSystem) = map.read(pos.0, pos.1) {
-            break;
-        } else {
-            map.write(pos.0, pos.1, MapCell::Counted);
-        }
-    }
-
-    steps
-}
-
 fn find_oxygen_tank_and_steps(droid_tape: &[i64]) -> ((i32, i32), u32) {
     let mut droid = Droid::new(&droid_tape);
     let mut map = Expanse::<MapCell>::new();
@@ -171,39 +98,16 @@ fn find_oxygen_tank_and_steps(droid_tape: &[i64]) -> ((i32, i32), u32) {
 
     let tank_location = map.find(|&cell| cell == MapCell::OxygenSystem).unwrap();
 
-    let step_count = walk_crumby_map_and_count_steps(&mut map);
+    let open_neighbors = expanse_neighbors(&map, |_| true);
+    let (_, step_count) = astar(
+        (0, 0),
+        &tank_location,
+        |&pos| open_neighbors(&pos).into_iter().map(|x| (x, 1)),
+        |&pos| manhattan(pos, tank_location),
+    )
+    .unwrap();
 
-    (tank_location, step_count)
-}
-
-fn oxygen_expand(map: &mut ShipMap) -> bool {
-    let mut did_expand = false;
-
-    for x in map.x_range() {
-        for y in map.y_range() {
-            if let Some(MapCell::TastyCrumb) = map.read(x, y) {
-                let a = map.read(x + 1, y) == Some(&MapCell::Counted);
-                let b = map.read(x - 1, y) == Some(&MapCell::Counted);
-                let c = map.read(x, y + 1) == Some(&MapCell::Counted);
-                let d = map.read(x, y - 1) == Some(&MapCell::Counted);
-
-                if a || b || c || d {
-                    map.write(x, y, MapCell::DustyCrumb);
-                    did_expand = true;
-                }
-            }
-        }
-    }
-
-    for x in map.x_range() {
-        for y in map.y_range() {
-            if map.read(x, y) == Some(&MapCell::DustyCrumb) {
-                map.write(x, y, MapCell::Counted);
-            }
-        }
-    }
-
-    did_expand
+    (tank_location, step_count as u32)
 }
 
 fn fill_ship_with_oxygen(pos: (i32, i32), droid_tape: &[i64]) -> u32 {
@@ -214,22 +118,9 @@ fn fill_ship_with_oxygen(pos: (i32, i32), droid_tape: &[i64]) -> u32 {
         droid.step_and_mark_map(&mut map);
     }
 
-    for x in map.x_range() {
-        for y in map.y_range() {
-            if map.read(x, y) == Some(&MapCell::DustyCrumb) {
-                map.write(x, y, MapCell::TastyCrumb);
-            }
-        }
-    }
-
-    map.write(pos.0, pos.1, MapCell::Counted);
+    let (_, minutes) = *map.flood_fill(pos, |_| true).last().unwrap();
 
-    let mut minutes = 0;
-    while oxygen_expand(&mut map) {
-        minutes += 1;
-    }
-
-    minutes
+    minutes as u32
 }
 
 pub fn main() {
@@ -244,3 +135,50 @@ pub fn main() {
 
     println!("{} {}", result0, result1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL_CHARS: [(MapCell, char); 4] = [
+        (MapCell::TastyCrumb, '.'),
+        (MapCell::DustyCrumb, ','),
+        (MapCell::OxygenSystem, 'O'),
+        (MapCell::DroidStart, 'D'),
+    ];
+
+    fn cell_to_char(cell: &MapCell) -> char {
+        CELL_CHARS.iter().find(|x| x.0 == *cell).unwrap().1
+    }
+
+    fn char_to_cell(ch: char) -> Option<MapCell> {
+        CELL_CHARS.iter().find(|x| x.1 == ch).map(|x| x.0)
+    }
+
+    // The explored map sits around the droid's start, so it comes back from ASCII moved to
+    // start at the origin and has to be moved back to compare cells.
+    #[test]
+    fn explored_map_round_trips_through_ascii() {
+        let tape: Vec<i64> = std::fs::read_to_string("data/day15.txt")
+            .unwrap()
+            .split(',')
+            .map(|x| x.trim().parse().unwrap())
+            .collect();
+
+        let mut droid = Droid::new(&tape);
+        let mut map = ShipMap::new();
+        for _ in 0..10000 {
+            droid.step_and_mark_map(&mut map);
+        }
+        map.write(0, 0, MapCell::DroidStart);
+
+        let text = map.to_ascii(true, '#', cell_to_char);
+        let parsed = ShipMap::from_ascii(&text, true, char_to_cell);
+        assert_eq!(parsed.to_ascii(true, '#', cell_to_char), text);
+
+        let moved = parsed.translate(map.x_range().start, map.y_range().start);
+        let moved_cells: Vec<_> = moved.cells().map(|(pos, &x)| (pos, x)).collect();
+        let map_cells: Vec<_> = map.cells().map(|(pos, &x)| (pos, x)).collect();
+        assert_eq!(moved_cells, map_cells);
+    }
+}
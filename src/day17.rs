@@ -1,88 +1,31 @@
 use crate::expanse::Expanse;
+use crate::geometry::{Direction, Point2};
 use crate::intcode::vm::IntCodeMachine;
 use std::cmp::min;
 use std::string::ToString;
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-enum Direction {
-    North,
-    South,
-    West,
-    East,
-}
-
-impl Direction {
-    pub fn move_point(&self, pos: (i32, i32)) -> (i32, i32) {
-        let mut result = pos;
-        match self {
-            Direction::North => result.1 -= 1,
-            Direction::South => result.1 += 1,
-            Direction::West => result.0 -= 1,
-            Direction::East => result.0 += 1,
-        };
-        result
-    }
-
-    pub fn relative_right(&self) -> Direction {
-        match self {
-            Direction::North => Direction::East,
-            Direction::East => Direction::South,
-            Direction::South => Direction::West,
-            Direction::West => Direction::North,
-        }
-    }
-
-    pub fn relative_left(&self) -> Direction {
-        match self {
-            Direction::North => Direction::West,
-            Direction::West => Direction::South,
-            Direction::South => Direction::East,
-            Direction::East => Direction::North,
-        }
-    }
-}
-
 type Scaffold = Expanse<()>;
 
 fn load_scaffold_and_robot_pos_from_camera_view(camera_view: &str) -> (Scaffold, (i32, i32)) {
-    let mut scaffold = Scaffold::new();
-    let mut ix = 0i32;
-    let mut iy = 0i32;
-
-    let mut robot_pos = (0i32, 0i32);
+    let view = Expanse::from_ascii(camera_view, false, Some);
+    let robot_pos = view.find(|&x| x == '^').unwrap();
 
-    for c in camera_view.chars() {
-        match c {
-            '\n' => {
-                iy += 1;
-                ix = 0;
-                continue;
-            }
-            '#' => scaffold.write(ix, iy, ()),
-            '^' => robot_pos = (ix, iy),
-            _ => (),
-        };
-        ix += 1;
-    }
+    let mut scaffold = Scaffold::new();
+    scaffold.extend(
+        view.iter()
+            .filter(|&(_, &x)| x == '#')
+            .map(|(pos, _)| (pos, ())),
+    );
 
     (scaffold, robot_pos)
 }
 
 fn sum_scaffold_alignment_parameters(scaffold: &Scaffold) -> u32 {
-    let mut sum = 0i32;
-
-    for x in scaffold.x_range() {
-        for y in scaffold.y_range() {
-            if scaffold.read(x, y).is_some()
-                && scaffold.read(x + 1, y).is_some()
-                && scaffold.read(x - 1, y).is_some()
-                && scaffold.read(x, y + 1).is_some()
-                && scaffold.read(x, y - 1).is_some()
-            {
-                sum += x * y;
-            }
-        }
-    }
+    let sum: i32 = scaffold
+        .iter()
+        .filter(|&((x, y), _)| scaffold.neighbors4(x, y).count() == 4)
+        .map(|((x, y), _)| x * y)
+        .sum();
 
     sum as u32
 }
@@ -141,21 +84,21 @@ impl PathToken {
 
 fn get_raw_robot_path(scaffold: &Scaffold, robot_pos: (i32, i32)) -> Vec<PathToken> {
     let mut path = Vec::<PathToken>::new();
-    let mut pos = robot_pos;
+    let mut pos = Point2::from(robot_pos);
     let mut dir = Direction::West;
     let mut walk_count = 0u32;
 
     path.push(PathToken::Turn(PathTurn::Left));
 
     loop {
-        let next_pos = dir.move_point(pos);
-        if scaffold.read(next_pos.0, next_pos.1).is_some() {
+        let next_pos = dir.step(pos, false);
+        if scaffold.read(next_pos.x, next_pos.y).is_some() {
             pos = next_pos;
             walk_count += 1;
         } else {
             let mut try_dir = |turned: Direction, path_turn: PathTurn| {
-                let look = turned.move_point(pos);
-                if scaffold.read(look.0, look.1).is_some() {
+                let look = turned.step(pos, false);
+                if scaffold.read(look.x, look.y).is_some() {
                     path.push(PathToken::Walk(walk_count));
                     path.push(PathToken::Turn(path_turn));
                     walk_count = 0;
@@ -165,12 +108,12 @@ fn get_raw_robot_path(scaffold: &Scaffold, robot_pos: (i32, i32)) -> Vec<PathTok
                 }
             };
 
-            if let Some(new_dir) = try_dir(dir.relative_left(), PathTurn::Left) {
+            if let Some(new_dir) = try_dir(dir.turn_left(), PathTurn::Left) {
                 dir = new_dir;
                 continue;
             }
 
-            if let Some(new_dir) = try_dir(dir.relative_right(), PathTurn::Right) {
+            if let Some(new_dir) = try_dir(dir.turn_right(), PathTurn::Right) {
                 dir = new_dir;
                 continue;
             }
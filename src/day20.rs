@@ -1,4 +1,6 @@
 use crate::expanse::Expanse;
+use crate::pathfind::{bfs_path, expanse_neighbors};
+use std::collections::HashMap;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum PortalKind {
@@ -6,15 +8,6 @@ enum PortalKind {
     Pop,
 }
 
-impl PortalKind {
-    pub fn other(&self) -> PortalKind {
-        match self {
-            PortalKind::Push => PortalKind::Pop,
-            PortalKind::Pop => PortalKind::Push,
-        }
-    }
-}
-
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum TileKind {
     Path,
@@ -23,7 +16,6 @@ enum TileKind {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct Tile {
-    pub dist: u32,
     pub kind: TileKind,
 }
 
@@ -31,207 +23,116 @@ fn is_capital_letter(ch: char) -> bool {
     ch >= 'A' && ch <= 'Z'
 }
 
-fn load_maze(chars: &Vec<Vec<char>>) -> Expanse<Tile> {
+fn load_maze(chars: &Expanse<char>) -> Expanse<Tile> {
     let mut result = Expanse::new();
 
-    let kind_at = |x: usize, y: usize| -> PortalKind {
-        if x == 2 || y == 2 || x == chars[0].len() - 3 || y == chars.len() - 3 {
+    let char_at = |x: i32, y: i32| *chars.read(x, y).unwrap_or(&' ');
+
+    let kind_at = |x: i32, y: i32| -> PortalKind {
+        if x == 2 || y == 2 || x == chars.x_range().end - 3 || y == chars.y_range().end - 3 {
             PortalKind::Pop
         } else {
             PortalKind::Push
         }
     };
 
-    for y in 0..chars.len() {
-        for x in 0..chars[y].len() {
-            if chars[y][x] != '.' {
-                continue;
-            }
-
-            let kind = if is_capital_letter(chars[y][x - 1]) {
-                TileKind::Portal(
-                    kind_at(x, y),
-                    format!("{}{}", chars[y][x - 2], chars[y][x - 1]),
-                )
-            } else if is_capital_letter(chars[y][x + 1]) {
-                TileKind::Portal(
-                    kind_at(x, y),
-                    format!("{}{}", chars[y][x + 1], chars[y][x + 2]),
-                )
-            } else if is_capital_letter(chars[y - 1][x]) {
-                TileKind::Portal(
-                    kind_at(x, y),
-                    format!("{}{}", chars[y - 2][x], chars[y - 1][x]),
-                )
-            } else if is_capital_letter(chars[y + 1][x]) {
-                TileKind::Portal(
-                    kind_at(x, y),
-                    format!("{}{}", chars[y + 1][x], chars[y + 2][x]),
-                )
-            } else {
-                TileKind::Path
-            };
-
-            result.write(
-                x as i32,
-                y as i32,
-                Tile {
-                    dist: 0,
-                    kind: kind,
-                },
-            );
+    for ((x, y), &ch) in chars.iter() {
+        if ch != '.' {
+            continue;
         }
+
+        let kind = if is_capital_letter(char_at(x - 1, y)) {
+            TileKind::Portal(
+                kind_at(x, y),
+                format!("{}{}", char_at(x - 2, y), char_at(x - 1, y)),
+            )
+        } else if is_capital_letter(char_at(x + 1, y)) {
+            TileKind::Portal(
+                kind_at(x, y),
+                format!("{}{}", char_at(x + 1, y), char_at(x + 2, y)),
+            )
+        } else if is_capital_letter(char_at(x, y - 1)) {
+            TileKind::Portal(
+                kind_at(x, y),
+                format!("{}{}", char_at(x, y - 2), char_at(x, y - 1)),
+            )
+        } else if is_capital_letter(char_at(x, y + 1)) {
+            TileKind::Portal(
+                kind_at(x, y),
+                format!("{}{}", char_at(x, y + 1), char_at(x, y + 2)),
+            )
+        } else {
+            TileKind::Path
+        };
+
+        result.write(x, y, Tile { kind });
     }
 
     result
 }
 
-fn find_portals(
-    maze: &Expanse<Tile>,
-    id: &str,
-    portal_kind: Option<PortalKind>,
-) -> Vec<(i32, i32)> {
-    maze.find_many(|Tile { kind, .. }| {
-        if let TileKind::Portal(pk, x) = kind {
-            x == id && (portal_kind.is_none() || portal_kind.unwrap() == *pk)
+fn find_portals(maze: &Expanse<Tile>, id: &str) -> Vec<(i32, i32)> {
+    maze.find_many(|Tile { kind }| {
+        if let TileKind::Portal(_, x) = kind {
+            x == id
         } else {
             false
         }
     })
 }
 
-fn can_walk(maze: &Expanse<Tile>, x: i32, y: i32) -> bool {
-    match maze.read(x, y) {
-        Some(Tile { dist, .. }) => *dist == 0,
-        _ => false,
-    }
-}
+// Maps each portal tile to the tile at the other end of it.
+fn link_portals(maze: &Expanse<Tile>) -> HashMap<(i32, i32), (i32, i32)> {
+    let mut result = HashMap::new();
 
-fn can_flat_portal(
-    _maze_proto: &Expanse<Tile>,
-    levels: &mut Vec<Expanse<Tile>>,
-    x: i32,
-    y: i32,
-    _z: usize,
-) -> Option<(i32, i32, usize)> {
-    if let Some(Tile {
-        kind: TileKind::Portal(_, id),
-        ..
-    }) = levels[0].read(x, y)
-    {
-        for &(px, py) in find_portals(&levels[0], id, None).iter() {
-            if px != x && py != y && levels[0].read(px, py).unwrap().dist == 0 {
-                return Some((px, py, 0));
+    for (pos, tile) in maze.iter() {
+        if let TileKind::Portal(_, id) = &tile.kind {
+            if let Some(&other) = find_portals(maze, id).iter().find(|&&x| x != pos) {
+                result.insert(pos, other);
             }
         }
-        None
-    } else {
-        None
-    }
-}
-
-fn can_recursive_portal(
-    maze_proto: &Expanse<Tile>,
-    levels: &mut Vec<Expanse<Tile>>,
-    x: i32,
-    y: i32,
-    z: usize,
-) -> Option<(i32, i32, usize)> {
-    let portal_kind: PortalKind;
-    let portal_id: String;
-
-    if let Some(Tile {
-        kind: TileKind::Portal(pk, id),
-        ..
-    }) = levels[z].read(x, y)
-    {
-        portal_kind = *pk;
-        portal_id = id.clone();
-    } else {
-        return None;
-    }
-
-    if z == 0 && portal_kind == PortalKind::Pop {
-        return None;
-    }
-
-    let z1 = match portal_kind {
-        PortalKind::Pop => z - 1,
-        PortalKind::Push => z + 1,
-    };
-
-    if z1 >= levels.len() {
-        levels.push(maze_proto.clone());
-    }
-
-    for &(px, py) in find_portals(&levels[z1], &portal_id, Some(portal_kind.other())).iter() {
-        if px != x && py != y && levels[z1].read(px, py).unwrap().dist == 0 {
-            return Some((px, py, z1));
-        }
     }
 
-    None
+    result
 }
 
 fn solve_maze(maze: &Expanse<Tile>, recursive: bool) -> u32 {
-    let mut levels = vec![maze.clone()];
-
-    let (x, y) = find_portals(&levels[0], "AA", None)[0];
-    let (zx, zy) = find_portals(&levels[0], "ZZ", None)[0];
-
-    let mut frontier = vec![(x, y, 0)];
-    let mut cur_dist = 1;
-
-    let portal_fn = if recursive {
-        can_recursive_portal
-    } else {
-        can_flat_portal
+    let start = find_portals(maze, "AA")[0];
+    let end = find_portals(maze, "ZZ")[0];
+    let links = link_portals(maze);
+    let walk = expanse_neighbors(maze, |_| true);
+
+    let warp = |x: i32, y: i32, z: usize| -> Option<(i32, i32, usize)> {
+        let (px, py) = *links.get(&(x, y))?;
+        let z1 = match maze.read(x, y) {
+            _ if !recursive => z,
+            Some(Tile {
+                kind: TileKind::Portal(PortalKind::Push, _),
+            }) => z + 1,
+            _ if z == 0 => return None,
+            _ => z - 1,
+        };
+        Some((px, py, z1))
     };
 
-    while frontier.len() > 0 {
-        for i in 0..frontier.len() {
-            let (x, y, z) = frontier[i];
-            levels[z].at(x, y).unwrap().dist = cur_dist;
-
-            if x == zx && y == zy && z == 0 {
-                return cur_dist - 1;
-            }
-        }
-
-        let mut new_frontier = Vec::<(i32, i32, usize)>::new();
-
-        for i in 0..frontier.len() {
-            let (x, y, z) = frontier[i];
-            if can_walk(&levels[z], x + 1, y) {
-                new_frontier.push((x + 1, y, z));
-            }
-            if can_walk(&levels[z], x - 1, y) {
-                new_frontier.push((x - 1, y, z));
-            }
-            if can_walk(&levels[z], x, y + 1) {
-                new_frontier.push((x, y + 1, z));
-            }
-            if can_walk(&levels[z], x, y - 1) {
-                new_frontier.push((x, y - 1, z));
-            }
-            if let Some(new_pos) = portal_fn(maze, &mut levels, x, y, z) {
-                new_frontier.push(new_pos);
-            }
-        }
-
-        cur_dist += 1;
-        frontier = new_frontier;
-    }
-
-    panic!()
+    let (_, steps) = bfs_path(
+        (start.0, start.1, 0usize),
+        |&(x, y, z)| (x, y) == end && z == 0,
+        |&(x, y, z)| {
+            let mut result: Vec<(i32, i32, usize)> =
+                walk(&(x, y)).into_iter().map(|(x, y)| (x, y, z)).collect();
+            result.extend(warp(x, y, z));
+            result
+        },
+    )
+    .unwrap();
+
+    steps as u32
 }
 
 pub fn main() {
-    let map_chars: Vec<Vec<char>> = std::fs::read_to_string("data/day20.txt")
-        .unwrap()
-        .lines()
-        .map(|x| x.chars().collect())
-        .collect();
+    let text = std::fs::read_to_string("data/day20.txt").unwrap();
+    let map_chars = Expanse::from_ascii(&text, false, |x| if x == ' ' { None } else { Some(x) });
 
     let maze = load_maze(&map_chars);
 
@@ -1,34 +1,107 @@
 use crate::intcode::vm::IntCodeMachine;
 
-fn build_program(lines: &[&str]) -> Vec<i64> {
-    if lines.len() > 15 {
-        panic!("Springscript program too long!");
+mod sat;
+mod springscript;
+mod synth;
+
+use springscript::{parse_hull, Mode, Script};
+use synth::Synthesizer;
+
+fn build_program(lines: &[&str]) -> Script {
+    match Script::parse(lines) {
+        Ok(x) => x,
+        Err(message) => panic!("Bad springscript program, {}", message),
     }
+}
 
-    let mut result_str = String::new();
+// Gives back the hull damage reading on success, or the frames drawn as the droid fell.
+fn run_script(tape: &[i64], script: &Script) -> Result<i64, String> {
+    let result = IntCodeMachine::run_all(tape, &script.to_input());
 
-    for line in lines {
-        result_str.push_str(line);
-        result_str.push_str("\n");
+    if *result.last().unwrap() > 0xFF {
+        return Ok(*result.last().unwrap());
     }
 
-    result_str.chars().map(|x| x as i64).collect()
+    Err(result.iter().map(|&x| x as u8 as char).collect())
 }
 
 fn run_program_or_print_failure(tape: &[i64], program_lines: &[&str]) -> i64 {
-    let program = build_program(program_lines);
-    let result = IntCodeMachine::run_all(tape, &program);
+    let script = build_program(program_lines);
 
-    if *result.last().unwrap() > 0xFF {
-        return *result.last().unwrap();
+    match run_script(tape, &script) {
+        Ok(x) => x,
+        Err(debug_frame) => {
+            println!("{}", debug_frame);
+
+            match parse_hull(&debug_frame) {
+                Some(hull) if script.simulate(&hull).is_ok() => {
+                    println!("The simulator gets across a hull the droid fell into")
+                }
+                Some(_) => (),
+                None => println!("No hull in the droid's last frame"),
+            }
+
+            0
+        }
     }
+}
+
+// Counterexample guided search, every hull the droid falls into on the real tape is added
+// to the set the next script has to survive.
+fn synthesize_script(tape: &[i64], mode: Mode) -> Result<(Script, i64), String> {
+    let mut synthesizer = Synthesizer::new(mode);
 
-    let debug_frame: String = result.iter().map(|&x| x as u8 as char).collect();
-    println!("{}", debug_frame);
+    loop {
+        let script = synthesizer.synthesize().ok_or_else(|| {
+            format!(
+                "No springscript survives all {} hulls",
+                synthesizer.hull_count()
+            )
+        })?;
 
-    0
+        match run_script(tape, &script) {
+            Ok(x) => return Ok((script, x)),
+            Err(debug_frame) => match parse_hull(&debug_frame) {
+                Some(hull) => synthesizer.add_hull(&hull),
+                None => {
+                    return Err(format!(
+                        "No hull in the droid's last frame\n{}",
+                        debug_frame
+                    ))
+                }
+            },
+        }
+    }
 }
 
+#[rustfmt::skip]
+const WALK_SCRIPT: [&str; 7] = [
+    "NOT A J",
+    "NOT B T",
+    "OR T J",
+    "NOT C T",
+    "OR T J",
+    "AND D J",
+    "WALK",
+];
+
+#[rustfmt::skip]
+const RUN_SCRIPT: [&str; 11] = [
+    "NOT A J",
+    "NOT B T",
+    "OR T J",
+    "NOT C T",
+    "OR T J",
+    "AND D J",
+    "NOT E T",
+    "NOT T T",
+    "OR H T",
+    "AND T J",
+    "RUN",
+];
+
+// Pass --synthesize to also search for the shortest scripts against the real tape, which
+// takes a few seconds rather than a few milliseconds.
 pub fn main() {
     let tape: Vec<i64> = std::fs::read_to_string("data/day21.txt")
         .unwrap()
@@ -36,31 +109,74 @@ pub fn main() {
         .map(|x| x.trim().parse().unwrap())
         .collect();
 
-    #[rustfmt::skip]
-    let result0 = run_program_or_print_failure(&tape, &[
-        "NOT A J",
-        "NOT B T",
-        "OR T J",
-        "NOT C T",
-        "OR T J",
-        "AND D J",
-        "WALK",
-    ]);
-
-    #[rustfmt::skip]
-    let result1 = run_program_or_print_failure(&tape, &[
-        "NOT A J",
-        "NOT B T",
-        "OR T J",
-        "NOT C T",
-        "OR T J",
-        "AND D J",
-        "NOT E T",
-        "NOT T T",
-        "OR H T",
-        "AND T J",
-        "RUN",
-    ]);
+    let result0 = run_program_or_print_failure(&tape, &WALK_SCRIPT);
+    let result1 = run_program_or_print_failure(&tape, &RUN_SCRIPT);
 
     println!("{} {}", result0, result1);
+
+    if std::env::args().any(|x| x == "--synthesize") {
+        for &(mode, expected) in &[(Mode::Walk, result0), (Mode::Run, result1)] {
+            match synthesize_script(&tape, mode) {
+                Ok((script, result)) if result == expected => println!("{}", script),
+                Ok((script, result)) => println!(
+                    "{}\nSynthesized script read {} rather than {}",
+                    script, result, expected
+                ),
+                Err(message) => println!("{}", message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every hull of the given length, starting under the droid, which the script gets across.
+    fn survivable_hulls(script: &Script, length: usize) -> Vec<Vec<bool>> {
+        (0..1u32 << (length - 1))
+            .map(|bits| {
+                let mut hull = vec![true];
+                hull.extend((0..length - 1).map(|i| bits & (1 << i) != 0));
+                hull
+            })
+            .filter(|hull| script.simulate(hull).is_ok())
+            .collect()
+    }
+
+    // The same counterexample loop as synthesize_script, with the simulator over a fixed set
+    // of hulls standing in for the tape.
+    fn synthesize_against(mode: Mode, hulls: &[Vec<bool>]) -> Script {
+        let mut synthesizer = Synthesizer::new(mode);
+
+        loop {
+            let script = synthesizer.synthesize().unwrap();
+            match hulls.iter().find(|x| script.simulate(x).is_err()) {
+                Some(hull) => synthesizer.add_hull(hull),
+                None => return script,
+            }
+        }
+    }
+
+    fn assert_synthesis_matches(mode: Mode, lines: &[&str], hull_length: usize) {
+        let reference = build_program(lines);
+        let hulls = survivable_hulls(&reference, hull_length);
+        let script = synthesize_against(mode, &hulls);
+
+        for hull in &hulls {
+            assert_eq!(script.simulate(hull), Ok(()), "{}", script);
+        }
+        assert!(script.validate().is_ok());
+        assert!(script.to_string().lines().count() <= lines.len());
+    }
+
+    #[test]
+    fn synthesized_walk_script_crosses_every_hull_the_fixed_one_does() {
+        assert_synthesis_matches(Mode::Walk, &WALK_SCRIPT, 12);
+    }
+
+    #[test]
+    fn synthesized_run_script_crosses_every_hull_the_fixed_one_does() {
+        assert_synthesis_matches(Mode::Run, &RUN_SCRIPT, 12);
+    }
 }
@@ -0,0 +1,529 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+// A small CDCL SAT solver, watched literals with first UIP clause learning, VSIDS style
+// variable activity, phase saving and geometric restarts. It never forgets learnt clauses,
+// which is fine for the few thousand variable problems it's used on.
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct Lit(u32);
+
+impl Lit {
+    pub fn positive(var: usize) -> Lit {
+        Lit(var as u32 * 2)
+    }
+
+    pub fn negative(var: usize) -> Lit {
+        Lit(var as u32 * 2 + 1)
+    }
+
+    pub fn var(self) -> usize {
+        (self.0 / 2) as usize
+    }
+
+    fn is_negative(self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl std::ops::Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Activity(f64, usize);
+
+impl Eq for Activity {}
+
+impl PartialOrd for Activity {
+    fn partial_cmp(&self, other: &Activity) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Activity {
+    fn cmp(&self, other: &Activity) -> Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .unwrap_or(Ordering::Equal)
+            .then(other.1.cmp(&self.1))
+    }
+}
+
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    watches: Vec<Vec<usize>>,
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    phases: Vec<bool>,
+    activity: Vec<f64>,
+    seen: Vec<bool>,
+    increment: f64,
+    heap: BinaryHeap<Activity>,
+    trail: Vec<Lit>,
+    trail_limits: Vec<usize>,
+    propagated: usize,
+    unsatisfiable: bool,
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver {
+            clauses: Vec::new(),
+            watches: Vec::new(),
+            values: Vec::new(),
+            levels: Vec::new(),
+            reasons: Vec::new(),
+            phases: Vec::new(),
+            activity: Vec::new(),
+            seen: Vec::new(),
+            increment: 1.0,
+            heap: BinaryHeap::new(),
+            trail: Vec::new(),
+            trail_limits: Vec::new(),
+            propagated: 0,
+            unsatisfiable: false,
+        }
+    }
+
+    pub fn new_var(&mut self) -> usize {
+        let var = self.values.len();
+        self.values.push(None);
+        self.levels.push(0);
+        self.reasons.push(None);
+        self.phases.push(false);
+        self.activity.push(0.0);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.heap.push(Activity(0.0, var));
+        var
+    }
+
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.values[lit.var()].map(|x| x != lit.is_negative())
+    }
+
+    fn level(&self) -> usize {
+        self.trail_limits.len()
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.values[var] = Some(!lit.is_negative());
+        self.levels[var] = self.level();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    // Clauses can only be added before solving starts.
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        if self.unsatisfiable {
+            return;
+        }
+
+        let mut clause: Vec<Lit> = Vec::new();
+        for &lit in lits {
+            match self.value(lit) {
+                Some(true) => return,
+                Some(false) => (),
+                None if clause.contains(&!lit) => return,
+                None if !clause.contains(&lit) => clause.push(lit),
+                None => (),
+            }
+        }
+
+        match clause.len() {
+            0 => self.unsatisfiable = true,
+            1 => {
+                self.assign(clause[0], None);
+                if self.propagate().is_some() {
+                    self.unsatisfiable = true;
+                }
+            }
+            _ => {
+                self.attach(clause);
+            }
+        }
+    }
+
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[(!clause[0]).index()].push(index);
+        self.watches[(!clause[1]).index()].push(index);
+        self.clauses.push(clause);
+        index
+    }
+
+    // Returns the index of a conflicting clause, if any.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let lit = self.trail[self.propagated];
+            self.propagated += 1;
+
+            let watching = std::mem::take(&mut self.watches[lit.index()]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+
+            for (i, &index) in watching.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&watching[i..]);
+                    break;
+                }
+
+                // Keep the falsified watch in the second slot.
+                if self.clauses[index][0] == !lit {
+                    self.clauses[index].swap(0, 1);
+                }
+
+                let first = self.clauses[index][0];
+                if self.value(first) == Some(true) {
+                    kept.push(index);
+                    continue;
+                }
+
+                let replacement = (2..self.clauses[index].len())
+                    .find(|&k| self.value(self.clauses[index][k]) != Some(false));
+
+                match replacement {
+                    Some(k) => {
+                        self.clauses[index].swap(1, k);
+                        let watch = !self.clauses[index][1];
+                        self.watches[watch.index()].push(index);
+                    }
+                    None => {
+                        kept.push(index);
+                        match self.value(first) {
+                            Some(false) => conflict = Some(index),
+                            _ => self.assign(first, Some(index)),
+                        }
+                    }
+                }
+            }
+
+            self.watches[lit.index()] = kept;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+
+        None
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.increment;
+
+        if self.activity[var] > 1e100 {
+            for x in self.activity.iter_mut() {
+                *x *= 1e-100;
+            }
+            self.increment *= 1e-100;
+            self.heap = (0..self.activity.len())
+                .map(|x| Activity(self.activity[x], x))
+                .collect();
+        } else {
+            self.heap.push(Activity(self.activity[var], var));
+        }
+    }
+
+    // First UIP learning, giving the learnt clause with its asserting literal first and
+    // the level to go back to.
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let mut learnt = vec![Lit(0)];
+        let mut marked = Vec::<usize>::new();
+        let mut pending = 0usize;
+        let mut clause = conflict;
+        let mut index = self.trail.len();
+        let mut resolved: Option<Lit> = None;
+
+        loop {
+            for k in 0..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                if Some(lit) == resolved {
+                    continue;
+                }
+                let var = lit.var();
+                if self.seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+                self.seen[var] = true;
+                marked.push(var);
+                self.bump(var);
+
+                if self.levels[var] == self.level() {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+
+            loop {
+                index -= 1;
+                if self.seen[self.trail[index].var()] {
+                    break;
+                }
+            }
+
+            let lit = self.trail[index];
+            pending -= 1;
+            if pending == 0 {
+                learnt[0] = !lit;
+                break;
+            }
+
+            resolved = Some(lit);
+            clause = self.reasons[lit.var()].unwrap();
+        }
+
+        for var in marked {
+            self.seen[var] = false;
+        }
+
+        let mut backtrack = 0;
+        if learnt.len() > 1 {
+            let deepest = (1..learnt.len())
+                .max_by_key(|&i| self.levels[learnt[i].var()])
+                .unwrap();
+            learnt.swap(1, deepest);
+            backtrack = self.levels[learnt[1].var()];
+        }
+
+        (learnt, backtrack)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.level() <= level {
+            return;
+        }
+
+        let start = self.trail_limits[level];
+        for lit in self.trail.drain(start..) {
+            let var = lit.var();
+            self.phases[var] = !lit.is_negative();
+            self.values[var] = None;
+            self.reasons[var] = None;
+            self.heap.push(Activity(self.activity[var], var));
+        }
+
+        self.trail_limits.truncate(level);
+        self.propagated = self.trail.len();
+    }
+
+    fn pick_branch(&mut self) -> Option<Lit> {
+        while let Some(Activity(activity, var)) = self.heap.pop() {
+            if self.values[var].is_none() && activity == self.activity[var] {
+                return Some(if self.phases[var] {
+                    Lit::positive(var)
+                } else {
+                    Lit::negative(var)
+                });
+            }
+        }
+
+        (0..self.values.len())
+            .find(|&x| self.values[x].is_none())
+            .map(Lit::negative)
+    }
+
+    // Returns a satisfying assignment indexed by variable, or None if there isn't one.
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        if self.unsatisfiable {
+            return None;
+        }
+
+        let mut restart_limit = 100.0;
+        let mut conflicts = 0usize;
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.level() == 0 {
+                    self.unsatisfiable = true;
+                    return None;
+                }
+
+                let (learnt, level) = self.analyze(conflict);
+                self.backtrack(level);
+                self.increment *= 1.05;
+                conflicts += 1;
+
+                if learnt.len() == 1 {
+                    self.assign(learnt[0], None);
+                } else {
+                    let asserting = learnt[0];
+                    let index = self.attach(learnt);
+                    self.assign(asserting, Some(index));
+                }
+                continue;
+            }
+
+            if conflicts as f64 >= restart_limit {
+                conflicts = 0;
+                restart_limit *= 1.5;
+                self.backtrack(0);
+                continue;
+            }
+
+            match self.pick_branch() {
+                Some(lit) => {
+                    self.trail_limits.push(self.trail.len());
+                    self.assign(lit, None);
+                }
+                None => return Some(self.values.iter().map(|x| x.unwrap()).collect()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solver_with(vars: usize, clauses: &[Vec<Lit>]) -> Solver {
+        let mut solver = Solver::new();
+        for _ in 0..vars {
+            solver.new_var();
+        }
+        for clause in clauses {
+            solver.add_clause(clause);
+        }
+        solver
+    }
+
+    fn satisfies(model: &[bool], clause: &[Lit]) -> bool {
+        clause.iter().any(|x| model[x.var()] != x.is_negative())
+    }
+
+    // Every clause of a satisfiable problem has to hold under the returned model, and an
+    // unsatisfiable one has to agree with trying every assignment.
+    fn check(vars: usize, clauses: &[Vec<Lit>]) -> bool {
+        match solver_with(vars, clauses).solve() {
+            Some(model) => {
+                assert_eq!(model.len(), vars);
+                for clause in clauses {
+                    assert!(satisfies(&model, clause), "{:?} fails {:?}", model, clause);
+                }
+                true
+            }
+            None => {
+                if vars <= 16 {
+                    let any = (0..1u32 << vars).any(|bits| {
+                        let model: Vec<bool> = (0..vars).map(|x| bits & (1 << x) != 0).collect();
+                        clauses.iter().all(|x| satisfies(&model, x))
+                    });
+                    assert!(!any, "missed a model of {:?}", clauses);
+                }
+                false
+            }
+        }
+    }
+
+    #[test]
+    fn trivial_problems() {
+        assert!(check(0, &[]));
+        assert!(check(1, &[vec![Lit::positive(0)]]));
+        assert!(check(2, &[vec![Lit::positive(0), Lit::negative(0)]]));
+        assert!(!check(1, &[vec![]]));
+        assert!(!check(1, &[vec![Lit::positive(0)], vec![Lit::negative(0)]]));
+        assert!(!check(
+            2,
+            &[
+                vec![Lit::positive(0), Lit::positive(1)],
+                vec![Lit::positive(0), Lit::negative(1)],
+                vec![Lit::negative(0), Lit::positive(1)],
+                vec![Lit::negative(0), Lit::negative(1)],
+            ]
+        ));
+    }
+
+    #[test]
+    fn unit_clauses_propagate_as_they_are_added() {
+        let (a, b, c) = (Lit::positive(0), Lit::positive(1), Lit::positive(2));
+        let mut solver = solver_with(3, &[vec![!a, b], vec![!b, c]]);
+        assert_eq!(solver.value(c), None);
+
+        solver.add_clause(&[a]);
+        assert_eq!(solver.value(b), Some(true));
+        assert_eq!(solver.value(c), Some(true));
+        assert_eq!(solver.solve(), Some(vec![true, true, true]));
+
+        // And a unit contradicting them makes the whole problem unsatisfiable.
+        let mut solver = solver_with(3, &[vec![!a, b], vec![!b, c], vec![a]]);
+        solver.add_clause(&[!c]);
+        assert!(solver.unsatisfiable);
+        assert_eq!(solver.solve(), None);
+    }
+
+    // n + 1 pigeons in n holes, needing a fair amount of clause learning to refute.
+    fn pigeonhole(holes: usize) -> (usize, Vec<Vec<Lit>>) {
+        let var = |pigeon: usize, hole: usize| pigeon * holes + hole;
+        let mut clauses = Vec::new();
+
+        for pigeon in 0..=holes {
+            clauses.push((0..holes).map(|x| Lit::positive(var(pigeon, x))).collect());
+        }
+        for hole in 0..holes {
+            for a in 0..=holes {
+                for b in a + 1..=holes {
+                    clauses.push(vec![
+                        Lit::negative(var(a, hole)),
+                        Lit::negative(var(b, hole)),
+                    ]);
+                }
+            }
+        }
+
+        ((holes + 1) * holes, clauses)
+    }
+
+    #[test]
+    fn pigeonhole_is_unsatisfiable() {
+        for holes in 1..=6 {
+            let (vars, clauses) = pigeonhole(holes);
+            assert!(!check(vars, &clauses), "{} holes", holes);
+        }
+
+        // Dropping one pigeon leaves room for the rest.
+        let (vars, mut clauses) = pigeonhole(5);
+        clauses.remove(0);
+        assert!(check(vars, &clauses));
+    }
+
+    #[test]
+    fn random_problems_give_valid_models() {
+        let mut seed = 12345u64;
+        let mut random = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+
+        let mut counts = [0, 0];
+        for _ in 0..200 {
+            let vars = 12;
+            // Around the 3-SAT phase transition, so both answers turn up.
+            let clauses: Vec<Vec<Lit>> = (0..51)
+                .map(|_| {
+                    (0..3)
+                        .map(|_| match random(2) {
+                            0 => Lit::positive(random(vars)),
+                            _ => Lit::negative(random(vars)),
+                        })
+                        .collect()
+                })
+                .collect();
+            counts[check(vars, &clauses) as usize] += 1;
+        }
+
+        assert!(counts[0] > 0 && counts[1] > 0, "{:?}", counts);
+    }
+}
@@ -0,0 +1,388 @@
+use std::fmt;
+
+// The springdroid only has room for this many instructions, not counting WALK or RUN.
+pub const MAX_INSTRUCTIONS: usize = 15;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    T,
+    J,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Opcode {
+    And,
+    Or,
+    Not,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Mode {
+    Walk,
+    Run,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct Instruction {
+    pub op: Opcode,
+    pub source: Register,
+    pub dest: Register,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Script {
+    pub instructions: Vec<Instruction>,
+    pub mode: Mode,
+}
+
+pub const SENSORS: [Register; 9] = [
+    Register::A,
+    Register::B,
+    Register::C,
+    Register::D,
+    Register::E,
+    Register::F,
+    Register::G,
+    Register::H,
+    Register::I,
+];
+
+impl Register {
+    fn parse(text: &str) -> Option<Register> {
+        match text {
+            "T" => Some(Register::T),
+            "J" => Some(Register::J),
+            _ => SENSORS.iter().find(|x| x.name() == text).copied(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Register::A => "A",
+            Register::B => "B",
+            Register::C => "C",
+            Register::D => "D",
+            Register::E => "E",
+            Register::F => "F",
+            Register::G => "G",
+            Register::H => "H",
+            Register::I => "I",
+            Register::T => "T",
+            Register::J => "J",
+        }
+    }
+
+    // How far ahead a sensor register looks, 1 for A up to 9 for I.
+    pub fn distance(self) -> Option<usize> {
+        SENSORS.iter().position(|&x| x == self).map(|x| x + 1)
+    }
+
+    pub fn is_writable(self) -> bool {
+        self == Register::T || self == Register::J
+    }
+}
+
+impl Mode {
+    pub fn sensor_count(self) -> usize {
+        match self {
+            Mode::Walk => 4,
+            Mode::Run => 9,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            Opcode::And => "AND",
+            Opcode::Or => "OR",
+            Opcode::Not => "NOT",
+        };
+        write!(f, "{} {} {}", op, self.source.name(), self.dest.name())
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ins in &self.instructions {
+            writeln!(f, "{}", ins)?;
+        }
+        match self.mode {
+            Mode::Walk => writeln!(f, "WALK"),
+            Mode::Run => writeln!(f, "RUN"),
+        }
+    }
+}
+
+fn parse_instruction(line: &str) -> Result<Instruction, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let op = match words.first() {
+        Some(&"AND") => Opcode::And,
+        Some(&"OR") => Opcode::Or,
+        Some(&"NOT") => Opcode::Not,
+        Some(x) => return Err(format!("unknown instruction '{}'", x)),
+        None => return Err(String::from("empty line")),
+    };
+
+    if words.len() != 3 {
+        return Err(format!("{} takes 2 registers", words[0]));
+    }
+
+    let source = match Register::parse(words[1]) {
+        Some(x) => x,
+        None => return Err(format!("unknown register '{}'", words[1])),
+    };
+    let dest = match Register::parse(words[2]) {
+        Some(x) => x,
+        None => return Err(format!("unknown register '{}'", words[2])),
+    };
+
+    Ok(Instruction { op, source, dest })
+}
+
+impl Script {
+    // Expects the same lines which would be sent to the droid, ending in WALK or RUN.
+    pub fn parse(lines: &[&str]) -> Result<Script, String> {
+        let (last, body) = match lines.split_last() {
+            Some(x) => x,
+            None => return Err(String::from("empty script")),
+        };
+
+        let mode = match last.trim() {
+            "WALK" => Mode::Walk,
+            "RUN" => Mode::Run,
+            x => {
+                return Err(format!(
+                    "line {}: script must end with WALK or RUN, not '{}'",
+                    lines.len(),
+                    x
+                ))
+            }
+        };
+
+        let mut instructions = Vec::<Instruction>::new();
+        for (i, line) in body.iter().enumerate() {
+            match parse_instruction(line) {
+                Ok(x) => instructions.push(x),
+                Err(message) => return Err(format!("line {}: {}", i + 1, message)),
+            }
+        }
+
+        let script = Script { instructions, mode };
+        script.validate()?;
+        Ok(script)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.instructions.len() > MAX_INSTRUCTIONS {
+            return Err(format!(
+                "{} instructions is more than the droid's limit of {}",
+                self.instructions.len(),
+                MAX_INSTRUCTIONS
+            ));
+        }
+
+        for (i, ins) in self.instructions.iter().enumerate() {
+            if !ins.dest.is_writable() {
+                return Err(format!(
+                    "line {}: can only write to T or J, not {}",
+                    i + 1,
+                    ins.dest.name()
+                ));
+            }
+            if let Some(distance) = ins.source.distance() {
+                if distance > self.mode.sensor_count() {
+                    return Err(format!(
+                        "line {}: register {} can't be read in WALK mode",
+                        i + 1,
+                        ins.source.name()
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The ASCII input for the springdroid's Intcode tape.
+    pub fn to_input(&self) -> Vec<i64> {
+        self.to_string().chars().map(|x| x as i64).collect()
+    }
+
+    // sensors[0] is the ground under A, one tile ahead of the droid.
+    pub fn jumps(&self, sensors: &[bool]) -> bool {
+        let mut t = false;
+        let mut j = false;
+
+        for ins in &self.instructions {
+            let x = match ins.source {
+                Register::T => t,
+                Register::J => j,
+                r => sensors[r.distance().unwrap() - 1],
+            };
+            let y = if ins.dest == Register::T {
+                &mut t
+            } else {
+                &mut j
+            };
+
+            *y = match ins.op {
+                Opcode::And => x && *y,
+                Opcode::Or => x || *y,
+                Opcode::Not => !x,
+            };
+        }
+
+        j
+    }
+
+    // Walks the droid from the start of the hull, with ground assumed past its end.
+    // Returns the position of the hole it falls into, if it does.
+    pub fn simulate(&self, hull: &[bool]) -> Result<(), usize> {
+        let ground = |x: usize| x >= hull.len() || hull[x];
+        let mut position = 0usize;
+
+        while position < hull.len() {
+            let sensors: Vec<bool> = (1..=SENSORS.len()).map(|x| ground(position + x)).collect();
+
+            position += if self.jumps(&sensors) { 4 } else { 1 };
+
+            if !ground(position) {
+                return Err(position);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Accepts either a bare hull like "#####.#..####" or the frames the droid's tape prints when
+// it falls, where the first line drawn with ground in it is the hull under the droid's start.
+pub fn parse_hull(text: &str) -> Option<Vec<bool>> {
+    let line = text
+        .lines()
+        .map(|x| x.trim())
+        .find(|x| x.contains('#') && x.chars().all(|c| c == '#' || c == '.' || c == '@'))?;
+
+    Some(line.chars().map(|x| x == '#').collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::vm::IntCodeMachine;
+
+    fn error(lines: &[&str]) -> String {
+        Script::parse(lines).unwrap_err()
+    }
+
+    #[test]
+    fn walk_only_reads_the_first_four_sensors() {
+        assert!(Script::parse(&["OR D J", "WALK"]).is_ok());
+        for sensor in &["E", "F", "G", "H", "I"] {
+            let line = format!("OR {} J", sensor);
+            assert_eq!(
+                error(&[&line, "WALK"]),
+                format!("line 1: register {} can't be read in WALK mode", sensor)
+            );
+            assert!(Script::parse(&[&line, "RUN"]).is_ok());
+        }
+    }
+
+    #[test]
+    fn only_t_and_j_can_be_written() {
+        assert!(Script::parse(&["NOT A T", "NOT T J", "WALK"]).is_ok());
+        assert_eq!(
+            error(&["NOT A T", "AND T A", "WALK"]),
+            "line 2: can only write to T or J, not A"
+        );
+        assert_eq!(
+            error(&["OR J E", "RUN"]),
+            "line 1: can only write to T or J, not E"
+        );
+    }
+
+    #[test]
+    fn instruction_limit_is_enforced() {
+        let mut lines = vec!["NOT T T"; MAX_INSTRUCTIONS];
+        lines.push("WALK");
+        assert!(Script::parse(&lines).is_ok());
+
+        lines.insert(0, "NOT J J");
+        assert_eq!(
+            error(&lines),
+            "16 instructions is more than the droid's limit of 15"
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert_eq!(error(&[]), "empty script");
+        assert_eq!(
+            error(&["NOT A J"]),
+            "line 1: script must end with WALK or RUN, not 'NOT A J'"
+        );
+        assert_eq!(
+            error(&["XOR A J", "WALK"]),
+            "line 1: unknown instruction 'XOR'"
+        );
+        assert_eq!(error(&["NOT A", "WALK"]), "line 1: NOT takes 2 registers");
+        assert_eq!(error(&["NOT A X", "WALK"]), "line 1: unknown register 'X'");
+    }
+
+    #[test]
+    fn parses_hulls_from_droid_frames() {
+        let frame = "Didn't make it across:\n\n.................\n.................\n@................\n#####.#..########\n";
+        assert_eq!(parse_hull("#.##"), Some(vec![true, false, true, true]));
+        assert_eq!(
+            parse_hull(frame).unwrap().iter().filter(|&&x| !x).count(),
+            3
+        );
+        assert_eq!(parse_hull("Walking...\n"), None);
+    }
+
+    // Scripts which fall into a hole on the real tape have to fall on the hull the droid
+    // draws too, at the same place, and a script which gets across has to survive it.
+    #[test]
+    fn simulator_agrees_with_the_droid() {
+        let tape: Vec<i64> = std::fs::read_to_string("data/day21.txt")
+            .unwrap()
+            .split(",")
+            .map(|x| x.trim().parse().unwrap())
+            .collect();
+        let across = Script::parse(&[
+            "NOT A J", "NOT B T", "OR T J", "NOT C T", "OR T J", "AND D J", "WALK",
+        ])
+        .unwrap();
+        assert!(
+            *IntCodeMachine::run_all(&tape, &across.to_input())
+                .last()
+                .unwrap()
+                > 0xFF
+        );
+
+        for lines in &[&["WALK"][..], &["NOT A J", "WALK"], &["NOT D J", "WALK"]] {
+            let script = Script::parse(lines).unwrap();
+            let output = IntCodeMachine::run_all(&tape, &script.to_input());
+            let frames: String = output.iter().map(|&x| x as u8 as char).collect();
+
+            // The last frame shows the droid just below the hole it fell through.
+            let hull = parse_hull(&frames).unwrap();
+            let fallen = frames.trim_end().lines().last().unwrap();
+            let hole = fallen.find('@').unwrap();
+
+            assert_eq!(script.simulate(&hull), Err(hole), "{}", frames);
+            assert_eq!(across.simulate(&hull), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use super::sat::{Lit, Solver};
+use super::springscript::{Instruction, Mode, Opcode, Register, Script, MAX_INSTRUCTIONS, SENSORS};
+
+// Finds the shortest springscript which gets the droid across every hull it's been shown.
+// For each length in turn the question "is there a script this long" is written out as a
+// SAT problem. The unknowns are each instruction's opcode and registers, the values of T
+// and J after every instruction for each distinct sensor window on the hulls, and which
+// tiles of each hull the droid reaches. Adding a hull only adds constraints, so lengths
+// already ruled out never need trying again.
+
+const OPCODES: [Opcode; 3] = [Opcode::And, Opcode::Or, Opcode::Not];
+
+pub struct Synthesizer {
+    mode: Mode,
+    hulls: Vec<Vec<bool>>,
+    shortest: usize,
+}
+
+// Variables describing one instruction.
+struct Choice {
+    ops: Vec<usize>,
+    sources: Vec<usize>,
+    writes_j: usize,
+}
+
+struct Encoding {
+    solver: Solver,
+    choices: Vec<Choice>,
+    sources: Vec<Register>,
+}
+
+fn exactly_one(solver: &mut Solver, vars: &[usize]) {
+    let any: Vec<Lit> = vars.iter().map(|&x| Lit::positive(x)).collect();
+    solver.add_clause(&any);
+
+    for (i, &a) in vars.iter().enumerate() {
+        for &b in &vars[i + 1..] {
+            solver.add_clause(&[Lit::negative(a), Lit::negative(b)]);
+        }
+    }
+}
+
+// Adds clauses making `a` equal `b` whenever `condition` holds.
+fn equal_when(solver: &mut Solver, condition: Lit, a: Lit, b: Lit) {
+    solver.add_clause(&[!condition, !a, b]);
+    solver.add_clause(&[!condition, a, !b]);
+}
+
+impl Encoding {
+    fn new(mode: Mode, length: usize, hulls: &[Vec<bool>]) -> Encoding {
+        let mut solver = Solver::new();
+        let mut sources: Vec<Register> = SENSORS[..mode.sensor_count()].to_vec();
+        sources.push(Register::T);
+        sources.push(Register::J);
+
+        let mut windows = HashMap::<Vec<bool>, usize>::new();
+        let hull_windows: Vec<Vec<usize>> = hulls
+            .iter()
+            .map(|hull| {
+                let ground = |x: usize| x >= hull.len() || hull[x];
+                (0..hull.len())
+                    .map(|p| {
+                        let window: Vec<bool> =
+                            (1..=mode.sensor_count()).map(|x| ground(p + x)).collect();
+                        let next = windows.len();
+                        *windows.entry(window).or_insert(next)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let choices: Vec<Choice> = (0..length)
+            .map(|_| Choice {
+                ops: OPCODES.iter().map(|_| solver.new_var()).collect(),
+                sources: sources.iter().map(|_| solver.new_var()).collect(),
+                writes_j: solver.new_var(),
+            })
+            .collect();
+
+        for choice in &choices {
+            exactly_one(&mut solver, &choice.ops);
+            exactly_one(&mut solver, &choice.sources);
+        }
+
+        // The final value of J in each window.
+        let mut jumps = vec![0usize; windows.len()];
+
+        for (window, &w) in &windows {
+            let mut t = solver.new_var();
+            let mut j = solver.new_var();
+            solver.add_clause(&[Lit::negative(t)]);
+            solver.add_clause(&[Lit::negative(j)]);
+
+            for choice in &choices {
+                let x = Lit::positive(solver.new_var());
+                let y = Lit::positive(solver.new_var());
+                let r = Lit::positive(solver.new_var());
+                let writes_j = Lit::positive(choice.writes_j);
+
+                for (&var, register) in choice.sources.iter().zip(&sources) {
+                    let selected = Lit::positive(var);
+                    match register {
+                        Register::T => equal_when(&mut solver, selected, x, Lit::positive(t)),
+                        Register::J => equal_when(&mut solver, selected, x, Lit::positive(j)),
+                        sensor => {
+                            let ground = window[sensor.distance().unwrap() - 1];
+                            solver.add_clause(&[!selected, if ground { x } else { !x }]);
+                        }
+                    }
+                }
+
+                equal_when(&mut solver, writes_j, y, Lit::positive(j));
+                equal_when(&mut solver, !writes_j, y, Lit::positive(t));
+
+                for (&var, op) in choice.ops.iter().zip(&OPCODES) {
+                    let selected = Lit::negative(var);
+                    match op {
+                        Opcode::And => {
+                            solver.add_clause(&[selected, !r, x]);
+                            solver.add_clause(&[selected, !r, y]);
+                            solver.add_clause(&[selected, r, !x, !y]);
+                        }
+                        Opcode::Or => {
+                            solver.add_clause(&[selected, r, !x]);
+                            solver.add_clause(&[selected, r, !y]);
+                            solver.add_clause(&[selected, !r, x, y]);
+                        }
+                        Opcode::Not => {
+                            solver.add_clause(&[selected, r, x]);
+                            solver.add_clause(&[selected, !r, !x]);
+                        }
+                    }
+                }
+
+                let next_t = solver.new_var();
+                let next_j = solver.new_var();
+                equal_when(&mut solver, writes_j, Lit::positive(next_j), r);
+                equal_when(
+                    &mut solver,
+                    writes_j,
+                    Lit::positive(next_t),
+                    Lit::positive(t),
+                );
+                equal_when(&mut solver, !writes_j, Lit::positive(next_t), r);
+                equal_when(
+                    &mut solver,
+                    !writes_j,
+                    Lit::positive(next_j),
+                    Lit::positive(j),
+                );
+                t = next_t;
+                j = next_j;
+            }
+
+            jumps[w] = j;
+        }
+
+        // Every tile the droid reaches leads on to another it reaches, never into a hole.
+        for (hull, indices) in hulls.iter().zip(&hull_windows) {
+            let reached: Vec<usize> = hull.iter().map(|_| solver.new_var()).collect();
+            solver.add_clause(&[Lit::positive(reached[0])]);
+
+            for (position, &w) in indices.iter().enumerate() {
+                let here = Lit::negative(reached[position]);
+                let jump = Lit::positive(jumps[w]);
+
+                for &(landing, condition) in &[(position + 4, !jump), (position + 1, jump)] {
+                    if landing >= hull.len() {
+                        continue;
+                    }
+                    if hull[landing] {
+                        solver.add_clause(&[here, condition, Lit::positive(reached[landing])]);
+                    } else {
+                        solver.add_clause(&[here, condition]);
+                    }
+                }
+            }
+        }
+
+        Encoding {
+            solver,
+            choices,
+            sources,
+        }
+    }
+
+    fn solve(mut self, mode: Mode) -> Option<Script> {
+        let model = self.solver.solve()?;
+        let chosen = |vars: &[usize]| vars.iter().position(|&x| model[x]).unwrap();
+
+        let instructions = self
+            .choices
+            .iter()
+            .map(|choice| Instruction {
+                op: OPCODES[chosen(&choice.ops)],
+                source: self.sources[chosen(&choice.sources)],
+                dest: if model[choice.writes_j] {
+                    Register::J
+                } else {
+                    Register::T
+                },
+            })
+            .collect();
+
+        Some(Script { instructions, mode })
+    }
+}
+
+impl Synthesizer {
+    pub fn new(mode: Mode) -> Synthesizer {
+        Synthesizer {
+            mode,
+            hulls: Vec::new(),
+            shortest: 0,
+        }
+    }
+
+    pub fn hull_count(&self) -> usize {
+        self.hulls.len()
+    }
+
+    pub fn add_hull(&mut self, hull: &[bool]) {
+        self.hulls.push(Vec::from(hull));
+    }
+
+    // Returns None if no script within the droid's instruction limit survives every hull.
+    pub fn synthesize(&mut self) -> Option<Script> {
+        for length in self.shortest..=MAX_INSTRUCTIONS {
+            if let Some(script) = Encoding::new(self.mode, length, &self.hulls).solve(self.mode) {
+                self.shortest = length;
+                debug_assert!(self.hulls.iter().all(|x| script.simulate(x).is_ok()));
+                return Some(script);
+            }
+        }
+
+        None
+    }
+}
@@ -1,294 +1,194 @@
-use crate::expanse::{Expanse, TwoVec};
-use std::collections::HashSet;
+mod bitboard;
 
-#[derive(Debug, Clone)]
-struct World {
-    width: i32,
-    height: i32,
-    grid: Expanse<()>,
-}
+use crate::expanse::Expanse;
 
-impl World {
-    fn new(input: &Vec<Vec<char>>) -> Self {
-        let mut grid = Expanse::<()>::new();
+fn parse_grid(text: &str) -> Expanse<()> {
+    Expanse::from_ascii(text, false, |x| if x == '#' { Some(()) } else { None })
+}
 
-        for y in 0..input.len() {
-            for x in 0..input[y].len() {
-                if input[y][x] == '#' {
-                    grid.write(x as i32, y as i32, ());
-                }
-            }
-        }
+pub fn main() {
+    let text = std::fs::read_to_string("data/day24.txt").unwrap();
+    let input = parse_grid(&text);
 
-        Self {
-            width: input[0].len() as i32,
-            height: input.len() as i32,
-            grid: grid,
-        }
-    }
+    let grid = bitboard::from_expanse(&input);
+    let result0 = bitboard::first_repeated_biodiversity(grid);
+    let result1 = bitboard::count_recursive_bugs(grid, 200);
 
-    fn neighbors_at(&self, x: i32, y: i32) -> i32 {
-        let a = if self.grid.read(x + 1, y).is_some() {
-            1
-        } else {
-            0
-        };
-        let b = if self.grid.read(x - 1, y).is_some() {
-            1
-        } else {
-            0
-        };
-        let c = if self.grid.read(x, y + 1).is_some() {
-            1
-        } else {
-            0
-        };
-        let d = if self.grid.read(x, y - 1).is_some() {
-            1
-        } else {
-            0
-        };
+    println!("{} {}", result0, result1);
+}
 
-        a + b + c + d
+// The bitboards are checked against the same rules run on the general Automaton, which
+// is much slower but has nothing specific to day 24 beyond the neighbor function.
+#[cfg(test)]
+mod tests {
+    use super::bitboard::{self, Grid, RecursiveGrids};
+    use super::*;
+    use adventofcode2019::automaton::{Automaton, Boundary, Neighborhood, Rules};
+    use std::collections::HashMap;
+
+    fn bug_rules() -> Rules {
+        Rules::parse("B12/S1").unwrap()
     }
 
-    fn step(&self) -> World {
-        let mut result = self.clone();
-
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let neighbs = self.neighbors_at(x, y);
+    // The recursive grids all live in one Expanse, with depth i stored at x offset 5i. Deeper
+    // grids sit in the middle tile of shallower ones, which is never part of any grid itself.
+    fn recursive_neighbors(gx: i32, y: i32) -> Vec<(i32, i32)> {
+        let i = gx.div_euclid(5);
+        let x = gx.rem_euclid(5);
+        let at = |x: i32, y: i32, i: i32| (i * 5 + x, y);
 
-                if self.grid.read(x, y).is_some() {
-                    if neighbs != 1 {
-                        result.grid.erase(x, y);
-                    }
-                } else if neighbs == 1 || neighbs == 2 {
-                    result.grid.write(x, y, ());
-                }
-            }
+        if x == 2 && y == 2 {
+            return Vec::new();
         }
 
-        result
-    }
-
-    fn get_biodiversity(&self) -> u64 {
-        let mut result = 0u64;
-        let mut digit = 1u64;
-
-        for y in 0..self.height {
-            for x in 0..self.width {
-                if self.grid.read(x, y).is_some() {
-                    result += digit;
+        let mut result = Vec::<(i32, i32)>::new();
+
+        for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (nx, ny) = (x + dx, y + dy);
+
+            if !(0..5).contains(&nx) || !(0..5).contains(&ny) {
+                result.push(at(2 + dx, 2 + dy, i - 1));
+            } else if nx == 2 && ny == 2 {
+                for k in 0..5 {
+                    result.push(match (dx, dy) {
+                        (1, 0) => at(0, k, i + 1),
+                        (-1, 0) => at(4, k, i + 1),
+                        (0, 1) => at(k, 0, i + 1),
+                        _ => at(k, 4, i + 1),
+                    });
                 }
-                digit <<= 1;
+            } else {
+                result.push(at(nx, ny, i));
             }
         }
 
         result
     }
 
-    #[allow(dead_code)]
-    fn draw(&self) {
-        let out = self
-            .grid
-            .render_to_string(false, ".", |_| String::from("#"));
-        println!("{}\n", out);
+    fn flat_world(grid: Grid) -> Automaton {
+        Automaton::new(
+            to_expanse(grid),
+            Neighborhood::VonNeumann,
+            bug_rules(),
+            Boundary::Bounded(0..5, 0..5),
+        )
     }
 
-    pub fn solve(input: &Vec<Vec<char>>) -> u64 {
-        let mut world = World::new(&input);
-        let mut set = HashSet::<u64>::new();
-        let mut diversity;
+    fn recursive_world(grid: Grid) -> Automaton {
+        Automaton::new(
+            to_expanse(grid),
+            Neighborhood::Custom(Box::new(recursive_neighbors)),
+            bug_rules(),
+            Boundary::Infinite,
+        )
+    }
 
-        loop {
-            diversity = world.get_biodiversity();
-            if set.contains(&diversity) {
-                break;
-            }
-            set.insert(diversity);
-            world = world.step();
+    fn to_expanse(grid: Grid) -> Expanse<()> {
+        let mut result = Expanse::new();
+        for i in (0..25).filter(|i| grid & (1 << i) != 0) {
+            result.write(i % 5, i / 5, ());
         }
-
-        diversity
+        result
     }
-}
-
-#[derive(Debug, Clone)]
-struct RecursiveWorld {
-    grids: TwoVec<Expanse<()>>,
-}
 
-impl RecursiveWorld {
-    fn new(input: &Vec<Vec<char>>) -> Self {
-        let mut grid = Expanse::<()>::new();
-
-        for y in 0..input.len() {
-            for x in 0..input[y].len() {
-                if input[y][x] == '#' {
-                    grid.write(x as i32, y as i32, ());
-                }
-            }
+    fn levels_of(cells: &Expanse<()>) -> HashMap<i32, Grid> {
+        let mut result = HashMap::<i32, Grid>::new();
+        for ((gx, y), _) in cells.iter() {
+            *result.entry(gx.div_euclid(5)).or_insert(0) |= 1 << (y * 5 + gx.rem_euclid(5));
         }
-
-        let mut vec = TwoVec::new();
-        vec.expand_to_contain(-1, Expanse::<()>::new);
-        vec.expand_to_contain(1, Expanse::<()>::new);
-        vec[0] = grid;
-
-        Self { grids: vec }
+        result
     }
 
-    fn ensure_enough_layers(&mut self) {
-        let start = self.grids.index_range().start;
-        let end = self.grids.index_range().end - 1;
+    // The puzzle input and a spread of other layouts, with the middle tile empty so they
+    // also make sense as recursive grids.
+    fn starting_grids() -> Vec<Grid> {
+        let text = std::fs::read_to_string("data/day24.txt").unwrap();
+        let mut result = vec![bitboard::from_expanse(&parse_grid(&text)), 0, 1 << 24];
 
-        if !self.grids[start].empty() {
-            self.grids.expand_to_contain(start - 1, Expanse::<()>::new);
-        }
-        if !self.grids[end].empty() {
-            self.grids.expand_to_contain(end + 1, Expanse::<()>::new);
+        let mut seed = 0x2545_f491u32;
+        for _ in 0..8 {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            result.push((seed >> 7) & 0x1ff_ffff & !(1 << 12));
         }
-    }
 
-    fn neighbors_at(&self, i: i32, x: i32, y: i32) -> i32 {
-        let checks = if x == 1 && y == 2 {
-            vec![
-                (0, 2, i),
-                (1, 1, i),
-                (1, 3, i),
-                (0, 0, i + 1),
-                (0, 1, i + 1),
-                (0, 2, i + 1),
-                (0, 3, i + 1),
-                (0, 4, i + 1),
-            ]
-        } else if x == 3 && y == 2 {
-            vec![
-                (4, 2, i),
-                (3, 1, i),
-                (3, 3, i),
-                (4, 0, i + 1),
-                (4, 1, i + 1),
-                (4, 2, i + 1),
-                (4, 3, i + 1),
-                (4, 4, i + 1),
-            ]
-        } else if x == 2 && y == 1 {
-            vec![
-                (2, 0, i),
-                (1, 1, i),
-                (3, 1, i),
-                (0, 0, i + 1),
-                (1, 0, i + 1),
-                (2, 0, i + 1),
-                (3, 0, i + 1),
-                (4, 0, i + 1),
-            ]
-        } else if x == 2 && y == 3 {
-            vec![
-                (2, 4, i),
-                (1, 3, i),
-                (3, 3, i),
-                (0, 4, i + 1),
-                (1, 4, i + 1),
-                (2, 4, i + 1),
-                (3, 4, i + 1),
-                (4, 4, i + 1),
-            ]
-        } else {
-            let l = if x == 0 { (1, 2, i - 1) } else { (x - 1, y, i) };
-            let r = if x == 4 { (3, 2, i - 1) } else { (x + 1, y, i) };
-            let u = if y == 0 { (2, 1, i - 1) } else { (x, y - 1, i) };
-            let d = if y == 4 { (2, 3, i - 1) } else { (x, y + 1, i) };
+        result
+    }
 
-            vec![l, r, u, d]
-        };
+    #[test]
+    fn flat_steps_match_automaton() {
+        for grid in starting_grids() {
+            let mut world = flat_world(grid);
+            let mut board = grid;
 
-        checks.iter().fold(0, |a, (x, y, i)| {
-            if self.grids.index_range().contains(i) {
-                a + if self.grids[*i].read(*x, *y).is_some() {
-                    1
-                } else {
-                    0
-                }
-            } else {
-                a
+            for _ in 0..500 {
+                world.step();
+                board = bitboard::step_flat(board);
+                assert_eq!(board, bitboard::from_expanse(world.cells()));
             }
-        })
+        }
     }
 
-    fn step(&self) -> Self {
-        let mut result = self.clone();
-        result.ensure_enough_layers();
-
-        for i in self.grids.index_range() {
-            for y in 0..5 {
-                for x in 0..5 {
-                    if x == 2 && y == 2 {
-                        continue;
-                    }
+    #[test]
+    fn recursive_steps_match_automaton() {
+        for grid in starting_grids() {
+            let mut world = recursive_world(grid);
+            let mut grids = RecursiveGrids::new(grid);
 
-                    let neighbors = self.neighbors_at(i, x, y);
+            for minute in 1..=60 {
+                world.step();
+                grids = grids.step();
 
-                    if self.grids[i].read(x, y).is_some() {
-                        if neighbors != 1 {
-                            result.grids[i].erase(x, y);
-                        }
-                    } else if neighbors == 1 || neighbors == 2 {
-                        result.grids[i].write(x, y, ());
-                    }
+                let levels = levels_of(world.cells());
+                for (depth, &level) in &levels {
+                    assert_eq!(grids.level(*depth), level, "minute {}", minute);
+                }
+                for depth in grids.depths().filter(|x| !levels.contains_key(x)) {
+                    assert_eq!(grids.level(depth), 0, "minute {}", minute);
                 }
             }
         }
-
-        result
-    }
-
-    #[allow(dead_code)]
-    fn draw(&self) {
-        for i in self.grids.index_range() {
-            let txt = self.grids[i].render_to_string(false, ".", |_| String::from("#"));
-            println!("Depth {}:\n{}\n", i, txt);
-        }
     }
 
-    fn count_bugs(&self) -> u64 {
-        let mut bug_count = 0u64;
+    #[test]
+    fn first_repeat_matches_automaton() {
+        for grid in starting_grids() {
+            let mut world = flat_world(grid);
+            let mut seen = std::collections::HashSet::new();
 
-        for i in self.grids.index_range() {
-            for x in self.grids[i].x_range() {
-                for y in self.grids[i].y_range() {
-                    if self.grids[i].read(x, y).is_some() {
-                        bug_count += 1;
-                    }
-                }
+            while seen.insert(bitboard::from_expanse(world.cells())) {
+                world.step();
             }
-        }
 
-        bug_count
+            assert_eq!(
+                bitboard::first_repeated_biodiversity(grid),
+                bitboard::from_expanse(world.cells())
+            );
+        }
     }
 
-    pub fn solve(input: &Vec<Vec<char>>) -> u64 {
-        let mut world = RecursiveWorld::new(&input);
-
-        for _ in 0..200 {
-            world = world.step();
+    // Millions of minutes of the flat grid, checked by jumping ahead through the cycle the
+    // layout eventually falls into.
+    #[test]
+    fn flat_runs_millions_of_minutes() {
+        const MINUTES: usize = 2_000_000;
+
+        let grid = starting_grids()[0];
+        let mut history = Vec::<Grid>::new();
+        let mut first_seen = HashMap::<Grid, usize>::new();
+        let mut board = grid;
+        while !first_seen.contains_key(&board) {
+            first_seen.insert(board, history.len());
+            history.push(board);
+            board = bitboard::step_flat(board);
         }
+        let cycle_start = first_seen[&board];
+        let cycle_length = history.len() - cycle_start;
+        let expected = history[cycle_start + (MINUTES - cycle_start) % cycle_length];
 
-        world.count_bugs()
+        let mut board = grid;
+        for _ in 0..MINUTES {
+            board = bitboard::step_flat(board);
+        }
+        assert_eq!(board, expected);
     }
 }
-
-pub fn main() {
-    let input: Vec<Vec<char>> = std::fs::read_to_string("data/day24.txt")
-        .unwrap()
-        .lines()
-        .map(|x| x.chars().collect())
-        .collect();
-
-    let result0 = World::solve(&input);
-    let result1 = RecursiveWorld::solve(&input);
-
-    println!("{} {}", result0, result1);
-}
@@ -0,0 +1,193 @@
+use crate::expanse::{Expanse, TwoVec};
+use std::ops::Range;
+
+// A 5x5 grid packed into the low 25 bits of a u32, with the bug at (x, y) in bit y * 5 + x.
+// Read as a number that's exactly the grid's biodiversity rating.
+pub type Grid = u32;
+
+const CENTER: usize = 12;
+
+const fn bit(x: i32, y: i32) -> u32 {
+    1 << (y * 5 + x)
+}
+
+// Same level neighbors, with nothing past the edges, as in part one.
+const fn flat_masks() -> [u32; 25] {
+    let mut result = [0u32; 25];
+    let mut i = 0;
+    while i < 25 {
+        let (x, y) = ((i % 5) as i32, (i / 5) as i32);
+        if x > 0 {
+            result[i] |= bit(x - 1, y);
+        }
+        if x < 4 {
+            result[i] |= bit(x + 1, y);
+        }
+        if y > 0 {
+            result[i] |= bit(x, y - 1);
+        }
+        if y < 4 {
+            result[i] |= bit(x, y + 1);
+        }
+        i += 1;
+    }
+    result
+}
+
+// As flat_masks, but the middle tile is the inner grid rather than a tile of its own.
+const fn same_level_masks() -> [u32; 25] {
+    let mut result = flat_masks();
+    let mut i = 0;
+    while i < 25 {
+        result[i] &= !(1 << CENTER);
+        i += 1;
+    }
+    result[CENTER] = 0;
+    result
+}
+
+// The tiles of the grid one level in which touch each tile around the middle.
+const fn inner_masks() -> [u32; 25] {
+    let mut result = [0u32; 25];
+    let mut k = 0;
+    while k < 5 {
+        result[bit_index(2, 1)] |= bit(k, 0);
+        result[bit_index(2, 3)] |= bit(k, 4);
+        result[bit_index(1, 2)] |= bit(0, k);
+        result[bit_index(3, 2)] |= bit(4, k);
+        k += 1;
+    }
+    result
+}
+
+// The tiles of the grid one level out which touch each tile on the edge.
+const fn outer_masks() -> [u32; 25] {
+    let mut result = [0u32; 25];
+    let mut k = 0;
+    while k < 5 {
+        result[bit_index(k, 0)] |= bit(2, 1);
+        result[bit_index(k, 4)] |= bit(2, 3);
+        result[bit_index(0, k)] |= bit(1, 2);
+        result[bit_index(4, k)] |= bit(3, 2);
+        k += 1;
+    }
+    result
+}
+
+const fn bit_index(x: i32, y: i32) -> usize {
+    (y * 5 + x) as usize
+}
+
+const FLAT: [u32; 25] = flat_masks();
+const SAME: [u32; 25] = same_level_masks();
+const INNER: [u32; 25] = inner_masks();
+const OUTER: [u32; 25] = outer_masks();
+
+fn bugs_live(grid: Grid, i: usize, neighbors: u32) -> bool {
+    neighbors == 1 || (neighbors == 2 && grid & (1 << i) == 0)
+}
+
+pub fn from_expanse(expanse: &Expanse<()>) -> Grid {
+    expanse
+        .iter()
+        .map(|((x, y), _)| bit(x, y))
+        .fold(0, |a, b| a | b)
+}
+
+pub fn step_flat(grid: Grid) -> Grid {
+    let mut result = 0;
+    for (i, mask) in FLAT.iter().enumerate() {
+        if bugs_live(grid, i, (grid & mask).count_ones()) {
+            result |= 1 << i;
+        }
+    }
+    result
+}
+
+// Every layout fits in a 2^25 bit set, so there's no need to hash them.
+pub fn first_repeated_biodiversity(grid: Grid) -> Grid {
+    let mut seen = vec![0u64; 1 << 19];
+    let mut grid = grid;
+
+    loop {
+        let (word, mask) = (grid as usize >> 6, 1u64 << (grid & 63));
+        if seen[word] & mask != 0 {
+            return grid;
+        }
+        seen[word] |= mask;
+        grid = step_flat(grid);
+    }
+}
+
+// Levels increase going inward. There's always an empty level at each end, so the next
+// step never needs to look past them. Unlike the flat grid, a step here costs more the
+// further the bugs have spread, since every level in use is visited.
+#[derive(Debug, Clone)]
+pub struct RecursiveGrids {
+    levels: TwoVec<Grid>,
+}
+
+impl RecursiveGrids {
+    pub fn new(grid: Grid) -> RecursiveGrids {
+        let mut levels = TwoVec::new();
+        levels.push_back(grid);
+        levels.push_back(0);
+        levels.push_front(0);
+        RecursiveGrids { levels }
+    }
+
+    pub fn level(&self, i: i32) -> Grid {
+        self.levels.get(i).copied().unwrap_or(0)
+    }
+
+    pub fn depths(&self) -> Range<i32> {
+        self.levels.index_range()
+    }
+
+    pub fn step(&self) -> RecursiveGrids {
+        let mut levels = self.levels.clone();
+
+        for (depth, next) in levels.iter_mut() {
+            let (outer, grid, inner) = (
+                self.level(depth - 1),
+                self.level(depth),
+                self.level(depth + 1),
+            );
+            *next = 0;
+
+            for i in 0..25 {
+                if i == CENTER {
+                    continue;
+                }
+                let neighbors = (grid & SAME[i]).count_ones()
+                    + (inner & INNER[i]).count_ones()
+                    + (outer & OUTER[i]).count_ones();
+                if bugs_live(grid, i, neighbors) {
+                    *next |= 1 << i;
+                }
+            }
+        }
+
+        let range = self.depths();
+        if levels[range.start] != 0 {
+            levels.push_front(0);
+        }
+        if levels[range.end - 1] != 0 {
+            levels.push_back(0);
+        }
+
+        RecursiveGrids { levels }
+    }
+
+    pub fn count_bugs(&self) -> u32 {
+        self.levels.iter().map(|(_, x)| x.count_ones()).sum()
+    }
+}
+
+pub fn count_recursive_bugs(grid: Grid, minutes: usize) -> u32 {
+    let mut grids = RecursiveGrids::new(grid);
+    for _ in 0..minutes {
+        grids = grids.step();
+    }
+    grids.count_bugs()
+}
@@ -1,17 +1,33 @@
+use crate::image::{Color, Image, BLACK};
 use std::clone::Clone;
+use std::collections::{HashMap, VecDeque};
 use std::ops::{Index, IndexMut, Range};
 
+mod diff;
+mod fill;
+mod nd;
+#[cfg(feature = "serde")]
+mod serialize;
+mod view;
+
+pub use diff::Diff;
+pub use fill::Components;
+pub use nd::ExpanseN;
+pub use view::ExpanseView;
+
+// A deque indexed by i32, where items keep their index as others are added or removed at
+// either end. Pushing to the front gives the new item the index before the old first one.
 #[derive(Debug, Clone)]
 pub struct TwoVec<T> {
-    negative: Vec<T>,
-    positive: Vec<T>,
+    items: VecDeque<T>,
+    start: i32,
 }
 
 impl<T> TwoVec<T> {
     pub fn new() -> TwoVec<T> {
         TwoVec {
-            negative: Vec::<T>::new(),
-            positive: Vec::<T>::new(),
+            items: VecDeque::new(),
+            start: 0,
         }
     }
 
@@ -19,26 +35,96 @@ impl<T> TwoVec<T> {
     where
         F: Fn() -> T,
     {
-        if index < 0 {
-            while (self.negative.len() as i32) < -index {
-                self.negative.push(fill());
-            }
-        } else {
-            while (self.positive.len() as i32) < index + 1 {
-                self.positive.push(fill());
-            }
+        while index < self.start {
+            self.push_front(fill());
+        }
+        while index >= self.index_range().end {
+            self.push_back(fill());
         }
     }
 
+    pub fn expand_to_contain_default(&mut self, index: i32)
+    where
+        T: Default,
+    {
+        self.expand_to_contain(index, T::default);
+    }
+
     pub fn index_range(&self) -> Range<i32> {
         Range {
-            start: -(self.negative.len() as i32),
-            end: self.positive.len() as i32,
+            start: self.start,
+            end: self.start + self.items.len() as i32,
         }
     }
 
-    pub fn empty(&self) -> bool {
-        self.negative.len() + self.positive.len() == 0
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn push_front(&mut self, item: T) {
+        self.items.push_front(item);
+        self.start -= 1;
+    }
+
+    pub fn push_back(&mut self, item: T) {
+        self.items.push_back(item);
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let item = self.items.pop_front()?;
+        self.start += 1;
+        Some(item)
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.items.pop_back()
+    }
+
+    fn offset(&self, i: i32) -> Option<usize> {
+        if self.index_range().contains(&i) {
+            Some((i - self.start) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, i: i32) -> Option<&T> {
+        self.offset(i).map(|x| &self.items[x])
+    }
+
+    pub fn get_mut(&mut self, i: i32) -> Option<&mut T> {
+        self.offset(i).map(move |x| &mut self.items[x])
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (i32, &T)> {
+        self.index_range().zip(self.items.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = (i32, &mut T)> {
+        self.index_range().zip(self.items.iter_mut())
+    }
+
+    // Removes the items for which f returns false. The first index stays where it was and the
+    // items after a removed one move down to close the gap.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(i32, &T) -> bool,
+    {
+        let mut i = self.start;
+        self.items.retain(|x| {
+            i += 1;
+            f(i - 1, x)
+        });
+    }
+}
+
+impl<T> Default for TwoVec<T> {
+    fn default() -> TwoVec<T> {
+        TwoVec::new()
     }
 }
 
@@ -46,162 +132,472 @@ impl<T> Index<i32> for TwoVec<T> {
     type Output = T;
 
     fn index(&self, i: i32) -> &T {
-        if i < 0 {
-            &self.negative[-i as usize - 1]
-        } else {
-            &self.positive[i as usize]
+        match self.offset(i) {
+            Some(x) => &self.items[x],
+            None => panic!("index {} is outside {:?}", i, self.index_range()),
         }
     }
 }
 
 impl<T> IndexMut<i32> for TwoVec<T> {
     fn index_mut(&mut self, i: i32) -> &mut T {
-        if i < 0 {
-            &mut self.negative[-i as usize - 1]
-        } else {
-            &mut self.positive[i as usize]
+        match self.offset(i) {
+            Some(x) => &mut self.items[x],
+            None => panic!("index {} is outside {:?}", i, self.index_range()),
         }
     }
 }
 
+// Cells are stored in square chunks keyed by chunk coordinates, so a write only ever touches
+// one chunk no matter how far the bounds grow, and memory follows the cells actually written.
+const CHUNK_BITS: i32 = 4;
+const CHUNK_SIZE: i32 = 1 << CHUNK_BITS;
+
+#[derive(Debug, Clone)]
+struct Chunk<T> {
+    cells: Vec<Option<T>>,
+    count: usize,
+}
+
+impl<T> Chunk<T> {
+    fn new() -> Chunk<T> {
+        Chunk {
+            cells: (0..CHUNK_SIZE * CHUNK_SIZE).map(|_| None).collect(),
+            count: 0,
+        }
+    }
+}
+
+fn chunk_key(x: i32, y: i32) -> (i32, i32) {
+    (x >> CHUNK_BITS, y >> CHUNK_BITS)
+}
+
+fn cell_index(x: i32, y: i32) -> usize {
+    (((x & (CHUNK_SIZE - 1)) << CHUNK_BITS) | (y & (CHUNK_SIZE - 1))) as usize
+}
+
+fn cell_position((cx, cy): (i32, i32), index: usize) -> (i32, i32) {
+    let index = index as i32;
+    (
+        (cx << CHUNK_BITS) | (index >> CHUNK_BITS),
+        (cy << CHUNK_BITS) | (index & (CHUNK_SIZE - 1)),
+    )
+}
+
+pub const NEIGHBORS4: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+pub const NEIGHBORS8: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+// A rectangle of positions as x and y ranges.
+pub type Rect = (Range<i32>, Range<i32>);
+
 #[derive(Debug, Clone)]
 pub struct Expanse<T> {
-    grid: TwoVec<TwoVec<Option<T>>>,
+    chunks: HashMap<(i32, i32), Chunk<T>>,
+    x_range: Range<i32>,
+    y_range: Range<i32>,
 }
 
 impl<T> Expanse<T> {
     pub fn new() -> Expanse<T> {
         Expanse {
-            grid: TwoVec::new(),
+            chunks: HashMap::new(),
+            x_range: 0..0,
+            y_range: 0..0,
         }
     }
 
     pub fn read(&self, x: i32, y: i32) -> Option<&T> {
-        if self.grid.index_range().contains(&x) && self.grid[x].index_range().contains(&y) {
-            self.grid[x][y].as_ref()
-        } else {
-            None
-        }
+        self.chunks
+            .get(&chunk_key(x, y))
+            .and_then(|chunk| chunk.cells[cell_index(x, y)].as_ref())
     }
 
     pub fn at(&mut self, x: i32, y: i32) -> Option<&mut T> {
-        if self.grid.index_range().contains(&x) && self.grid[x].index_range().contains(&y) {
-            self.grid[x][y].as_mut()
-        } else {
-            None
-        }
+        self.chunks
+            .get_mut(&chunk_key(x, y))
+            .and_then(|chunk| chunk.cells[cell_index(x, y)].as_mut())
     }
 
+    fn include(&mut self, x: i32, y: i32) {
+        self.x_range = self.x_range.start.min(x)..self.x_range.end.max(x + 1);
+        self.y_range = self.y_range.start.min(y)..self.y_range.end.max(y + 1);
+    }
+
+    // Writing only ever grows the ranges, which stretch back to the origin as when every column
+    // was stored out to the furthest write. shrink_to_fit trims them to the occupied cells.
     pub fn write(&mut self, x: i32, y: i32, item: T) {
-        self.grid.expand_to_contain(x, || TwoVec::new());
-        for i in self.grid.index_range() {
-            self.grid[i].expand_to_contain(y, || None);
+        self.include(x, y);
+
+        let chunk = self
+            .chunks
+            .entry(chunk_key(x, y))
+            .or_insert_with(Chunk::new);
+        let cell = &mut chunk.cells[cell_index(x, y)];
+        if cell.is_none() {
+            chunk.count += 1;
         }
-        self.grid[x][y] = Some(item);
+        *cell = Some(item);
     }
 
     pub fn empty(&self) -> bool {
-        for x in self.x_range() {
-            for y in self.y_range() {
-                if self.read(x, y).is_some() {
-                    return false;
+        self.chunks.is_empty()
+    }
+
+    pub fn erase(&mut self, x: i32, y: i32) {
+        let key = chunk_key(x, y);
+
+        if let Some(chunk) = self.chunks.get_mut(&key) {
+            if chunk.cells[cell_index(x, y)].take().is_some() {
+                chunk.count -= 1;
+                if chunk.count == 0 {
+                    self.chunks.remove(&key);
                 }
             }
         }
-        true
     }
 
-    pub fn erase(&mut self, x: i32, y: i32) {
-        if self.at(x, y).is_some() {
-            self.grid[x][y] = None;
-        }
+    // Occupied cells in no particular order, which is the cheapest way to visit them all.
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), &T)> + '_ {
+        self.chunks.iter().flat_map(|(&key, chunk)| {
+            chunk
+                .cells
+                .iter()
+                .enumerate()
+                .filter_map(move |(i, cell)| cell.as_ref().map(|x| (cell_position(key, i), x)))
+        })
     }
 
-    pub fn find_many<F>(&self, f: F) -> Vec<(i32, i32)>
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = ((i32, i32), &mut T)> + '_ {
+        self.chunks.iter_mut().flat_map(|(&key, chunk)| {
+            chunk
+                .cells
+                .iter_mut()
+                .enumerate()
+                .filter_map(move |(i, cell)| cell.as_mut().map(|x| (cell_position(key, i), x)))
+        })
+    }
+
+    // Occupied cells ordered by x and then y. This sorts them all, so iter is better where
+    // the order doesn't matter.
+    pub fn cells(&self) -> impl Iterator<Item = ((i32, i32), &T)> + '_ {
+        let mut positions: Vec<(i32, i32)> = self.iter().map(|(pos, _)| pos).collect();
+        positions.sort();
+
+        positions
+            .into_iter()
+            .map(move |(x, y)| ((x, y), self.read(x, y).unwrap()))
+    }
+
+    // The occupied cells among the four orthogonally adjacent to (x, y).
+    pub fn neighbors4(&self, x: i32, y: i32) -> impl Iterator<Item = ((i32, i32), &T)> + '_ {
+        NEIGHBORS4.iter().filter_map(move |&(dx, dy)| {
+            self.read(x + dx, y + dy)
+                .map(|cell| ((x + dx, y + dy), cell))
+        })
+    }
+
+    // As neighbors4 but including diagonals.
+    pub fn neighbors8(&self, x: i32, y: i32) -> impl Iterator<Item = ((i32, i32), &T)> + '_ {
+        NEIGHBORS8.iter().filter_map(move |&(dx, dy)| {
+            self.read(x + dx, y + dy)
+                .map(|cell| ((x + dx, y + dy), cell))
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.chunks.values().map(|x| x.count).sum()
+    }
+
+    // Erases every cell for which f returns false. Like erase, the ranges are left as they were.
+    pub fn retain<F>(&mut self, mut f: F)
     where
-        F: Fn(&T) -> bool,
+        F: FnMut((i32, i32), &T) -> bool,
     {
-        let mut result = Vec::<(i32, i32)>::new();
-
-        for x in self.grid.index_range() {
-            for y in self.grid[x].index_range() {
-                match self.read(x, y) {
-                    Some(z) => {
-                        if f(z) {
-                            result.push((x, y));
-                        }
+        for (&key, chunk) in self.chunks.iter_mut() {
+            for (i, cell) in chunk.cells.iter_mut().enumerate() {
+                if let Some(x) = cell {
+                    if !f(cell_position(key, i), x) {
+                        *cell = None;
+                        chunk.count -= 1;
                     }
-                    None => (),
                 }
             }
         }
 
+        self.chunks.retain(|_, chunk| chunk.count > 0);
+    }
+
+    // Takes every cell out in no particular order, leaving the ranges as they were.
+    pub fn drain(&mut self) -> impl Iterator<Item = ((i32, i32), T)> {
+        std::mem::take(&mut self.chunks)
+            .into_iter()
+            .flat_map(|(key, chunk)| {
+                chunk
+                    .cells
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(move |(i, cell)| cell.map(|x| (cell_position(key, i), x)))
+            })
+    }
+
+    // Matching positions ordered by x and then y. Only the matches get sorted.
+    pub fn find_many<F>(&self, f: F) -> Vec<(i32, i32)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let mut result: Vec<(i32, i32)> = self
+            .iter()
+            .filter(|&(_, x)| f(x))
+            .map(|(pos, _)| pos)
+            .collect();
+        result.sort_unstable();
         result
     }
 
+    // The first match ordered by x and then y. A chunk stores its cells in that order, so
+    // its first match is its smallest, and the answer is the smallest of those.
     pub fn find<F>(&self, f: F) -> Option<(i32, i32)>
     where
         F: Fn(&T) -> bool,
     {
-        for x in self.grid.index_range() {
-            for y in self.grid[x].index_range() {
-                match self.read(x, y) {
-                    Some(z) => {
-                        if f(z) {
-                            return Some((x, y));
-                        }
-                    }
-                    None => (),
-                }
-            }
+        self.chunks
+            .iter()
+            .filter_map(|(&key, chunk)| {
+                chunk
+                    .cells
+                    .iter()
+                    .position(|cell| matches!(cell, Some(x) if f(x)))
+                    .map(|i| cell_position(key, i))
+            })
+            .min()
+    }
+
+    // The smallest rectangle holding every occupied cell, or None if there aren't any.
+    pub fn bounding_box_of_occupied(&self) -> Option<Rect> {
+        let mut positions = self.iter().map(|(pos, _)| pos);
+        let (x, y) = positions.next()?;
+
+        let (mut min_x, mut max_x, mut min_y, mut max_y) = (x, x, y, y);
+        for (x, y) in positions {
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
         }
-        None
+
+        Some((min_x..max_x + 1, min_y..max_y + 1))
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        let (x_range, y_range) = self.bounding_box_of_occupied().unwrap_or((0..0, 0..0));
+        self.x_range = x_range;
+        self.y_range = y_range;
+    }
+
+    // Keeps only the cells inside the rectangle, which becomes the new ranges.
+    pub fn crop(mut self, rect: Rect) -> Expanse<T> {
+        let (x_range, y_range) = rect;
+        self.retain(|(x, y), _| x_range.contains(&x) && y_range.contains(&y));
+        self.x_range = x_range;
+        self.y_range = y_range;
+        self
+    }
+
+    // Moves every cell, and the ranges with them, by a mapping which keeps rows and columns
+    // lined up, so the corners of the ranges still give the new ranges.
+    fn transform<F>(mut self, f: F) -> Expanse<T>
+    where
+        F: Fn(i32, i32) -> (i32, i32),
+    {
+        let mut result = Expanse::new();
+
+        if !self.x_range.is_empty() && !self.y_range.is_empty() {
+            let (ax, ay) = f(self.x_range.start, self.y_range.start);
+            let (bx, by) = f(self.x_range.end - 1, self.y_range.end - 1);
+            result.x_range = ax.min(bx)..ax.max(bx) + 1;
+            result.y_range = ay.min(by)..ay.max(by) + 1;
+        }
+
+        for ((x, y), item) in self.drain() {
+            let (x, y) = f(x, y);
+            result.write(x, y, item);
+        }
+
+        result
+    }
+
+    pub fn translate(self, dx: i32, dy: i32) -> Expanse<T> {
+        self.transform(|x, y| (x + dx, y + dy))
+    }
+
+    // Rotations are about the origin, and a quarter turn takes the positive x axis onto the
+    // positive y axis, which is clockwise as drawn with y increasing down.
+    pub fn rotate90(self) -> Expanse<T> {
+        self.transform(|x, y| (-y, x))
+    }
+
+    pub fn rotate180(self) -> Expanse<T> {
+        self.transform(|x, y| (-x, -y))
+    }
+
+    pub fn rotate270(self) -> Expanse<T> {
+        self.transform(|x, y| (y, -x))
+    }
+
+    // Mirrors across the y axis, negating x.
+    pub fn flip_x(self) -> Expanse<T> {
+        self.transform(|x, y| (-x, y))
+    }
+
+    // Mirrors across the x axis, negating y.
+    pub fn flip_y(self) -> Expanse<T> {
+        self.transform(|x, y| (x, -y))
+    }
+
+    // Mirrors across the diagonal, swapping x and y.
+    pub fn transpose(self) -> Expanse<T> {
+        self.transform(|x, y| (y, x))
+    }
+
+    // Shrinks the ranges to the occupied cells and moves their corner to the origin.
+    pub fn normalize(mut self) -> Expanse<T> {
+        self.shrink_to_fit();
+        let (dx, dy) = (-self.x_range.start, -self.y_range.start);
+        self.translate(dx, dy)
     }
 
     pub fn x_range(&self) -> Range<i32> {
-        self.grid.index_range()
+        self.x_range.clone()
     }
 
     pub fn y_range(&self) -> Range<i32> {
-        if self.grid.empty() {
-            0..0
-        } else {
-            self.grid[0].index_range()
+        self.y_range.clone()
+    }
+
+    // Reads a grid drawn one row per line, with f deciding what each character holds. The top
+    // left character lands on the origin, or the bottom left if y increases up, and the ranges
+    // cover the whole drawing even where f gave nothing, so to_ascii draws it back exactly.
+    // The exceptions are a newline at the very end, which is dropped, and lines shorter than
+    // the longest, which come back padded with to_ascii's empty character.
+    pub fn from_ascii<F>(text: &str, y_increases_up: bool, f: F) -> Expanse<T>
+    where
+        F: Fn(char) -> Option<T>,
+    {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut result = Expanse::new();
+
+        for (row, line) in lines.iter().enumerate() {
+            let y = if y_increases_up {
+                (lines.len() - 1 - row) as i32
+            } else {
+                row as i32
+            };
+
+            for (x, ch) in line.chars().enumerate() {
+                result.include(x as i32, y);
+                if let Some(item) = f(ch) {
+                    result.write(x as i32, y, item);
+                }
+            }
         }
+
+        result
+    }
+
+    // Like render_to_string with a character per cell, but without a newline after the last
+    // row, as text read from a file usually isn't.
+    pub fn to_ascii<F>(&self, y_increases_up: bool, empty: char, f: F) -> String
+    where
+        F: Fn(&T) -> char,
+    {
+        let mut result =
+            self.render_to_string(y_increases_up, &empty.to_string(), |x| f(x).to_string());
+        result.pop();
+        result
+    }
+
+    // Draws each position in the ranges as a square of scale by scale pixels, colored by f,
+    // which is asked about empty positions too.
+    pub fn to_image<F>(&self, y_increases_up: bool, scale: usize, f: F) -> Image
+    where
+        F: Fn(Option<&T>) -> Color,
+    {
+        let columns = self.x_range().len();
+        let rows = self.y_range().len();
+        let mut image = Image::new(columns * scale, rows * scale, scale, BLACK);
+
+        for (column, x) in self.x_range().enumerate() {
+            for (row, y) in self.y_range().enumerate() {
+                let row = if y_increases_up { rows - 1 - row } else { row };
+                image.fill_cell(column, row, f(self.read(x, y)));
+            }
+        }
+
+        image
     }
 
     pub fn render_to_string<F>(&self, y_increases_up: bool, empty: &str, f: F) -> String
     where
         F: Fn(&T) -> String,
     {
-        fn inner_fn<T, G, I: Iterator<Item = i32>>(
-            this: &Expanse<T>,
-            empty: &str,
-            f: G,
-            y_range: I,
-        ) -> String
-        where
-            G: Fn(&T) -> String,
-        {
-            let mut result = String::new();
-
-            for y in y_range {
-                for x in this.x_range() {
-                    match this.read(x, y) {
-                        Some(x) => result.push_str(f(x).as_str()),
-                        None => result.push_str(empty),
-                    };
-                }
-                result.push_str("\n");
-            }
+        render_grid(
+            self.x_range(),
+            self.y_range(),
+            y_increases_up,
+            |x, y| match self.read(x, y) {
+                Some(x) => f(x),
+                None => empty.to_string(),
+            },
+        )
+    }
+}
 
-            result
+// One line per row of the ranges, with cell giving the text for each position.
+fn render_grid<G>(x_range: Range<i32>, y_range: Range<i32>, y_increases_up: bool, cell: G) -> String
+where
+    G: Fn(i32, i32) -> String,
+{
+    let rows: Box<dyn Iterator<Item = i32>> = if y_increases_up {
+        Box::new(y_range.rev())
+    } else {
+        Box::new(y_range)
+    };
+
+    let mut result = String::new();
+
+    for y in rows {
+        for x in x_range.clone() {
+            result.push_str(&cell(x, y));
         }
+        result.push('\n');
+    }
 
-        if y_increases_up {
-            inner_fn(self, empty, f, self.y_range().rev())
-        } else {
-            inner_fn(self, empty, f, self.y_range())
+    result
+}
+
+impl<T> Default for Expanse<T> {
+    fn default() -> Expanse<T> {
+        Expanse::new()
+    }
+}
+
+impl<T> Extend<((i32, i32), T)> for Expanse<T> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = ((i32, i32), T)>,
+    {
+        for ((x, y), item) in iter {
+            self.write(x, y, item);
         }
     }
 }
@@ -212,16 +608,402 @@ impl<T: Clone> Expanse<T> {
     where
         F: Fn(&T) -> U,
     {
-        let mut new_expanse = Expanse::<U>::new();
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|(&key, chunk)| {
+                let cells = chunk.cells.iter().map(|x| x.as_ref().map(&f)).collect();
+                (
+                    key,
+                    Chunk {
+                        cells,
+                        count: chunk.count,
+                    },
+                )
+            })
+            .collect();
 
-        for x in self.x_range() {
-            for y in self.y_range() {
-                if let Some(cell) = self.read(x, y) {
-                    new_expanse.write(x, y, f(cell));
-                }
-            }
+        Expanse {
+            chunks,
+            x_range: self.x_range.clone(),
+            y_range: self.y_range.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::vm::IntCodeMachine;
+
+    fn chars_except(empty: char) -> impl Fn(char) -> Option<char> {
+        move |x| if x == empty { None } else { Some(x) }
+    }
+
+    fn assert_ascii_round_trips(text: &str, empty: char) {
+        for &y_increases_up in &[false, true] {
+            let expanse = Expanse::from_ascii(text, y_increases_up, chars_except(empty));
+            assert_eq!(expanse.to_ascii(y_increases_up, empty, |&x| x), text);
         }
+    }
+
+    fn two_vec_items(two_vec: &TwoVec<i32>) -> Vec<(i32, i32)> {
+        two_vec.iter().map(|(i, &x)| (i, x)).collect()
+    }
+
+    #[test]
+    fn two_vec_indices_stay_put_as_it_grows() {
+        let mut two_vec = TwoVec::new();
+        assert!(two_vec.is_empty());
+        assert_eq!(two_vec.index_range(), 0..0);
+
+        two_vec.push_back(10);
+        two_vec.push_back(11);
+        two_vec.push_front(9);
+        two_vec.push_front(8);
+        two_vec.push_back(12);
+
+        assert_eq!(two_vec.index_range(), -2..3);
+        assert_eq!(two_vec.len(), 5);
+        assert_eq!(
+            two_vec_items(&two_vec),
+            vec![(-2, 8), (-1, 9), (0, 10), (1, 11), (2, 12)]
+        );
+        assert_eq!(two_vec.get(0), Some(&10));
+        assert_eq!(two_vec.get(-2), Some(&8));
+        assert_eq!(two_vec.get(-3), None);
+        assert_eq!(two_vec.get(3), None);
+
+        for i in 0..100 {
+            two_vec.push_front(-i);
+            two_vec.push_back(i);
+            assert_eq!(two_vec[0], 10);
+            assert_eq!(two_vec[2], 12);
+            assert_eq!(two_vec[-2], 8);
+        }
+        assert_eq!(two_vec.index_range(), -102..103);
+    }
+
+    #[test]
+    fn two_vec_indices_stay_put_as_it_shrinks() {
+        let mut two_vec = TwoVec::new();
+        for i in 0..5 {
+            two_vec.push_back(i);
+        }
+
+        assert_eq!(two_vec.pop_front(), Some(0));
+        assert_eq!(two_vec.pop_back(), Some(4));
+        assert_eq!(two_vec.index_range(), 1..4);
+        assert_eq!(two_vec[1], 1);
+
+        *two_vec.get_mut(2).unwrap() = 20;
+        two_vec.retain(|i, _| i != 1);
+        assert_eq!(two_vec_items(&two_vec), vec![(1, 20), (2, 3)]);
+
+        assert_eq!(two_vec.pop_back(), Some(3));
+        assert_eq!(two_vec.pop_back(), Some(20));
+        assert_eq!(two_vec.pop_back(), None);
+        assert_eq!(two_vec.pop_front(), None);
+        assert!(two_vec.is_empty());
+    }
+
+    #[test]
+    fn two_vec_expands_to_contain() {
+        let mut two_vec = TwoVec::<i32>::default();
+        two_vec.expand_to_contain(-3, || 7);
+        assert_eq!(two_vec.index_range(), -3..0);
+        two_vec.expand_to_contain_default(2);
+        assert_eq!(two_vec.index_range(), -3..3);
+        assert_eq!(
+            two_vec.iter().rev().map(|(_, &x)| x).collect::<Vec<_>>(),
+            vec![0, 0, 0, 7, 7, 7]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "index 3 is outside 0..3")]
+    fn two_vec_index_out_of_range_panics() {
+        let mut two_vec = TwoVec::new();
+        two_vec.expand_to_contain_default(2);
+        let _: i32 = two_vec[3];
+    }
+
+    // Cells spread over negative and positive chunks, with several matches in most of them.
+    fn scattered() -> Expanse<i32> {
+        let mut expanse = Expanse::new();
+        let mut seed = 12345u32;
+        for _ in 0..2000 {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let x = (seed >> 8) as i32 % 100 - 50;
+            let y = (seed >> 20) as i32 % 100 - 50;
+            expanse.write(x, y, (seed % 7) as i32);
+        }
+        expanse
+    }
+
+    #[test]
+    fn find_agrees_with_cells_order() {
+        let expanse = scattered();
+
+        for value in 0..8 {
+            let in_order: Vec<(i32, i32)> = expanse
+                .cells()
+                .filter(|&(_, &x)| x == value)
+                .map(|(pos, _)| pos)
+                .collect();
+
+            assert_eq!(expanse.find(|&x| x == value), in_order.first().copied());
+            assert_eq!(expanse.find_many(|&x| x == value), in_order);
+        }
+    }
+
+    // Cells with no symmetry, away from the origin, so every transform moves them somewhere new.
+    fn lopsided() -> Expanse<char> {
+        Expanse::from_ascii("ab.\nc..\nd.e", false, chars_except('.')).translate(3, -7)
+    }
+
+    fn assert_same(a: &Expanse<char>, b: &Expanse<char>) {
+        assert_eq!((a.x_range(), a.y_range()), (b.x_range(), b.y_range()));
+        assert_eq!(a.cells().collect::<Vec<_>>(), b.cells().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn four_quarter_turns_are_the_identity() {
+        let grid = lopsided();
+
+        assert_same(
+            &grid.clone().rotate90().rotate90().rotate90().rotate90(),
+            &grid,
+        );
+        assert_same(
+            &grid.clone().rotate90().rotate90(),
+            &grid.clone().rotate180(),
+        );
+        assert_same(
+            &grid.clone().rotate90().rotate180(),
+            &grid.clone().rotate270(),
+        );
+        assert_same(&grid.clone().rotate270().rotate90(), &grid);
+        assert_same(&grid.clone().rotate180().rotate180(), &grid);
+    }
+
+    #[test]
+    fn flipping_twice_is_the_identity() {
+        let grid = lopsided();
+
+        assert_same(&grid.clone().flip_x().flip_x(), &grid);
+        assert_same(&grid.clone().flip_y().flip_y(), &grid);
+        assert_same(&grid.clone().transpose().transpose(), &grid);
+        assert_same(&grid.clone().flip_x().flip_y(), &grid.clone().rotate180());
+    }
+
+    #[test]
+    fn transpose_is_a_rotation_and_a_flip() {
+        let grid = lopsided();
+        let transposed = grid.clone().transpose();
+
+        assert_same(&transposed, &grid.clone().rotate90().flip_x());
+        assert_same(&transposed, &grid.clone().flip_x().rotate270());
+        assert_same(&transposed, &grid.clone().flip_y().rotate90());
+        assert_eq!(transposed.read(-7, 3), Some(&'a'));
+        assert_eq!(transposed.read(-5, 3), Some(&'d'));
+        assert_eq!(
+            transposed.normalize().to_ascii(false, '.', |&x| x),
+            "acd\nb..\n..e"
+        );
+    }
+
+    #[test]
+    fn puzzle_grids_round_trip() {
+        for &(day, empty) in &[(10, '.'), (18, '.'), (20, ' '), (24, '.')] {
+            let text = std::fs::read_to_string(format!("data/day{}.txt", day)).unwrap();
+            assert_ascii_round_trips(&text, empty);
+        }
+    }
+
+    #[test]
+    fn camera_view_round_trips() {
+        let tape: Vec<i64> = std::fs::read_to_string("data/day17.txt")
+            .unwrap()
+            .split(',')
+            .map(|x| x.trim().parse().unwrap())
+            .collect();
+        let view: String = IntCodeMachine::run_all(&tape, &[])
+            .iter()
+            .map(|&x| x as u8 as char)
+            .collect();
+
+        assert_ascii_round_trips(view.trim_end(), '.');
+    }
+
+    #[test]
+    fn ascii_origin_follows_y_direction() {
+        let text = "a.\n.b";
+
+        let down = Expanse::from_ascii(text, false, chars_except('.'));
+        assert_eq!(down.read(0, 0), Some(&'a'));
+        assert_eq!(down.read(1, 1), Some(&'b'));
+
+        let up = Expanse::from_ascii(text, true, chars_except('.'));
+        assert_eq!(up.read(0, 1), Some(&'a'));
+        assert_eq!(up.read(1, 0), Some(&'b'));
+    }
+
+    #[test]
+    fn ascii_drops_final_newline() {
+        let expanse = Expanse::from_ascii("#.\n.#\n", false, chars_except('.'));
+        assert_eq!(expanse.y_range(), 0..2);
+        assert_eq!(expanse.to_ascii(false, '.', |&x| x), "#.\n.#");
+    }
+
+    #[test]
+    fn ascii_pads_ragged_lines() {
+        let expanse = Expanse::from_ascii("#\n.#.\n", false, chars_except('.'));
+        assert_eq!(expanse.x_range(), 0..3);
+        assert_eq!(expanse.to_ascii(false, '.', |&x| x), "#..\n.#.");
+        assert_eq!(expanse.to_ascii(true, ' ', |&x| x), " # \n#  ");
+    }
+
+    fn positions<'a, T: 'a>(cells: impl Iterator<Item = ((i32, i32), &'a T)>) -> Vec<(i32, i32)> {
+        cells.map(|(pos, _)| pos).collect()
+    }
+
+    #[test]
+    fn cells_are_ordered_and_agree_with_iter() {
+        let mut expanse = scattered();
+        let in_order = positions(expanse.cells());
+
+        assert!(in_order.windows(2).all(|x| x[0] < x[1]));
+        assert_eq!(in_order.len(), expanse.count());
+
+        let mut unordered = positions(expanse.iter());
+        unordered.sort_unstable();
+        assert_eq!(unordered, in_order);
+
+        for (_, x) in expanse.iter_mut() {
+            *x += 10;
+        }
+        assert!(expanse.iter().all(|(_, &x)| (10..17).contains(&x)));
+        assert_eq!(positions(expanse.cells()), in_order);
+    }
+
+    #[test]
+    fn neighbors_cross_chunk_boundaries() {
+        let mut expanse = Expanse::new();
+        for &pos in &[
+            (15, 0),
+            (16, 0),
+            (-1, 0),
+            (0, 0),
+            (0, 15),
+            (0, 16),
+            (-1, -16),
+        ] {
+            expanse.write(pos.0, pos.1, pos);
+        }
+
+        let cases = [
+            ((15, 0), vec![(16, 0)]),
+            ((16, 0), vec![(15, 0)]),
+            ((17, 0), vec![(16, 0)]),
+            ((0, 0), vec![(-1, 0)]),
+            ((-1, 0), vec![(0, 0)]),
+            ((0, 15), vec![(0, 16)]),
+            ((0, 16), vec![(0, 15)]),
+            ((-1, -15), vec![(-1, -16)]),
+            ((8, 8), vec![]),
+        ];
+        for (pos, expected) in &cases {
+            let found: Vec<_> = expanse.neighbors4(pos.0, pos.1).collect();
+            assert!(found.iter().all(|(at, &x)| *at == x));
+            assert_eq!(&positions(found.into_iter()), expected, "{:?}", pos);
+        }
+
+        // Diagonals included, in NEIGHBORS8 order starting from east.
+        assert_eq!(positions(expanse.neighbors8(-1, -1)), vec![(0, 0), (-1, 0)]);
+        assert_eq!(positions(expanse.neighbors8(1, -1)), vec![(0, 0)]);
+        assert_eq!(positions(expanse.neighbors8(15, 1)), vec![(15, 0), (16, 0)]);
+        assert_eq!(positions(expanse.neighbors8(0, 1)), vec![(-1, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn retain_drops_empty_chunks_and_keeps_ranges() {
+        let mut expanse = scattered();
+        let ranges = (expanse.x_range(), expanse.y_range());
+        let kept: Vec<((i32, i32), i32)> = expanse
+            .cells()
+            .filter(|&((x, _), &value)| x < -20 && value % 2 == 0)
+            .map(|(pos, &value)| (pos, value))
+            .collect();
+
+        expanse.retain(|(x, _), &value| x < -20 && value % 2 == 0);
+
+        assert_eq!(expanse.count(), kept.len());
+        assert_eq!(
+            expanse
+                .cells()
+                .map(|(pos, &x)| (pos, x))
+                .collect::<Vec<_>>(),
+            kept
+        );
+        assert!(expanse.chunks.values().all(|x| x.count > 0));
+        assert!(expanse.chunks.keys().all(|&(cx, _)| cx < -1));
+        assert_eq!((expanse.x_range(), expanse.y_range()), ranges);
+
+        expanse.retain(|_, _| false);
+        assert!(expanse.empty());
+        assert_eq!(expanse.count(), 0);
+    }
+
+    #[test]
+    fn drain_empties_and_keeps_ranges() {
+        let mut expanse = scattered();
+        let ranges = (expanse.x_range(), expanse.y_range());
+        let before: Vec<((i32, i32), i32)> = expanse.cells().map(|(pos, &x)| (pos, x)).collect();
+
+        let mut drained: Vec<((i32, i32), i32)> = expanse.drain().collect();
+        drained.sort_unstable();
+        assert_eq!(drained, before);
+
+        assert!(expanse.empty());
+        assert_eq!(expanse.count(), 0);
+        assert_eq!(expanse.iter().count(), 0);
+        let ((x, y), _) = before[0];
+        assert_eq!(expanse.read(x, y), None);
+        assert_eq!((expanse.x_range(), expanse.y_range()), ranges);
+
+        // Writing again grows from the old ranges rather than from nothing.
+        expanse.write(200, -200, 1);
+        assert_eq!(expanse.count(), 1);
+        assert_eq!(expanse.x_range(), ranges.0.start..201);
+        assert_eq!(expanse.y_range(), -200..ranges.1.end);
+
+        // And the drained cells build the same expanse again.
+        let mut rebuilt = Expanse::new();
+        rebuilt.extend(drained);
+        assert_eq!((rebuilt.x_range(), rebuilt.y_range()), ranges);
+        assert_eq!(
+            rebuilt
+                .cells()
+                .map(|(pos, &x)| (pos, x))
+                .collect::<Vec<_>>(),
+            before
+        );
+    }
+
+    #[test]
+    fn extend_overwrites_and_counts_each_cell_once() {
+        let mut expanse = Expanse::new();
+        expanse.extend(vec![((2, 3), 'a'), ((-20, 3), 'b'), ((2, 3), 'c')]);
+
+        assert_eq!(expanse.count(), 2);
+        assert_eq!(expanse.read(2, 3), Some(&'c'));
+        assert_eq!(expanse.x_range(), -20..3);
+        assert_eq!(expanse.y_range(), 0..4);
 
-        new_expanse
+        expanse.erase(2, 3);
+        expanse.erase(2, 3);
+        assert_eq!(expanse.count(), 1);
+        assert_eq!(positions(expanse.cells()), vec![(-20, 3)]);
     }
 }
@@ -0,0 +1,139 @@
+use super::{render_grid, Expanse};
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+// What changed going from one Expanse to another, with each list ordered by x and then y.
+// Changed cells hold the value before and then after.
+#[derive(Debug)]
+pub struct Diff<'a, T> {
+    before: &'a Expanse<T>,
+    after: &'a Expanse<T>,
+    pub added: Vec<((i32, i32), &'a T)>,
+    pub removed: Vec<((i32, i32), &'a T)>,
+    pub changed: Vec<((i32, i32), &'a T, &'a T)>,
+}
+
+impl<T: PartialEq> Expanse<T> {
+    // Treats self as the earlier state and other as the later one.
+    pub fn diff<'a>(&'a self, other: &'a Expanse<T>) -> Diff<'a, T> {
+        let positions: BTreeSet<(i32, i32)> = self
+            .iter()
+            .chain(other.iter())
+            .map(|(pos, _)| pos)
+            .collect();
+
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+
+        for (x, y) in positions {
+            match (self.read(x, y), other.read(x, y)) {
+                (None, Some(new)) => added.push(((x, y), new)),
+                (Some(old), None) => removed.push(((x, y), old)),
+                (Some(old), Some(new)) if old != new => changed.push(((x, y), old, new)),
+                _ => {}
+            }
+        }
+
+        Diff {
+            before: self,
+            after: other,
+            added,
+            removed,
+            changed,
+        }
+    }
+}
+
+fn union(a: Range<i32>, b: Range<i32>) -> Range<i32> {
+    a.start.min(b.start)..a.end.max(b.end)
+}
+
+impl<'a, T: PartialEq> Diff<'a, T> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    // Draws the later state over the ranges of both, with f for cells which stayed the same
+    // and '+', '-' and '~' in place of cells which were added, removed and changed.
+    pub fn to_ascii<F>(&self, y_increases_up: bool, empty: char, f: F) -> String
+    where
+        F: Fn(&T) -> char,
+    {
+        let x_range = union(self.before.x_range(), self.after.x_range());
+        let y_range = union(self.before.y_range(), self.after.y_range());
+
+        let mut result = render_grid(x_range, y_range, y_increases_up, |x, y| {
+            let ch = match (self.before.read(x, y), self.after.read(x, y)) {
+                (None, None) => empty,
+                (None, Some(_)) => '+',
+                (Some(_), None) => '-',
+                (Some(old), Some(new)) if old != new => '~',
+                (Some(_), Some(new)) => f(new),
+            };
+            ch.to_string()
+        });
+        result.pop();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Expanse<char> {
+        Expanse::from_ascii(text, false, |x| if x == '.' { None } else { Some(x) })
+    }
+
+    #[test]
+    fn same_cells_give_an_empty_diff() {
+        let a = parse("ab.\n.cd");
+        let b = parse("ab.\n.cd");
+
+        let diff = a.diff(&b);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_ascii(false, '.', |&x| x), "ab.\n.cd");
+        assert!(Expanse::<char>::new().diff(&Expanse::new()).is_empty());
+    }
+
+    #[test]
+    fn lists_added_removed_and_changed_cells() {
+        let before = parse("ab.\n.cd");
+        let after = parse("aB.\nxc.");
+
+        let diff = before.diff(&after);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.added, vec![((0, 1), &'x')]);
+        assert_eq!(diff.removed, vec![((2, 1), &'d')]);
+        assert_eq!(diff.changed, vec![((1, 0), &'b', &'B')]);
+
+        let back = after.diff(&before);
+        assert_eq!(back.added, vec![((2, 1), &'d')]);
+        assert_eq!(back.removed, vec![((0, 1), &'x')]);
+        assert_eq!(back.changed, vec![((1, 0), &'B', &'b')]);
+    }
+
+    #[test]
+    fn lists_are_ordered_by_x_then_y() {
+        let before = Expanse::<char>::new();
+        let mut after = Expanse::new();
+        for &(x, y) in &[(3, -1), (-2, 5), (3, -4), (0, 0), (-2, -5)] {
+            after.write(x, y, '#');
+        }
+
+        let added: Vec<(i32, i32)> = before.diff(&after).added.iter().map(|x| x.0).collect();
+        assert_eq!(added, vec![(-2, -5), (-2, 5), (0, 0), (3, -4), (3, -1)]);
+    }
+
+    #[test]
+    fn renders_markers_over_both_ranges() {
+        let before = parse("ab.\n.cd");
+        let mut after = parse("aB.\nxc.");
+        after.write(3, -1, 'e');
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.to_ascii(false, '.', |&x| x), "...+\na~..\n+c-.");
+        assert_eq!(diff.to_ascii(true, ' ', |&x| x), "+c- \na~  \n   +");
+    }
+}
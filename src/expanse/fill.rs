@@ -0,0 +1,124 @@
+use super::{Expanse, Rect};
+use std::collections::{HashSet, VecDeque};
+
+// The passable cells of an Expanse split into orthogonally connected groups. Components are
+// numbered from 0 in the order their first cell comes in cells(), and labels holds the
+// number of the component each passable cell belongs to.
+#[derive(Debug, Clone)]
+pub struct Components {
+    pub labels: Expanse<usize>,
+    pub sizes: Vec<usize>,
+    pub bounds: Vec<Rect>,
+}
+
+impl<T> Expanse<T> {
+    // Every passable cell orthogonally reachable from the seed, in the order a breadth first
+    // fill reaches them, along with the number of steps it took. Empty if the seed itself
+    // isn't passable.
+    pub fn flood_fill<F>(&self, seed: (i32, i32), passable: F) -> Vec<((i32, i32), usize)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let mut result = Vec::new();
+
+        match self.read(seed.0, seed.1) {
+            Some(cell) if passable(cell) => {}
+            _ => return result,
+        }
+
+        let mut seen = HashSet::<(i32, i32)>::new();
+        let mut frontier = VecDeque::<((i32, i32), usize)>::new();
+        seen.insert(seed);
+        frontier.push_back((seed, 0));
+
+        while let Some(((x, y), distance)) = frontier.pop_front() {
+            result.push(((x, y), distance));
+
+            for (next, cell) in self.neighbors4(x, y) {
+                if passable(cell) && seen.insert(next) {
+                    frontier.push_back((next, distance + 1));
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn label_components<F>(&self, passable: F) -> Components
+    where
+        F: Fn(&T) -> bool,
+    {
+        let mut labels = Expanse::new();
+        let mut sizes = Vec::new();
+        let mut bounds = Vec::new();
+
+        for (seed, cell) in self.cells() {
+            if !passable(cell) || labels.read(seed.0, seed.1).is_some() {
+                continue;
+            }
+
+            let label = sizes.len();
+            let fill = self.flood_fill(seed, &passable);
+            let (mut min_x, mut max_x, mut min_y, mut max_y) = (seed.0, seed.0, seed.1, seed.1);
+
+            for &((x, y), _) in &fill {
+                labels.write(x, y, label);
+                min_x = min_x.min(x);
+                max_x = max_x.max(x);
+                min_y = min_y.min(y);
+                max_y = max_y.max(y);
+            }
+
+            sizes.push(fill.len());
+            bounds.push((min_x..max_x + 1, min_y..max_y + 1));
+        }
+
+        labels.x_range = self.x_range();
+        labels.y_range = self.y_range();
+
+        Components {
+            labels,
+            sizes,
+            bounds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Expanse<char> {
+        Expanse::from_ascii(text, false, Some)
+    }
+
+    #[test]
+    fn flood_fill_goes_breadth_first() {
+        let maze = parse("..#\n#..\n..#");
+        let fill = maze.flood_fill((0, 0), |&x| x == '.');
+
+        assert_eq!(fill.len(), 6);
+        assert_eq!(fill[0], ((0, 0), 0));
+        assert_eq!(fill.last(), Some(&((0, 2), 4)));
+        assert!(fill.windows(2).all(|x| x[0].1 <= x[1].1));
+        assert!(maze.flood_fill((2, 0), |&x| x == '.').is_empty());
+        assert!(maze.flood_fill((5, 5), |&x| x == '.').is_empty());
+    }
+
+    #[test]
+    fn labels_cover_the_same_ranges() {
+        let grid = parse("..#..\n###.#\n.#...").crop((1..5, 0..3));
+        let components = grid.label_components(|&x| x == '.');
+
+        assert_eq!(components.sizes, vec![1, 6]);
+        assert_eq!(components.bounds, vec![(1..2, 0..1), (2..5, 0..3)]);
+        assert_eq!(components.labels.x_range(), 1..5);
+        assert_eq!(components.labels.y_range(), 0..3);
+        assert_eq!(
+            components
+                .labels
+                .to_ascii(false, '#', |&x| (b'0' + x as u8) as char),
+            "0#11\n##1#\n#111"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use super::Expanse;
+use std::collections::HashMap;
+use std::ops::Range;
+
+// A sparse grid in any number of dimensions, positioned by signed coordinates. It's a plain
+// hash map rather than chunked like Expanse, since higher dimensional grids tend to be small
+// and scattered. Ranges grow with writes the same way Expanse's do.
+#[derive(Debug, Clone)]
+pub struct ExpanseN<T, const D: usize> {
+    cells: HashMap<[i32; D], T>,
+    start: [i32; D],
+    end: [i32; D],
+}
+
+impl<T, const D: usize> ExpanseN<T, D> {
+    pub fn new() -> ExpanseN<T, D> {
+        ExpanseN {
+            cells: HashMap::new(),
+            start: [0; D],
+            end: [0; D],
+        }
+    }
+
+    pub fn read(&self, pos: &[i32; D]) -> Option<&T> {
+        self.cells.get(pos)
+    }
+
+    pub fn at(&mut self, pos: &[i32; D]) -> Option<&mut T> {
+        self.cells.get_mut(pos)
+    }
+
+    pub fn write(&mut self, pos: [i32; D], item: T) {
+        for ((start, end), &coord) in self.start.iter_mut().zip(self.end.iter_mut()).zip(&pos) {
+            *start = (*start).min(coord);
+            *end = (*end).max(coord + 1);
+        }
+        self.cells.insert(pos, item);
+    }
+
+    pub fn erase(&mut self, pos: &[i32; D]) {
+        self.cells.remove(pos);
+    }
+
+    pub fn count(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn range(&self, axis: usize) -> Range<i32> {
+        self.start[axis]..self.end[axis]
+    }
+
+    // Occupied cells in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = ([i32; D], &T)> {
+        self.cells.iter().map(|(&pos, item)| (pos, item))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = ([i32; D], &mut T)> {
+        self.cells.iter_mut().map(|(&pos, item)| (pos, item))
+    }
+
+    // The 2*D positions one step along a single axis, as with neighbors4.
+    pub fn orthogonal_positions(pos: &[i32; D]) -> Vec<[i32; D]> {
+        let mut result = Vec::with_capacity(2 * D);
+        for axis in 0..D {
+            for &delta in &[1, -1] {
+                let mut next = *pos;
+                next[axis] += delta;
+                result.push(next);
+            }
+        }
+        result
+    }
+
+    // All 3^D - 1 positions touching this one, diagonals included, as with neighbors8.
+    pub fn surrounding_positions(pos: &[i32; D]) -> Vec<[i32; D]> {
+        let count = 3usize.pow(D as u32);
+        let mut result = Vec::with_capacity(count - 1);
+
+        for mut code in 0..count {
+            let mut next = *pos;
+            for coord in next.iter_mut() {
+                *coord += (code % 3) as i32 - 1;
+                code /= 3;
+            }
+            if next != *pos {
+                result.push(next);
+            }
+        }
+
+        result
+    }
+
+    pub fn orthogonal_neighbors(&self, pos: &[i32; D]) -> impl Iterator<Item = ([i32; D], &T)> {
+        Self::orthogonal_positions(pos)
+            .into_iter()
+            .filter_map(move |x| self.read(&x).map(|item| (x, item)))
+    }
+
+    pub fn surrounding_neighbors(&self, pos: &[i32; D]) -> impl Iterator<Item = ([i32; D], &T)> {
+        Self::surrounding_positions(pos)
+            .into_iter()
+            .filter_map(move |x| self.read(&x).map(|item| (x, item)))
+    }
+}
+
+impl<T, const D: usize> Default for ExpanseN<T, D> {
+    fn default() -> ExpanseN<T, D> {
+        ExpanseN::new()
+    }
+}
+
+impl<T: Clone, const D: usize> ExpanseN<T, D> {
+    // The 2D plane through `at` spanned by the two axes, with x_axis becoming x and y_axis
+    // becoming y. The coordinates of `at` along those two axes are ignored.
+    pub fn slice(&self, x_axis: usize, y_axis: usize, at: &[i32; D]) -> Expanse<T> {
+        let mut result = Expanse::new();
+
+        let in_plane = |pos: &[i32; D]| {
+            (0..D).all(|axis| axis == x_axis || axis == y_axis || pos[axis] == at[axis])
+        };
+
+        for (pos, item) in self.cells.iter() {
+            if in_plane(pos) {
+                result.write(pos[x_axis], pos[y_axis], item.clone());
+            }
+        }
+
+        result.include(self.start[x_axis], self.start[y_axis]);
+        if self.end[x_axis] > self.start[x_axis] && self.end[y_axis] > self.start[y_axis] {
+            result.include(self.end[x_axis] - 1, self.end[y_axis] - 1);
+        }
+
+        result
+    }
+}
+
+impl<T: Clone> ExpanseN<T, 2> {
+    pub fn from_expanse(expanse: &Expanse<T>) -> ExpanseN<T, 2> {
+        let mut result = ExpanseN::new();
+        for ((x, y), item) in expanse.iter() {
+            result.write([x, y], item.clone());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every position with each coordinate in -1..=1.
+    fn cube<const D: usize>() -> ExpanseN<(), D> {
+        let mut result = ExpanseN::new();
+        result.write([0; D], ());
+        for pos in ExpanseN::<(), D>::surrounding_positions(&[0; D]) {
+            result.write(pos, ());
+        }
+        result
+    }
+
+    #[test]
+    fn read_and_write_in_3d() {
+        let mut expanse = ExpanseN::<char, 3>::new();
+        assert!(expanse.empty());
+
+        expanse.write([1, -2, 3], 'a');
+        expanse.write([-4, 5, 0], 'b');
+        assert_eq!(expanse.read(&[1, -2, 3]), Some(&'a'));
+        assert_eq!(expanse.read(&[-4, 5, 0]), Some(&'b'));
+        assert_eq!(expanse.read(&[1, -2, 2]), None);
+        assert_eq!(expanse.count(), 2);
+        assert_eq!(
+            (0..3).map(|x| expanse.range(x)).collect::<Vec<_>>(),
+            vec![-4..2, -2..6, 0..4]
+        );
+
+        *expanse.at(&[1, -2, 3]).unwrap() = 'c';
+        assert_eq!(expanse.read(&[1, -2, 3]), Some(&'c'));
+
+        expanse.erase(&[1, -2, 3]);
+        assert_eq!(expanse.read(&[1, -2, 3]), None);
+        assert_eq!(expanse.count(), 1);
+        assert_eq!(expanse.range(2), 0..4);
+    }
+
+    #[test]
+    fn read_and_write_in_4d() {
+        let mut expanse = ExpanseN::<i32, 4>::default();
+        for w in -2..2 {
+            for z in 0..3 {
+                expanse.write([w, z, w * z, -w], w * 10 + z);
+            }
+        }
+
+        assert_eq!(expanse.count(), 12);
+        assert_eq!(expanse.read(&[-2, 2, -4, 2]), Some(&-18));
+        assert_eq!(expanse.read(&[1, 1, 1, 1]), None);
+        assert_eq!(expanse.range(0), -2..2);
+        assert_eq!(expanse.range(2), -4..3);
+        assert_eq!(expanse.range(3), -1..3);
+
+        for (_, item) in expanse.iter_mut() {
+            *item += 1;
+        }
+        assert_eq!(expanse.iter().map(|(_, &x)| x).sum::<i32>(), -36);
+    }
+
+    #[test]
+    fn neighbour_counts_in_3d() {
+        let positions = ExpanseN::<(), 3>::surrounding_positions(&[5, 5, 5]);
+        assert_eq!(positions.len(), 26);
+        assert!(positions.iter().all(|x| x != &[5, 5, 5]));
+        assert_eq!(ExpanseN::<(), 3>::orthogonal_positions(&[5, 5, 5]).len(), 6);
+
+        let cube = cube::<3>();
+        assert_eq!(cube.count(), 27);
+        assert_eq!(cube.orthogonal_neighbors(&[0, 0, 0]).count(), 6);
+        assert_eq!(cube.surrounding_neighbors(&[0, 0, 0]).count(), 26);
+        assert_eq!(cube.orthogonal_neighbors(&[1, 1, 1]).count(), 3);
+        assert_eq!(cube.surrounding_neighbors(&[1, 1, 1]).count(), 7);
+        assert_eq!(cube.surrounding_neighbors(&[1, 0, 0]).count(), 17);
+        assert_eq!(cube.surrounding_neighbors(&[3, 0, 0]).count(), 0);
+    }
+
+    #[test]
+    fn neighbour_counts_in_4d() {
+        assert_eq!(ExpanseN::<(), 4>::orthogonal_positions(&[0; 4]).len(), 8);
+        assert_eq!(ExpanseN::<(), 4>::surrounding_positions(&[0; 4]).len(), 80);
+
+        let cube = cube::<4>();
+        assert_eq!(cube.count(), 81);
+        assert_eq!(cube.orthogonal_neighbors(&[0; 4]).count(), 8);
+        assert_eq!(cube.surrounding_neighbors(&[0; 4]).count(), 80);
+        assert_eq!(cube.orthogonal_neighbors(&[1, -1, 1, -1]).count(), 4);
+        assert_eq!(cube.surrounding_neighbors(&[1, -1, 1, -1]).count(), 15);
+    }
+
+    #[test]
+    fn slices_pick_out_one_plane() {
+        let mut expanse = ExpanseN::<char, 3>::new();
+        expanse.write([0, 0, 0], 'a');
+        expanse.write([1, 2, 0], 'b');
+        expanse.write([1, 2, 1], 'c');
+        expanse.write([-1, 0, 1], 'd');
+
+        let z0 = expanse.slice(0, 1, &[0, 0, 0]);
+        assert_eq!(z0.count(), 2);
+        assert_eq!(z0.read(1, 2), Some(&'b'));
+        assert_eq!(z0.x_range(), -1..2);
+        assert_eq!(z0.y_range(), 0..3);
+
+        let z1 = expanse.slice(0, 1, &[7, 7, 1]);
+        assert_eq!(z1.count(), 2);
+        assert_eq!(z1.read(1, 2), Some(&'c'));
+        assert_eq!(z1.read(-1, 0), Some(&'d'));
+
+        // z across and x down, through y = 2.
+        let zx = expanse.slice(2, 0, &[0, 2, 0]);
+        assert_eq!(zx.count(), 2);
+        assert_eq!(zx.read(0, 1), Some(&'b'));
+        assert_eq!(zx.read(1, 1), Some(&'c'));
+        assert_eq!(zx.x_range(), 0..2);
+        assert_eq!(zx.y_range(), -1..2);
+    }
+
+    #[test]
+    fn slicing_a_2d_expanse_gives_it_back() {
+        let text = "#..\n.#.\n..#";
+        let flat = Expanse::from_ascii(text, false, |x| if x == '#' { Some(x) } else { None });
+        let back = ExpanseN::from_expanse(&flat).slice(0, 1, &[0, 0]);
+        assert_eq!(back.to_ascii(false, '.', |&x| x), text);
+    }
+}
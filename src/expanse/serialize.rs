@@ -0,0 +1,165 @@
+use super::{Expanse, TwoVec};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// An Expanse is stored sparsely as its occupied cells, each as (x, y, value), along with
+// its ranges so empty rows and columns at the edges survive the round trip.
+#[derive(Serialize)]
+struct SparseRef<'a, T> {
+    x_range: (i32, i32),
+    y_range: (i32, i32),
+    cells: Vec<(i32, i32, &'a T)>,
+}
+
+#[derive(Deserialize)]
+struct Sparse<T> {
+    x_range: (i32, i32),
+    y_range: (i32, i32),
+    cells: Vec<(i32, i32, T)>,
+}
+
+// A TwoVec is stored as the index of its first item followed by all the items in order.
+#[derive(Serialize)]
+struct DenseRef<'a, T> {
+    start: i32,
+    items: Vec<&'a T>,
+}
+
+#[derive(Deserialize)]
+struct Dense<T> {
+    start: i32,
+    items: Vec<T>,
+}
+
+impl<T: Serialize> Serialize for Expanse<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SparseRef {
+            x_range: (self.x_range.start, self.x_range.end),
+            y_range: (self.y_range.start, self.y_range.end),
+            cells: self.cells().map(|((x, y), item)| (x, y, item)).collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Expanse<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sparse = Sparse::<T>::deserialize(deserializer)?;
+
+        let mut result = Expanse::new();
+        result.x_range = sparse.x_range.0..sparse.x_range.1;
+        result.y_range = sparse.y_range.0..sparse.y_range.1;
+        result.extend(sparse.cells.into_iter().map(|(x, y, item)| ((x, y), item)));
+
+        Ok(result)
+    }
+}
+
+impl<T: Serialize> Serialize for TwoVec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DenseRef {
+            start: self.start,
+            items: self.items.iter().collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for TwoVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let dense = Dense::<T>::deserialize(deserializer)?;
+
+        Ok(TwoVec {
+            items: dense.items.into(),
+            start: dense.start,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Debug;
+    use std::ops::Range;
+
+    type Snapshot<T> = (Range<i32>, Range<i32>, Vec<((i32, i32), T)>);
+
+    fn snapshot<T: Clone>(expanse: &Expanse<T>) -> Snapshot<T> {
+        let cells = expanse.cells().map(|(pos, x)| (pos, x.clone())).collect();
+        (expanse.x_range(), expanse.y_range(), cells)
+    }
+
+    fn assert_round_trips<T>(expanse: &Expanse<T>)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + Clone + PartialEq + Debug,
+    {
+        let json = serde_json::to_string(expanse).unwrap();
+        let from_json: Expanse<T> = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot(&from_json), snapshot(expanse));
+
+        let binary = bincode::serialize(expanse).unwrap();
+        let from_binary: Expanse<T> = bincode::deserialize(&binary).unwrap();
+        assert_eq!(snapshot(&from_binary), snapshot(expanse));
+    }
+
+    #[test]
+    fn sparse_expanse_round_trips() {
+        let mut expanse = Expanse::new();
+        expanse.write(0, 0, String::from("origin"));
+        expanse.write(1000, 3, String::from("far"));
+        expanse.write(17, -40, String::from("apart"));
+
+        assert_round_trips(&expanse);
+        assert_round_trips(&Expanse::<u8>::new());
+    }
+
+    #[test]
+    fn negative_coordinates_round_trip() {
+        let mut expanse = Expanse::new();
+        expanse.write(-1, -1, 1u8);
+        expanse.write(-33, 20, 2);
+        expanse.write(-16, -17, 3);
+
+        assert_round_trips(&expanse);
+        assert_round_trips(&expanse.clone().translate(-500, -500));
+    }
+
+    #[test]
+    fn empty_edges_round_trip() {
+        let expanse = Expanse::from_ascii("....\n.#..\n....", true, |x| {
+            if x == '#' {
+                Some(())
+            } else {
+                None
+            }
+        });
+
+        assert_round_trips(&expanse);
+        assert_eq!(
+            serde_json::to_string(&expanse).unwrap(),
+            r#"{"x_range":[0,4],"y_range":[0,3],"cells":[[1,1,null]]}"#
+        );
+    }
+
+    #[test]
+    fn two_vec_round_trips() {
+        let mut two_vec = TwoVec::new();
+        for i in 0..5 {
+            two_vec.push_back(i * 10);
+            two_vec.push_front(-i);
+        }
+
+        let json = serde_json::to_string(&two_vec).unwrap();
+        let from_json: TwoVec<i32> = serde_json::from_str(&json).unwrap();
+        let binary = bincode::serialize(&two_vec).unwrap();
+        let from_binary: TwoVec<i32> = bincode::deserialize(&binary).unwrap();
+
+        let expected: Vec<(i32, i32)> = two_vec.iter().map(|(i, &x)| (i, x)).collect();
+        for decoded in &[from_json, from_binary] {
+            assert_eq!(decoded.index_range(), two_vec.index_range());
+            assert_eq!(
+                decoded.iter().map(|(i, &x)| (i, x)).collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use super::{render_grid, Expanse, Rect};
+use std::ops::Range;
+
+// A rectangle of an Expanse, borrowed rather than copied out, for looking at one part of a
+// big grid. Positions keep the coordinates they have in the Expanse.
+#[derive(Debug)]
+pub struct ExpanseView<'a, T> {
+    expanse: &'a Expanse<T>,
+    x_range: Range<i32>,
+    y_range: Range<i32>,
+}
+
+impl<'a, T> Clone for ExpanseView<'a, T> {
+    fn clone(&self) -> ExpanseView<'a, T> {
+        ExpanseView {
+            expanse: self.expanse,
+            x_range: self.x_range.clone(),
+            y_range: self.y_range.clone(),
+        }
+    }
+}
+
+impl<T> Expanse<T> {
+    pub fn view(&self, (x_range, y_range): Rect) -> ExpanseView<'_, T> {
+        ExpanseView {
+            expanse: self,
+            x_range,
+            y_range,
+        }
+    }
+}
+
+impl<'a, T> ExpanseView<'a, T> {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.x_range.contains(&x) && self.y_range.contains(&y)
+    }
+
+    // Nothing outside the rectangle is visible, even where the Expanse has something.
+    pub fn read(&self, x: i32, y: i32) -> Option<&'a T> {
+        if self.contains(x, y) {
+            self.expanse.read(x, y)
+        } else {
+            None
+        }
+    }
+
+    pub fn x_range(&self) -> Range<i32> {
+        self.x_range.clone()
+    }
+
+    pub fn y_range(&self) -> Range<i32> {
+        self.y_range.clone()
+    }
+
+    // Occupied cells ordered by x and then y, as with Expanse::cells. This reads every
+    // position in the rectangle, so it's for views smaller than the whole Expanse.
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), &'a T)> + '_ {
+        let expanse = self.expanse;
+        self.x_range().flat_map(move |x| {
+            self.y_range()
+                .filter_map(move |y| expanse.read(x, y).map(|item| ((x, y), item)))
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn render_to_string<F>(&self, y_increases_up: bool, empty: &str, f: F) -> String
+    where
+        F: Fn(&T) -> String,
+    {
+        render_grid(
+            self.x_range(),
+            self.y_range(),
+            y_increases_up,
+            |x, y| match self.read(x, y) {
+                Some(x) => f(x),
+                None => empty.to_string(),
+            },
+        )
+    }
+
+    pub fn to_ascii<F>(&self, y_increases_up: bool, empty: char, f: F) -> String
+    where
+        F: Fn(&T) -> char,
+    {
+        let mut result =
+            self.render_to_string(y_increases_up, &empty.to_string(), |x| f(x).to_string());
+        result.pop();
+        result
+    }
+}
+
+impl<'a, T: Clone> ExpanseView<'a, T> {
+    // Copies the visible cells out, with the ranges of the view.
+    pub fn to_expanse(&self) -> Expanse<T> {
+        let mut result = Expanse::new();
+        for ((x, y), item) in self.iter() {
+            result.write(x, y, item.clone());
+        }
+
+        result.x_range = self.x_range();
+        result.y_range = self.y_range();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> Expanse<char> {
+        let text = "abcd\nefgh\nijkl";
+        Expanse::from_ascii(text, false, |x| if x == 'f' { None } else { Some(x) })
+    }
+
+    #[test]
+    fn reads_stop_at_the_bounds() {
+        let grid = grid();
+        let view = grid.view((1..3, 0..2));
+
+        assert_eq!(view.x_range(), 1..3);
+        assert_eq!(view.y_range(), 0..2);
+        assert!(view.contains(1, 0));
+        assert!(view.contains(2, 1));
+        assert!(!view.contains(3, 1));
+        assert!(!view.contains(1, 2));
+        assert!(!view.contains(0, 0));
+
+        assert_eq!(view.read(2, 1), Some(&'g'));
+        assert_eq!(view.read(1, 1), None);
+        assert_eq!(view.read(0, 0), None);
+        assert_eq!(view.read(3, 2), None);
+        assert_eq!(grid.read(3, 2), Some(&'l'));
+    }
+
+    #[test]
+    fn iterates_the_visible_cells_in_order() {
+        let grid = grid();
+        let view = grid.view((1..3, 0..3));
+
+        let cells: Vec<((i32, i32), char)> = view.iter().map(|(pos, &x)| (pos, x)).collect();
+        assert_eq!(
+            cells,
+            vec![
+                ((1, 0), 'b'),
+                ((1, 2), 'j'),
+                ((2, 0), 'c'),
+                ((2, 1), 'g'),
+                ((2, 2), 'k'),
+            ]
+        );
+        assert_eq!(view.count(), 5);
+    }
+
+    #[test]
+    fn views_can_reach_past_the_expanse() {
+        let grid = grid();
+        let view = grid.view((2..6, -1..1));
+
+        assert_eq!(view.count(), 2);
+        assert_eq!(view.to_ascii(false, '.', |&x| x), "....\ncd..");
+        assert_eq!(view.to_ascii(true, '.', |&x| x), "cd..\n....");
+    }
+
+    #[test]
+    fn renders_only_the_rectangle() {
+        let grid = grid();
+        let view = grid.view((0..2, 1..3));
+
+        let doubled = |x: &char| x.to_ascii_uppercase().to_string().repeat(2);
+        assert_eq!(view.to_ascii(false, '.', |&x| x), "e.\nij");
+        assert_eq!(view.render_to_string(false, "__", doubled), "EE__\nIIJJ\n");
+        assert_eq!(grid.view((1..1, 0..3)).to_ascii(false, '.', |&x| x), "\n\n");
+    }
+
+    #[test]
+    fn copies_out_with_the_view_ranges() {
+        let grid = grid();
+        let copy = grid.view((1..5, 1..3)).to_expanse();
+
+        assert_eq!(copy.x_range(), 1..5);
+        assert_eq!(copy.y_range(), 1..3);
+        assert_eq!(copy.count(), 5);
+        assert_eq!(copy.to_ascii(false, '.', |&x| x), ".gh.\njkl.");
+    }
+}
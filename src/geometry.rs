@@ -0,0 +1,345 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+// Points on grids and in space, and compass directions for walking between them. Whether
+// north is +y or -y depends on the puzzle, so anything turning a direction into an offset
+// takes y_increases_up, the same as Expanse's ASCII and rendering functions.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Point2 {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Point3 {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Point2 {
+    pub const ZERO: Point2 = Point2 { x: 0, y: 0 };
+
+    pub fn new(x: i32, y: i32) -> Point2 {
+        Point2 { x, y }
+    }
+
+    pub fn manhattan(self, other: Point2) -> i32 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    pub fn signum(self) -> Point2 {
+        Point2::new(self.x.signum(), self.y.signum())
+    }
+}
+
+impl Point3 {
+    pub const ZERO: Point3 = Point3 { x: 0, y: 0, z: 0 };
+
+    pub fn new(x: i32, y: i32, z: i32) -> Point3 {
+        Point3 { x, y, z }
+    }
+
+    pub fn manhattan(self, other: Point3) -> i32 {
+        (self.x - other.x).abs() + (self.y - other.y).abs() + (self.z - other.z).abs()
+    }
+
+    pub fn signum(self) -> Point3 {
+        Point3::new(self.x.signum(), self.y.signum(), self.z.signum())
+    }
+}
+
+impl From<(i32, i32)> for Point2 {
+    fn from((x, y): (i32, i32)) -> Point2 {
+        Point2 { x, y }
+    }
+}
+
+impl From<Point2> for (i32, i32) {
+    fn from(p: Point2) -> (i32, i32) {
+        (p.x, p.y)
+    }
+}
+
+impl From<[i32; 3]> for Point3 {
+    fn from([x, y, z]: [i32; 3]) -> Point3 {
+        Point3 { x, y, z }
+    }
+}
+
+impl From<Point3> for [i32; 3] {
+    fn from(p: Point3) -> [i32; 3] {
+        [p.x, p.y, p.z]
+    }
+}
+
+macro_rules! impl_point_ops {
+    ($point:ident, $($field:ident),+) => {
+        impl Add for $point {
+            type Output = $point;
+            fn add(self, rhs: $point) -> $point {
+                $point { $($field: self.$field + rhs.$field),+ }
+            }
+        }
+
+        impl Sub for $point {
+            type Output = $point;
+            fn sub(self, rhs: $point) -> $point {
+                $point { $($field: self.$field - rhs.$field),+ }
+            }
+        }
+
+        impl Mul<i32> for $point {
+            type Output = $point;
+            fn mul(self, rhs: i32) -> $point {
+                $point { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        impl Neg for $point {
+            type Output = $point;
+            fn neg(self) -> $point {
+                $point { $($field: -self.$field),+ }
+            }
+        }
+
+        impl AddAssign for $point {
+            fn add_assign(&mut self, rhs: $point) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl SubAssign for $point {
+            fn sub_assign(&mut self, rhs: $point) {
+                *self = *self - rhs;
+            }
+        }
+    };
+}
+
+impl_point_ops!(Point2, x, y);
+impl_point_ops!(Point3, x, y, z);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Turn {
+    Left,
+    Right,
+    Around,
+}
+
+impl Direction {
+    // Clockwise from north.
+    pub const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    fn from_index(i: usize) -> Direction {
+        Direction::ALL[i % 4]
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn turn(self, turn: Turn) -> Direction {
+        match turn {
+            Turn::Left => self.turn_left(),
+            Turn::Right => self.turn_right(),
+            Turn::Around => self.turn_around(),
+        }
+    }
+
+    pub fn turn_left(self) -> Direction {
+        Direction::from_index(self.index() + 3)
+    }
+
+    pub fn turn_right(self) -> Direction {
+        Direction::from_index(self.index() + 1)
+    }
+
+    pub fn turn_around(self) -> Direction {
+        Direction::from_index(self.index() + 2)
+    }
+
+    pub fn offset(self, y_increases_up: bool) -> Point2 {
+        let north = if y_increases_up { 1 } else { -1 };
+        match self {
+            Direction::North => Point2::new(0, north),
+            Direction::East => Point2::new(1, 0),
+            Direction::South => Point2::new(0, -north),
+            Direction::West => Point2::new(-1, 0),
+        }
+    }
+
+    pub fn step(self, from: Point2, y_increases_up: bool) -> Point2 {
+        from + self.offset(y_increases_up)
+    }
+
+    pub fn from_offset(offset: Point2, y_increases_up: bool) -> Option<Direction> {
+        Direction::ALL
+            .iter()
+            .copied()
+            .find(|x| x.offset(y_increases_up) == offset)
+    }
+
+    // The movement commands of the day 15 repair droid: north 1, south 2, west 3, east 4.
+    pub fn to_movement_command(self) -> i64 {
+        match self {
+            Direction::North => 1,
+            Direction::South => 2,
+            Direction::West => 3,
+            Direction::East => 4,
+        }
+    }
+
+    pub fn from_movement_command(command: i64) -> Option<Direction> {
+        match command {
+            1 => Some(Direction::North),
+            2 => Some(Direction::South),
+            3 => Some(Direction::West),
+            4 => Some(Direction::East),
+            _ => None,
+        }
+    }
+
+    // How the day 17 camera draws the vacuum robot facing each way.
+    pub fn to_robot_char(self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<',
+        }
+    }
+
+    pub fn from_robot_char(c: char) -> Option<Direction> {
+        match c {
+            '^' => Some(Direction::North),
+            '>' => Some(Direction::East),
+            'v' => Some(Direction::South),
+            '<' => Some(Direction::West),
+            _ => None,
+        }
+    }
+}
+
+impl Turn {
+    // The turn instructions of the day 11 painting robot: left 0, right 1.
+    pub fn from_paint_command(command: i64) -> Option<Turn> {
+        match command {
+            0 => Some(Turn::Left),
+            1 => Some(Turn::Right),
+            _ => None,
+        }
+    }
+
+    pub fn to_paint_command(self) -> Option<i64> {
+        match self {
+            Turn::Left => Some(0),
+            Turn::Right => Some(1),
+            Turn::Around => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Direction::*;
+
+    #[test]
+    fn turns() {
+        // Facing, left, right, around.
+        let table = [
+            (North, West, East, South),
+            (East, North, South, West),
+            (South, East, West, North),
+            (West, South, North, East),
+        ];
+
+        for &(facing, left, right, around) in &table {
+            assert_eq!(facing.turn_left(), left);
+            assert_eq!(facing.turn_right(), right);
+            assert_eq!(facing.turn_around(), around);
+            assert_eq!(facing.turn(Turn::Left), left);
+            assert_eq!(facing.turn(Turn::Right), right);
+            assert_eq!(facing.turn(Turn::Around), around);
+            assert_eq!(facing.turn_left().turn_right(), facing);
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        // Direction, offset with y increasing up, offset with y increasing down.
+        let table = [
+            (North, (0, 1), (0, -1)),
+            (East, (1, 0), (1, 0)),
+            (South, (0, -1), (0, 1)),
+            (West, (-1, 0), (-1, 0)),
+        ];
+
+        for &(direction, up, down) in &table {
+            assert_eq!(direction.offset(true), up.into());
+            assert_eq!(direction.offset(false), down.into());
+            assert_eq!(Direction::from_offset(up.into(), true), Some(direction));
+            assert_eq!(Direction::from_offset(down.into(), false), Some(direction));
+            assert_eq!(
+                direction.step(Point2::new(5, 5), false),
+                Point2::new(5 + down.0, 5 + down.1)
+            );
+        }
+
+        assert_eq!(Direction::from_offset(Point2::new(1, 1), true), None);
+        assert_eq!(Direction::from_offset(Point2::new(0, 0), false), None);
+        assert_eq!(Direction::from_offset(Point2::new(0, 2), true), None);
+    }
+
+    #[test]
+    fn movement_commands() {
+        let table = [(North, 1), (South, 2), (West, 3), (East, 4)];
+
+        for &(direction, command) in &table {
+            assert_eq!(direction.to_movement_command(), command);
+            assert_eq!(Direction::from_movement_command(command), Some(direction));
+        }
+        for &command in &[0, 5, -1] {
+            assert_eq!(Direction::from_movement_command(command), None);
+        }
+    }
+
+    #[test]
+    fn robot_chars() {
+        let table = [(North, '^'), (East, '>'), (South, 'v'), (West, '<')];
+
+        for &(direction, c) in &table {
+            assert_eq!(direction.to_robot_char(), c);
+            assert_eq!(Direction::from_robot_char(c), Some(direction));
+        }
+        for &c in &['X', '#', 'V', '.'] {
+            assert_eq!(Direction::from_robot_char(c), None);
+        }
+    }
+
+    #[test]
+    fn paint_commands() {
+        assert_eq!(Turn::from_paint_command(0), Some(Turn::Left));
+        assert_eq!(Turn::from_paint_command(1), Some(Turn::Right));
+        assert_eq!(Turn::from_paint_command(2), None);
+        for &turn in &[Turn::Left, Turn::Right] {
+            let command = turn.to_paint_command().unwrap();
+            assert_eq!(Turn::from_paint_command(command), Some(turn));
+        }
+        assert_eq!(Turn::Around.to_paint_command(), None);
+    }
+}
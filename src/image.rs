@@ -0,0 +1,277 @@
+use std::io;
+use std::path::Path;
+
+pub type Color = [u8; 3];
+
+pub const BLACK: Color = [0, 0, 0];
+pub const WHITE: Color = [255, 255, 255];
+
+// Distinct colors for cells which are one of a few kinds, like an enum's variants in order.
+pub const PALETTE: [Color; 8] = [
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+    [128, 128, 128],
+];
+
+// An RGB image drawn from a grid, where every cell covers a square of cell_size pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, cell_size: usize, fill: Color) -> Image {
+        Image {
+            width,
+            height,
+            cell_size,
+            pixels: vec![fill; width * height],
+        }
+    }
+
+    pub fn fill_cell(&mut self, column: usize, row: usize, color: Color) {
+        for y in row * self.cell_size..(row + 1) * self.cell_size {
+            let start = y * self.width + column * self.cell_size;
+            for pixel in &mut self.pixels[start..start + self.cell_size] {
+                *pixel = color;
+            }
+        }
+    }
+
+    // Draws a one pixel line along the top and left edge of every cell, and closes the
+    // bottom and right of the image.
+    pub fn with_grid_lines(mut self, color: Color) -> Image {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if x % self.cell_size == 0
+                    || y % self.cell_size == 0
+                    || x == self.width - 1
+                    || y == self.height - 1
+                {
+                    self.pixels[y * self.width + x] = color;
+                }
+            }
+        }
+        self
+    }
+
+    // Binary PPM, which almost anything can open.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut result = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in &self.pixels {
+            result.extend_from_slice(pixel);
+        }
+        result
+    }
+
+    // The image data goes in uncompressed deflate blocks. The files are larger than they could
+    // be, but it keeps the encoder small and these images are never big.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut header = Vec::<u8>::new();
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut scanlines = Vec::<u8>::with_capacity((self.width * 3 + 1) * self.height);
+        for row in self.pixels.chunks(self.width.max(1)).take(self.height) {
+            scanlines.push(0);
+            for pixel in row {
+                scanlines.extend_from_slice(pixel);
+            }
+        }
+
+        let mut result = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        write_png_chunk(&mut result, b"IHDR", &header);
+        write_png_chunk(&mut result, b"IDAT", &zlib_stored(&scanlines));
+        write_png_chunk(&mut result, b"IEND", &[]);
+        result
+    }
+
+    // Picks the format from the extension, PNG unless it's .ppm.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let bytes = match path.extension().and_then(|x| x.to_str()) {
+            Some("ppm") => self.to_ppm(),
+            _ => self.to_png(),
+        };
+        std::fs::write(path, bytes)
+    }
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut result = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+
+    if blocks.peek().is_none() {
+        result.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        result.push(if last { 1 } else { 0 });
+        result.extend_from_slice(&length.to_le_bytes());
+        result.extend_from_slice(&(!length).to_le_bytes());
+        result.extend_from_slice(block);
+    }
+
+    result.extend_from_slice(&adler32(data).to_be_bytes());
+    result
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expanse::Expanse;
+
+    const RED: Color = [255, 0, 0];
+
+    // Three columns and two rows, with 2x2 pixel cells.
+    fn known_image(y_increases_up: bool) -> Image {
+        let expanse = Expanse::from_ascii("#..\n.##", false, |x| Some(x == '#'));
+        expanse.to_image(y_increases_up, 2, |x| match x {
+            Some(true) => RED,
+            _ => WHITE,
+        })
+    }
+
+    fn pixel_rows(image: &Image) -> Vec<String> {
+        image
+            .pixels
+            .chunks(image.width)
+            .map(|row| {
+                row.iter()
+                    .map(|&x| if x == RED { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The chunks of a PNG after its signature, as (kind, data) pairs, checking each CRC.
+    fn png_chunks(bytes: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut result = Vec::new();
+        let mut rest = &bytes[8..];
+
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let body = &rest[4..8 + length];
+            let crc = &rest[8 + length..12 + length];
+            assert_eq!(crc, &crc32(body).to_be_bytes()[..]);
+
+            result.push((
+                String::from_utf8(body[..4].to_vec()).unwrap(),
+                body[4..].to_vec(),
+            ));
+            rest = &rest[12 + length..];
+        }
+
+        result
+    }
+
+    #[test]
+    fn cells_become_squares_of_pixels() {
+        let image = known_image(false);
+        assert_eq!((image.width, image.height, image.cell_size), (6, 4, 2));
+        assert_eq!(
+            pixel_rows(&image),
+            vec!["##....", "##....", "..####", "..####"]
+        );
+
+        let flipped = known_image(true);
+        assert_eq!(
+            pixel_rows(&flipped),
+            vec!["..####", "..####", "##....", "##...."]
+        );
+    }
+
+    #[test]
+    fn ppm_has_header_then_raw_pixels() {
+        let ppm = known_image(false).to_ppm();
+        let header = b"P6\n6 4\n255\n";
+
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 6 * 4 * 3);
+        assert_eq!(
+            &ppm[header.len()..header.len() + 6],
+            &[255, 0, 0, 255, 0, 0]
+        );
+        assert_eq!(&ppm[header.len() + 6..header.len() + 9], &WHITE);
+        assert_eq!(&ppm[ppm.len() - 3..], &RED);
+    }
+
+    #[test]
+    fn png_holds_the_same_pixels() {
+        let image = known_image(false);
+        let png = image.to_png();
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+
+        let chunks = png_chunks(&png);
+        let kinds: Vec<&str> = chunks.iter().map(|x| x.0.as_str()).collect();
+        assert_eq!(kinds, vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(chunks[0].1, vec![0, 0, 0, 6, 0, 0, 0, 4, 8, 2, 0, 0, 0]);
+        assert!(chunks[2].1.is_empty());
+
+        // One stored block: zlib header, final block flag, then the length and its complement.
+        let idat = &chunks[1].1;
+        let scanlines: Vec<u8> = image
+            .pixels
+            .chunks(image.width)
+            .flat_map(|row| std::iter::once(0).chain(row.iter().flatten().copied()))
+            .collect();
+        let length = scanlines.len() as u16;
+        assert_eq!(&idat[..3], &[0x78, 0x01, 1]);
+        assert_eq!(&idat[3..5], &length.to_le_bytes());
+        assert_eq!(&idat[5..7], &(!length).to_le_bytes());
+        assert_eq!(&idat[7..idat.len() - 4], &scanlines[..]);
+        assert_eq!(&idat[idat.len() - 4..], &adler32(&scanlines).to_be_bytes());
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod compiler;
+
 pub mod defs {
     pub const I_HALT: i64 = 99;
     pub const I_ADD: i64 = 01;
@@ -46,7 +48,27 @@ pub mod vm {
         }
 
         pub fn poke(&mut self, addr: usize, val: i64) {
-            self.tape[addr] = val;
+            self.write_to_tape(addr, val);
+        }
+
+        pub fn ip(&self) -> usize {
+            self.ip
+        }
+
+        pub fn relative_base(&self) -> i64 {
+            self.relative_base
+        }
+
+        pub fn peek(&self, addr: usize) -> i64 {
+            self.read_from_tape(addr)
+        }
+
+        pub fn set_ip(&mut self, ip: usize) {
+            self.ip = ip;
+        }
+
+        pub fn set_relative_base(&mut self, relative_base: i64) {
+            self.relative_base = relative_base;
         }
 
         pub fn run(&mut self) -> RunResult {
@@ -55,92 +77,100 @@ pub mod vm {
             }
 
             loop {
-                match self.tape[self.ip] % 100 {
-                    I_HALT => {
-                        self.last_result = Some(RunResult::Halted);
-                        break;
-                    }
+                if let Some(result) = self.step() {
+                    return result;
+                }
+            }
+        }
 
-                    I_ADD => {
-                        let arg0 = self.get_arg(0);
-                        let arg1 = self.get_arg(1);
-                        let arg2 = self.get_out_arg(2);
-                        self.write_to_tape(arg2 as usize, arg0 + arg1);
-                        self.ip += 4
-                    }
+        // Executes a single instruction, returning a result only if it was one which
+        // would have made run() stop.
+        pub fn step(&mut self) -> Option<RunResult> {
+            match self.tape[self.ip] % 100 {
+                I_HALT => {
+                    self.last_result = Some(RunResult::Halted);
+                    return self.last_result;
+                }
 
-                    I_MUL => {
-                        let arg0 = self.get_arg(0);
-                        let arg1 = self.get_arg(1);
-                        let arg2 = self.get_out_arg(2);
-                        self.write_to_tape(arg2 as usize, arg0 * arg1);
-                        self.ip += 4
-                    }
+                I_ADD => {
+                    let arg0 = self.get_arg(0);
+                    let arg1 = self.get_arg(1);
+                    let arg2 = self.get_out_arg(2);
+                    self.write_to_tape(arg2 as usize, arg0 + arg1);
+                    self.ip += 4
+                }
 
-                    I_IN => {
-                        self.input_address = self.get_out_arg(0);
-                        self.last_result = Some(RunResult::RequiresInput);
-                        self.ip += 2;
-                        break;
-                    }
+                I_MUL => {
+                    let arg0 = self.get_arg(0);
+                    let arg1 = self.get_arg(1);
+                    let arg2 = self.get_out_arg(2);
+                    self.write_to_tape(arg2 as usize, arg0 * arg1);
+                    self.ip += 4
+                }
 
-                    I_OUT => {
-                        let arg0 = self.get_arg(0);
-                        self.last_result = Some(RunResult::ProvidingOutput(arg0));
-                        self.ip += 2;
-                        break;
-                    }
+                I_IN => {
+                    self.input_address = self.get_out_arg(0);
+                    self.last_result = Some(RunResult::RequiresInput);
+                    self.ip += 2;
+                    return self.last_result;
+                }
 
-                    I_JNZ => {
-                        let arg0 = self.get_arg(0);
-                        let arg1 = self.get_arg(1);
-                        if arg0 != 0 {
-                            self.ip = arg1 as usize
-                        } else {
-                            self.ip += 3
-                        }
-                    }
+                I_OUT => {
+                    let arg0 = self.get_arg(0);
+                    self.last_result = Some(RunResult::ProvidingOutput(arg0));
+                    self.ip += 2;
+                    return self.last_result;
+                }
 
-                    I_JZ => {
-                        let arg0 = self.get_arg(0);
-                        let arg1 = self.get_arg(1);
-                        if arg0 == 0 {
-                            self.ip = arg1 as usize
-                        } else {
-                            self.ip += 3
-                        }
+                I_JNZ => {
+                    let arg0 = self.get_arg(0);
+                    let arg1 = self.get_arg(1);
+                    if arg0 != 0 {
+                        self.ip = arg1 as usize
+                    } else {
+                        self.ip += 3
                     }
+                }
 
-                    I_LESS => {
-                        let arg0 = self.get_arg(0);
-                        let arg1 = self.get_arg(1);
-                        let arg2 = self.get_out_arg(2);
-                        self.write_to_tape(arg2 as usize, if arg0 < arg1 { 1 } else { 0 });
-                        self.ip += 4
+                I_JZ => {
+                    let arg0 = self.get_arg(0);
+                    let arg1 = self.get_arg(1);
+                    if arg0 == 0 {
+                        self.ip = arg1 as usize
+                    } else {
+                        self.ip += 3
                     }
+                }
 
-                    I_CMP => {
-                        let arg0 = self.get_arg(0);
-                        let arg1 = self.get_arg(1);
-                        let arg2 = self.get_out_arg(2);
-                        self.write_to_tape(arg2 as usize, if arg0 == arg1 { 1 } else { 0 });
-                        self.ip += 4
-                    }
+                I_LESS => {
+                    let arg0 = self.get_arg(0);
+                    let arg1 = self.get_arg(1);
+                    let arg2 = self.get_out_arg(2);
+                    self.write_to_tape(arg2 as usize, if arg0 < arg1 { 1 } else { 0 });
+                    self.ip += 4
+                }
 
-                    I_RBA => {
-                        let arg0 = self.get_arg(0);
-                        self.relative_base += arg0;
-                        self.ip += 2
-                    }
+                I_CMP => {
+                    let arg0 = self.get_arg(0);
+                    let arg1 = self.get_arg(1);
+                    let arg2 = self.get_out_arg(2);
+                    self.write_to_tape(arg2 as usize, if arg0 == arg1 { 1 } else { 0 });
+                    self.ip += 4
+                }
 
-                    _ => panic!(
-                        "ABORTED: Encountered unknown opcode {} at location {}",
-                        self.tape[self.ip], self.ip
-                    ),
+                I_RBA => {
+                    let arg0 = self.get_arg(0);
+                    self.relative_base += arg0;
+                    self.ip += 2
                 }
+
+                _ => panic!(
+                    "ABORTED: Encountered unknown opcode {} at location {}",
+                    self.tape[self.ip], self.ip
+                ),
             }
 
-            self.last_result.unwrap()
+            None
         }
 
         fn write_to_tape(&mut self, address: usize, value: i64) {
@@ -230,7 +260,8 @@ pub mod vm {
 
 pub mod assembler {
     use crate::intcode::defs::*;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
+    use std::fmt;
 
     #[derive(Debug)]
     struct InstructionDef {
@@ -248,6 +279,64 @@ pub mod assembler {
         pub internal_labels: Vec<(String, u64)>,
     }
 
+    #[derive(Debug)]
+    enum SourceItem {
+        Label(String, usize),
+        Instruction(ParsedInstruction, usize),
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct AssembleError {
+        pub line: usize,
+        pub message: String,
+    }
+
+    impl fmt::Display for AssembleError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct ListingEntry {
+        pub address: usize,
+        pub words: Vec<i64>,
+        pub text: String,
+        pub line: usize,
+        pub data: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LabelSite {
+        pub name: String,
+        pub line: usize,
+        pub column: usize,
+        pub definition: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TestCase {
+        pub line: usize,
+        pub inputs: Vec<i64>,
+        pub expect: Vec<i64>,
+        // Overrides how many instructions the case may run before it counts as failed.
+        pub steps: Option<usize>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Program {
+        pub tape: Vec<i64>,
+        pub symbols: HashMap<String, i64>,
+        pub listing: Vec<ListingEntry>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone)]
+    enum Peephole {
+        Keep,
+        Remove,
+        Replace(String),
+    }
+
     #[derive(PartialEq, Eq, Debug, Clone, Copy)]
     enum AddressMode {
         Pointer,
@@ -255,7 +344,7 @@ pub mod assembler {
         Relative,
     }
 
-    const INSTRUCTIONS: [InstructionDef; 12] = [
+    const INSTRUCTIONS: [InstructionDef; 13] = [
         InstructionDef {
             name: "halt",
             opcode: I_HALT,
@@ -328,17 +417,49 @@ pub mod assembler {
             inargs: 0,
             outargs: 0,
         },
+        InstructionDef {
+            name: "ascii",
+            opcode: -1,
+            inargs: 0,
+            outargs: 0,
+        },
+    ];
+
+    const PSEUDO_INSTRUCTIONS: [(&str, usize); 13] = [
+        ("push", 1),
+        ("pop", 1),
+        ("call", 1),
+        ("ret", 0),
+        ("enter", 1),
+        ("leave", 0),
+        ("mov", 2),
+        ("jmp", 1),
+        ("not", 2),
+        ("neg", 2),
+        ("inc", 1),
+        ("dec", 1),
+        ("sub", 3),
     ];
 
-    fn parse_label(labels: &HashMap<String, i64>, arg: &str) -> (i64, AddressMode) {
+    fn lookup_label(labels: &HashMap<String, i64>, name: &str) -> Result<i64, String> {
+        match labels.get(name) {
+            Some(x) => Ok(*x),
+            None => Err(format!("undefined label '{}'", name)),
+        }
+    }
+
+    fn parse_label(labels: &HashMap<String, i64>, arg: &str) -> Result<(i64, AddressMode), String> {
         if arg.starts_with("$") {
-            (0, AddressMode::Pointer)
-        } else if arg.starts_with("&") {
-            (labels[&arg[1..]], AddressMode::Immediate)
-        } else if arg.starts_with("^") {
-            (arg[1..].parse::<i64>().unwrap(), AddressMode::Relative)
+            Ok((0, AddressMode::Pointer))
+        } else if let Some(name) = arg.strip_prefix("&") {
+            Ok((lookup_label(labels, name)?, AddressMode::Immediate))
+        } else if let Some(offset) = arg.strip_prefix("^") {
+            match offset.parse::<i64>() {
+                Ok(x) => Ok((x, AddressMode::Relative)),
+                Err(_) => Err(format!("bad relative offset '{}'", arg)),
+            }
         } else {
-            (labels[arg], AddressMode::Pointer)
+            Ok((lookup_label(labels, arg)?, AddressMode::Pointer))
         }
     }
 
@@ -353,22 +474,25 @@ pub mod assembler {
     fn assemble_parsed_instruction(
         labels: &HashMap<String, i64>,
         parsed: &ParsedInstruction,
-    ) -> Vec<i64> {
+    ) -> Result<Vec<i64>, String> {
         if parsed.def.opcode < 0 {
+            if parsed.def.name == "ascii" {
+                return Ok(parsed.words[1..]
+                    .iter()
+                    .map(|x| x.parse::<i64>().unwrap())
+                    .collect());
+            }
+
             let arg = &parsed.words[1];
             let arg_val = match arg.parse::<i64>() {
                 Ok(x) => x,
-                Err(_) => {
-                    let (arg_val, _) = parse_label(labels, arg);
-                    arg_val
-                }
+                Err(_) => parse_label(labels, arg)?.0,
             };
 
-            return match parsed.def.name {
+            return Ok(match parsed.def.name {
                 "dd" => vec![arg_val],
-                "fill" => vec![arg_val; parsed.size as usize],
-                _ => panic!(),
-            };
+                _ => vec![arg_val; parsed.size as usize],
+            });
         }
 
         let mut result = Vec::<i64>::new();
@@ -384,7 +508,7 @@ pub mod assembler {
                     x
                 }
                 Err(_) => {
-                    let (arg_val, mode) = parse_label(labels, arg);
+                    let (arg_val, mode) = parse_label(labels, arg)?;
                     op_flags += get_address_mode_flag(word_i, mode);
                     arg_val
                 }
@@ -394,9 +518,12 @@ pub mod assembler {
         }
 
         for _ in 0..parsed.def.outargs {
-            let (arg_val, mode) = parse_label(labels, &parsed.words[word_i]);
+            let (arg_val, mode) = parse_label(labels, &parsed.words[word_i])?;
             if mode == AddressMode::Immediate {
-                panic!("Cannot have immediate-mode out arg");
+                return Err(format!(
+                    "cannot have immediate-mode out arg '{}'",
+                    parsed.words[word_i]
+                ));
             }
             op_flags += get_address_mode_flag(word_i, mode);
             result.push(arg_val);
@@ -405,21 +532,79 @@ pub mod assembler {
 
         result.insert(0, op_flags + parsed.def.opcode);
 
-        result
+        Ok(result)
     }
 
-    fn parse_instruction_text(text: &str) -> ParsedInstruction {
-        let no_commas = String::from(text).replace(",", " ");
-        let words: Vec<String> = no_commas
-            .split_whitespace()
-            .map(|x| String::from(x))
-            .collect();
+    // ascii "text" emits one word per character, and ascii "text", nl appends a newline.
+    fn parse_ascii_directive(text: &str) -> Result<Vec<String>, String> {
+        let start = match text.find('"') {
+            Some(x) => x + 1,
+            None => return Err(String::from("ascii expects a quoted string")),
+        };
+        let mut codes = Vec::<i64>::new();
+        let mut chars = text[start..].chars();
+
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => match chars.next() {
+                    Some('n') => codes.push('\n' as i64),
+                    Some(x) => codes.push(x as i64),
+                    None => return Err(String::from("unterminated string")),
+                },
+                Some(x) => codes.push(x as i64),
+                None => return Err(String::from("unterminated string")),
+            }
+        }
+
+        let rest: String = chars.collect();
+        match rest.replace(",", " ").trim() {
+            "" => (),
+            "nl" => codes.push('\n' as i64),
+            x => return Err(format!("unexpected '{}' after string", x)),
+        }
+
+        let mut words = vec![String::from("ascii")];
+        words.extend(codes.iter().map(|x| x.to_string()));
+        Ok(words)
+    }
 
-        let ins: &InstructionDef = INSTRUCTIONS.iter().find(|x| x.name == words[0]).unwrap();
+    fn parse_instruction_text(text: &str) -> Result<ParsedInstruction, String> {
+        let words: Vec<String> = if text.starts_with("ascii") {
+            parse_ascii_directive(text)?
+        } else {
+            text.replace(",", " ")
+                .split_whitespace()
+                .map(String::from)
+                .collect()
+        };
+
+        let name = match words.first() {
+            Some(x) => x,
+            None => return Err(String::from("expected an instruction")),
+        };
+        let ins: &InstructionDef = match INSTRUCTIONS.iter().find(|x| &x.name == name) {
+            Some(x) => x,
+            None => return Err(format!("unknown instruction '{}'", name)),
+        };
+
+        let operands = match words[0].as_str() {
+            "dd" => 1,
+            "fill" => 2,
+            "ascii" => words.len() - 1,
+            _ => (ins.inargs + ins.outargs) as usize,
+        };
+        if words.len() != operands + 1 {
+            return Err(format!("{} takes {} operands", words[0], operands));
+        }
 
         let size = match words[0].as_str() {
             "dd" => 1,
-            "fill" => words[2].parse::<u64>().unwrap(),
+            "fill" => match words[2].parse::<u64>() {
+                Ok(x) => x,
+                Err(_) => return Err(format!("bad fill size '{}'", words[2])),
+            },
+            "ascii" => words.len() as u64 - 1,
             _ => 1 + ins.inargs + ins.outargs,
         };
 
@@ -430,59 +615,909 @@ pub mod assembler {
             }
         }
 
-        ParsedInstruction {
+        Ok(ParsedInstruction {
             size: size,
             def: ins,
             words: words,
             internal_labels: internal_labels,
+        })
+    }
+
+    // Stack calling convention
+    //
+    // The relative base is used as a stack pointer which always points at the next free
+    // cell, and the stack grows upwards. Programs using the stack must first point the
+    // relative base at free memory, e.g. `rba &stack` with `stack:` as the last label in
+    // the file. Operands to `push` and `pop` are resolved before the stack pointer moves,
+    // so `^-1` always refers to the current top of the stack.
+    //
+    //   push x     ->  add x, 0, ^0       rba 1
+    //   pop x      ->  add ^-1, 0, x      rba -1
+    //   call f     ->  add &ret, 0, ^0    rba 1    jz 0, &f    ret:
+    //   ret        ->  rba -1             jz 0, ^0
+    //   enter n    ->  rba n+1            add -(n+1), 0, ^-1
+    //   leave      ->  rba ^-1
+    //
+    // Callers push arguments before `call` and pop them again afterwards. A routine that
+    // needs locals starts with `enter n` and must `leave` before it `ret`s. Inside a frame
+    // with k arguments and n locals and nothing else pushed, local i lives at ^(i-n-1),
+    // the return address at ^(-n-2) and argument j at ^(j-k-n-2). Values are handed back
+    // to the caller by overwriting an argument slot.
+    //
+    // The remaining pseudo-instructions are plain conveniences.
+    //
+    //   mov a, b      ->  add a, 0, b
+    //   jmp t         ->  jz 0, t
+    //   not a, b      ->  cmp a, 0, b
+    //   neg a, b      ->  mul a, -1, b
+    //   inc a         ->  add a, 1, a
+    //   dec a         ->  add a, -1, a
+    //   sub a, b, c   ->  mul b, -1, c    add a, c, c
+    //
+    // sub needs no scratch cell, but picks a different sequence when its operands overlap.
+    fn expand_pseudo_instruction(text: &str, unique_id: &mut u64) -> Result<Vec<String>, String> {
+        let no_commas = String::from(text).replace(",", " ");
+        let words: Vec<&str> = no_commas.split_whitespace().collect();
+
+        let name = match words.first() {
+            Some(&x) => x,
+            None => return Err(String::from("expected an instruction")),
+        };
+
+        match PSEUDO_INSTRUCTIONS.iter().find(|x| x.0 == name) {
+            Some((name, operands)) if words.len() != operands + 1 => {
+                return Err(format!("{} takes {} operands", name, operands))
+            }
+            Some(_) => (),
+            None => return Ok(vec![String::from(text)]),
+        }
+
+        Ok(match name {
+            "push" => vec![format!("add {}, 0, ^0", words[1]), String::from("rba 1")],
+            "pop" => vec![format!("add ^-1, 0, {}", words[1]), String::from("rba -1")],
+            "call" => {
+                *unique_id += 1;
+                let ret_label = format!("__call_ret_{}", unique_id);
+                vec![
+                    format!("add &{}, 0, ^0", ret_label),
+                    String::from("rba 1"),
+                    format!("jz 0, &{}", words[1]),
+                    format!("{}:", ret_label),
+                ]
+            }
+            "ret" => vec![String::from("rba -1"), String::from("jz 0, ^0")],
+            "enter" => {
+                let size = match words[1].parse::<i64>() {
+                    Ok(x) if x >= 0 => x + 1,
+                    _ => return Err(format!("bad frame size '{}'", words[1])),
+                };
+                vec![format!("rba {}", size), format!("add {}, 0, ^-1", -size)]
+            }
+            "leave" => vec![String::from("rba ^-1")],
+            "mov" => vec![format!("add {}, 0, {}", words[1], words[2])],
+            "jmp" => vec![format!("jz 0, {}", words[1])],
+            "not" => vec![format!("cmp {}, 0, {}", words[1], words[2])],
+            "neg" => vec![format!("mul {}, -1, {}", words[1], words[2])],
+            "inc" => vec![format!("add {}, 1, {}", words[1], words[1])],
+            "dec" => vec![format!("add {}, -1, {}", words[1], words[1])],
+            "sub" => {
+                let (a, b, c) = (words[1], words[2], words[3]);
+                if a == c && b == c {
+                    vec![format!("mul 0, 0, {}", c)]
+                } else if a == c {
+                    vec![
+                        format!("mul {}, -1, {}", c, c),
+                        format!("add {}, {}, {}", c, b, c),
+                        format!("mul {}, -1, {}", c, c),
+                    ]
+                } else {
+                    vec![
+                        format!("mul {}, -1, {}", b, c),
+                        format!("add {}, {}, {}", a, c, c),
+                    ]
+                }
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    // Every mnemonic the assembler accepts along with its operand count, for editor tooling.
+    pub fn mnemonics() -> Vec<(&'static str, usize)> {
+        let real = INSTRUCTIONS.iter().map(|x| match x.name {
+            "dd" | "ascii" => (x.name, 1),
+            "fill" => (x.name, 2),
+            _ => (x.name, (x.inargs + x.outargs) as usize),
+        });
+
+        real.chain(PSEUDO_INSTRUCTIONS.iter().cloned()).collect()
+    }
+
+    // Finds every label definition and use in the source, with 1-based lines and 0-based
+    // columns. This follows the same comment, string and label rules as the assembler.
+    pub fn label_sites(text: &str) -> Vec<LabelSite> {
+        let mut result = Vec::<LabelSite>::new();
+        let is_word_char = |ch: char| !ch.is_whitespace() && ch != ',' && ch != ':' && ch != ';';
+
+        for (line_index, raw_line) in text.lines().enumerate() {
+            let chars: Vec<char> = raw_line.chars().collect();
+            let mut at_mnemonic = true;
+            let mut in_ascii = false;
+            let mut i = 0usize;
+
+            while i < chars.len() {
+                match chars[i] {
+                    ';' => break,
+                    '"' => {
+                        i += 1;
+                        while i < chars.len() && chars[i] != '"' {
+                            i += if chars[i] == '\\' { 2 } else { 1 };
+                        }
+                        i += 1;
+                    }
+                    ':' => {
+                        at_mnemonic = true;
+                        i += 1;
+                    }
+                    ch if is_word_char(ch) => {
+                        let start = i;
+                        while i < chars.len() && is_word_char(chars[i]) {
+                            i += 1;
+                        }
+                        let word: String = chars[start..i].iter().collect();
+
+                        let site = if chars.get(i) == Some(&':') {
+                            Some((word.as_str(), start, true))
+                        } else if at_mnemonic {
+                            at_mnemonic = false;
+                            in_ascii = word == "ascii";
+                            None
+                        } else if in_ascii {
+                            None
+                        } else if let Some(name) = word.strip_prefix("$") {
+                            Some((name, start + 1, true))
+                        } else if let Some(name) = word.strip_prefix("&") {
+                            Some((name, start + 1, false))
+                        } else if word.starts_with("^") || word.parse::<i64>().is_ok() {
+                            None
+                        } else {
+                            Some((word.as_str(), start, false))
+                        };
+
+                        if let Some((name, column, definition)) = site {
+                            result.push(LabelSite {
+                                name: String::from(name),
+                                line: line_index + 1,
+                                column,
+                                definition,
+                            });
+                        }
+                    }
+                    _ => i += 1,
+                }
+            }
         }
+
+        result
+    }
+
+    // Strips comments and puts every label on its own line, leaving string literals alone.
+    fn split_source_lines(source: &str) -> Vec<(usize, String)> {
+        let mut result = Vec::<(usize, String)>::new();
+
+        for (line_index, raw_line) in source.lines().enumerate() {
+            let mut line = String::new();
+            let mut in_string = false;
+            let mut escaped = false;
+
+            for ch in raw_line.chars() {
+                if in_string {
+                    if escaped {
+                        escaped = false;
+                    } else if ch == '\\' {
+                        escaped = true;
+                    } else if ch == '"' {
+                        in_string = false;
+                    }
+                    line.push(ch);
+                    continue;
+                }
+
+                match ch {
+                    ';' => break,
+                    '"' => {
+                        in_string = true;
+                        line.push(ch);
+                    }
+                    ':' => {
+                        line.push(ch);
+                        result.push((line_index + 1, String::from(line.trim())));
+                        line.clear();
+                    }
+                    _ => line.push(ch),
+                }
+            }
+
+            result.push((line_index + 1, String::from(line.trim())));
+        }
+
+        result.retain(|x| !x.1.is_empty());
+        result
     }
 
     pub fn assemble(path: &str, debug: bool) -> Vec<i64> {
-        let source: Vec<String> = std::fs::read_to_string(path)
-            .unwrap()
-            .replace(":", ":\n")
-            .lines()
-            .map(|x| String::from(x.trim()))
-            .filter(|x| x.len() > 0 && !x.starts_with(";"))
-            .collect();
-
-        let mut address_labels = HashMap::<String, i64>::new();
-        let mut cur_address = 0u64;
-        let mut instructions = Vec::<ParsedInstruction>::new();
+        assemble_source(&std::fs::read_to_string(path).unwrap(), debug, false)
+    }
 
-        for line in source {
-            if line.ends_with(":") {
-                address_labels.insert(String::from(line.replace(":", "")), cur_address as i64);
-            } else {
-                let parsed = parse_instruction_text(&line);
+    fn peephole(words: &[String], following_labels: &[&str]) -> Peephole {
+        let literal = |i: usize| words[i].parse::<i64>().ok();
+
+        match words[0].as_str() {
+            "add" | "mul" | "less" | "cmp" => {
+                let out = &words[3];
+
+                if let (Some(a), Some(b)) = (literal(1), literal(2)) {
+                    let folded = match words[0].as_str() {
+                        "add" => a.checked_add(b),
+                        "mul" => a.checked_mul(b),
+                        "less" => Some(if a < b { 1 } else { 0 }),
+                        _ => Some(if a == b { 1 } else { 0 }),
+                    };
+                    return match folded {
+                        Some(x) if words[0] != "add" || b != 0 => {
+                            Peephole::Replace(format!("add {}, 0, {}", x, out))
+                        }
+                        _ => Peephole::Keep,
+                    };
+                }
+
+                let identity = if words[0] == "add" { 0 } else { 1 };
+                if (words[0] == "add" || words[0] == "mul")
+                    && ((literal(2) == Some(identity) && &words[1] == out)
+                        || (literal(1) == Some(identity) && &words[2] == out))
+                {
+                    return Peephole::Remove;
+                }
+
+                if words[0] == "mul" && (literal(1) == Some(0) || literal(2) == Some(0)) {
+                    return Peephole::Replace(format!("add 0, 0, {}", out));
+                }
+
+                Peephole::Keep
+            }
+
+            "rba" if literal(1) == Some(0) => Peephole::Remove,
+
+            "jz" | "jnz" => {
+                let never_taken = match literal(1) {
+                    Some(0) => words[0] == "jnz",
+                    Some(_) => words[0] == "jz",
+                    None => false,
+                };
+                let jumps_to_next =
+                    words[2].starts_with("&") && following_labels.contains(&&words[2][1..]);
+
+                if never_taken || jumps_to_next {
+                    Peephole::Remove
+                } else {
+                    Peephole::Keep
+                }
+            }
 
-                for label in &parsed.internal_labels {
-                    address_labels.insert(label.0.clone(), (cur_address + label.1) as i64);
+            _ => Peephole::Keep,
+        }
+    }
+
+    // Peephole pass over the parsed program, run before any addresses are assigned. Any
+    // instruction whose cells might be read or written as data is left exactly as it is,
+    // i.e. ones carrying a $label or sitting at a label which is used as something other
+    // than a jump target. Instructions are only ever removed if every jump is to a label.
+    fn optimize(items: &mut Vec<SourceItem>) {
+        let mut data_labels = HashSet::<String>::new();
+        let mut can_remove = true;
+
+        for item in items.iter() {
+            if let SourceItem::Instruction(ins, _) = item {
+                let is_jump = ins.def.opcode == I_JZ || ins.def.opcode == I_JNZ;
+
+                for (i, word) in ins.words.iter().enumerate().skip(1) {
+                    if word.parse::<i64>().is_ok() {
+                        can_remove &= !(is_jump && i == 2);
+                    } else if let Some(label) = word.strip_prefix("&") {
+                        if !(is_jump && i == 2) {
+                            data_labels.insert(String::from(label));
+                        }
+                    } else if !word.starts_with("^") && !word.starts_with("$") {
+                        data_labels.insert(word.clone());
+                    }
                 }
+            }
+        }
 
-                cur_address += parsed.size;
-                instructions.push(parsed);
+        let mut protected = Vec::<bool>::new();
+        let mut at_data_label = false;
+
+        for item in items.iter() {
+            match item {
+                SourceItem::Label(name, _) => {
+                    at_data_label |= data_labels.contains(name);
+                    protected.push(true);
+                }
+                SourceItem::Instruction(ins, _) => {
+                    protected.push(at_data_label || !ins.internal_labels.is_empty());
+                    at_data_label = false;
+                }
             }
         }
 
-        let mut output = Vec::<i64>::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for i in 0..items.len() {
+                if protected[i] {
+                    continue;
+                }
+
+                let (words, line) = match &items[i] {
+                    SourceItem::Instruction(ins, line) => (ins.words.clone(), *line),
+                    SourceItem::Label(_, _) => continue,
+                };
+
+                let following_labels: Vec<&str> = items[i + 1..]
+                    .iter()
+                    .map_while(|x| match x {
+                        SourceItem::Label(name, _) => Some(name.as_str()),
+                        SourceItem::Instruction(_, _) => None,
+                    })
+                    .collect();
 
-        for ins in instructions {
-            let addr = output.len();
-            let asm = assemble_parsed_instruction(&address_labels, &ins);
-            if debug {
-                println!("{} : {:?} : {:?}", addr, ins.words, asm)
+                match peephole(&words, &following_labels) {
+                    Peephole::Keep => (),
+                    Peephole::Remove if !can_remove => (),
+                    Peephole::Remove => {
+                        items.remove(i);
+                        protected.remove(i);
+                        changed = true;
+                        break;
+                    }
+                    Peephole::Replace(text) => {
+                        let parsed = parse_instruction_text(&text).unwrap();
+                        items[i] = SourceItem::Instruction(parsed, line);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn assemble_program(
+        text: &str,
+        optimize_pass: bool,
+    ) -> Result<Program, Vec<AssembleError>> {
+        let mut items = Vec::<SourceItem>::new();
+        let mut errors = Vec::<AssembleError>::new();
+        let mut pseudo_count = 0u64;
+
+        for (line_num, source_line) in split_source_lines(text) {
+            let lines = match expand_pseudo_instruction(&source_line, &mut pseudo_count) {
+                Ok(x) => x,
+                Err(message) => {
+                    errors.push(AssembleError {
+                        line: line_num,
+                        message,
+                    });
+                    continue;
+                }
             };
-            output.extend(asm);
+
+            for line in lines {
+                if line.ends_with(":") {
+                    items.push(SourceItem::Label(line.replace(":", ""), line_num));
+                    continue;
+                }
+
+                match parse_instruction_text(&line) {
+                    Ok(parsed) => items.push(SourceItem::Instruction(parsed, line_num)),
+                    Err(message) => errors.push(AssembleError {
+                        line: line_num,
+                        message,
+                    }),
+                }
+            }
+        }
+
+        if optimize_pass && errors.is_empty() {
+            optimize(&mut items);
+        }
+
+        let mut symbols = HashMap::<String, i64>::new();
+        let mut cur_address = 0u64;
+        let mut instructions = Vec::<(ParsedInstruction, usize)>::new();
+
+        let mut define = |name: &str, address: u64, line: usize| {
+            if symbols.insert(String::from(name), address as i64).is_some() {
+                errors.push(AssembleError {
+                    line,
+                    message: format!("label '{}' is defined twice", name),
+                });
+            }
+        };
+
+        for item in items {
+            match item {
+                SourceItem::Label(name, line) => define(&name, cur_address, line),
+                SourceItem::Instruction(parsed, line) => {
+                    for label in &parsed.internal_labels {
+                        define(&label.0, cur_address + label.1, line);
+                    }
+
+                    cur_address += parsed.size;
+                    instructions.push((parsed, line));
+                }
+            }
+        }
+
+        let mut tape = Vec::<i64>::new();
+        let mut listing = Vec::<ListingEntry>::new();
+
+        for (ins, line) in instructions {
+            match assemble_parsed_instruction(&symbols, &ins) {
+                Ok(words) => {
+                    listing.push(ListingEntry {
+                        address: tape.len(),
+                        words: words.clone(),
+                        text: ins.words.join(" "),
+                        line,
+                        data: ins.def.opcode < 0,
+                    });
+                    tape.extend(words);
+                }
+                Err(message) => errors.push(AssembleError { line, message }),
+            }
+        }
+
+        if !errors.is_empty() {
+            errors.sort_by_key(|x| x.line);
+            return Err(errors);
         }
 
+        Ok(Program {
+            tape,
+            symbols,
+            listing,
+        })
+    }
+
+    pub fn assemble_source(text: &str, debug: bool, optimize_pass: bool) -> Vec<i64> {
+        let program = match assemble_program(text, optimize_pass) {
+            Ok(x) => x,
+            Err(errors) => panic!("{}", errors[0]),
+        };
+
         if debug {
+            for entry in &program.listing {
+                println!("{} : {} : {:?}", entry.address, entry.text, entry.words)
+            }
             println!("");
-            println!("{:?}", output);
+            println!("{:?}", program.tape);
             println!("");
         }
 
-        output
+        program.tape
+    }
+
+    // Values are comma separated, and value*count repeats a value, e.g. 0*150 for a blank layer.
+    fn parse_test_values(text: &str) -> Result<Vec<i64>, String> {
+        let mut result = Vec::<i64>::new();
+
+        for item in text.split(',').filter(|x| !x.is_empty()) {
+            let parts: Vec<&str> = item.split('*').collect();
+            let value = parts[0].parse::<i64>();
+            let count = if parts.len() > 1 {
+                parts[1].parse::<usize>()
+            } else {
+                Ok(1)
+            };
+
+            match (parts.len(), value, count) {
+                (1, Ok(v), Ok(n)) | (2, Ok(v), Ok(n)) => result.extend(vec![v; n]),
+                _ => return Err(format!("bad test value '{}'", item)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Test cases live in comments such as ";! test inputs=1,2,3 expect=6", with an optional
+    // steps=N for cases which need more instructions than the tester allows by default.
+    pub fn parse_test_directives(text: &str) -> Result<Vec<TestCase>, Vec<AssembleError>> {
+        let mut cases = Vec::<TestCase>::new();
+        let mut errors = Vec::<AssembleError>::new();
+
+        for (line_index, raw_line) in text.lines().enumerate() {
+            let directive = match raw_line.trim().strip_prefix(";!") {
+                Some(x) => x,
+                None => continue,
+            };
+            let words: Vec<&str> = directive.split_whitespace().collect();
+            let mut case = TestCase {
+                line: line_index + 1,
+                inputs: Vec::new(),
+                expect: Vec::new(),
+                steps: None,
+            };
+
+            if words.first() != Some(&"test") {
+                errors.push(AssembleError {
+                    line: case.line,
+                    message: String::from("unknown directive, expected ';! test'"),
+                });
+                continue;
+            }
+
+            for word in &words[1..] {
+                let parsed = match word.find('=') {
+                    Some(i) if &word[..i] == "inputs" => {
+                        parse_test_values(&word[i + 1..]).map(|x| case.inputs = x)
+                    }
+                    Some(i) if &word[..i] == "expect" => {
+                        parse_test_values(&word[i + 1..]).map(|x| case.expect = x)
+                    }
+                    Some(i) if &word[..i] == "steps" => match word[i + 1..].parse::<usize>() {
+                        Ok(x) => {
+                            case.steps = Some(x);
+                            Ok(())
+                        }
+                        Err(_) => Err(format!("bad step count '{}'", &word[i + 1..])),
+                    },
+                    _ => Err(format!("unknown test setting '{}'", word)),
+                };
+
+                if let Err(message) = parsed {
+                    errors.push(AssembleError {
+                        line: case.line,
+                        message,
+                    });
+                }
+            }
+
+            cases.push(case);
+        }
+
+        if errors.is_empty() {
+            Ok(cases)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Symbol files list one label per line as "<address> <name>", sorted by address.
+    pub fn format_symbols(symbols: &HashMap<String, i64>) -> String {
+        let mut sorted: Vec<(&String, &i64)> = symbols.iter().collect();
+        sorted.sort_by_key(|x| (*x.1, x.0));
+
+        sorted
+            .iter()
+            .map(|(name, address)| format!("{} {}\n", address, name))
+            .collect()
+    }
+
+    pub fn parse_symbols(text: &str) -> Result<HashMap<String, i64>, String> {
+        let mut result = HashMap::<String, i64>::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+
+            match (words.len(), words[0].parse::<i64>()) {
+                (2, Ok(address)) => result.insert(String::from(words[1]), address),
+                _ => return Err(format!("line {}: expected '<address> <name>'", i + 1)),
+            };
+        }
+
+        Ok(result)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::intcode::vm::IntCodeMachine;
+
+        fn first_error(text: &str) -> String {
+            match assemble_program(text, false) {
+                Ok(_) => panic!("expected '{}' not to assemble", text),
+                Err(errors) => errors[0].message.clone(),
+            }
+        }
+
+        #[test]
+        fn comma_only_lines_are_errors() {
+            assert_eq!(first_error(","), "expected an instruction");
+            assert_eq!(first_error("  ,  \n"), "expected an instruction");
+            assert_eq!(first_error("label:\n,"), "expected an instruction");
+            assert_eq!(first_error("halt\n,,\nhalt"), "expected an instruction");
+            assert!(assemble_program("halt\n; ,\nhalt", false).is_ok());
+        }
+
+        #[test]
+        fn test_directives_take_a_step_budget() {
+            let cases = parse_test_directives(";! test inputs=1*3 expect=6 steps=500\n").unwrap();
+            assert_eq!(
+                cases,
+                vec![TestCase {
+                    line: 1,
+                    inputs: vec![1, 1, 1],
+                    expect: vec![6],
+                    steps: Some(500),
+                }]
+            );
+            assert_eq!(
+                parse_test_directives(";! test expect=1").unwrap()[0].steps,
+                None
+            );
+
+            let errors = parse_test_directives("\n;! test steps=lots").unwrap_err();
+            assert_eq!(errors[0].line, 2);
+            assert_eq!(errors[0].message, "bad step count 'lots'");
+        }
+
+        fn site(name: &str, line: usize, column: usize, definition: bool) -> LabelSite {
+            LabelSite {
+                name: String::from(name),
+                line,
+                column,
+                definition,
+            }
+        }
+
+        #[test]
+        fn label_sites_finds_definitions_and_uses() {
+            let text =
+                "start: in n\n  loop: jnz $flag 0, &loop ; jz x, &nowhere\n  add n, ^-1, total";
+
+            assert_eq!(
+                label_sites(text),
+                vec![
+                    site("start", 1, 0, true),
+                    site("n", 1, 10, false),
+                    site("loop", 2, 2, true),
+                    site("flag", 2, 13, true),
+                    site("loop", 2, 22, false),
+                    site("n", 3, 6, false),
+                    site("total", 3, 14, false),
+                ]
+            );
+        }
+
+        #[test]
+        fn label_sites_skips_strings_numbers_and_mnemonics() {
+            assert_eq!(label_sites("ascii \"a: b, &c\""), vec![]);
+            assert_eq!(label_sites("out \"x\", 3, -4, ^2"), vec![]);
+            assert_eq!(label_sites("halt\n; x: y\nhalt ;"), vec![]);
+            assert_eq!(
+                label_sites("msg: ascii \"q\\\"; r:\"\nout msg"),
+                vec![site("msg", 1, 0, true), site("msg", 2, 4, false)]
+            );
+            assert_eq!(
+                label_sites("a:b: out c"),
+                vec![
+                    site("a", 1, 0, true),
+                    site("b", 1, 2, true),
+                    site("c", 1, 9, false),
+                ]
+            );
+        }
+
+        #[test]
+        fn mnemonics_include_pseudo_instructions() {
+            let all = mnemonics();
+            for &(name, operands) in &[("add", 3), ("halt", 0), ("fill", 2), ("ascii", 1)] {
+                assert!(all.contains(&(name, operands)), "{}", name);
+            }
+            for &(name, operands) in &[("push", 1), ("sub", 3), ("ret", 0), ("mov", 2)] {
+                assert!(all.contains(&(name, operands)), "{}", name);
+            }
+        }
+
+        // Runs the source both with and without the peephole pass, which have to agree.
+        fn run(text: &str, inputs: &[i64]) -> Vec<i64> {
+            let (plain, optimized) = both_tapes(text);
+            let output = IntCodeMachine::run_all(&plain, inputs);
+            assert_eq!(IntCodeMachine::run_all(&optimized, inputs), output);
+            output
+        }
+
+        #[test]
+        fn push_and_pop_use_the_stack() {
+            let text = "rba &stack\nin a\npush a\npush 7\npush ^-2\npop b\npop c\npop d\n\
+                        push 1\npop ^0\nout b\nout c\nout d\nout ^0\nhalt\n\
+                        a: dd 0\nb: dd 0\nc: dd 0\nd: dd 0\nstack: dd 0";
+            assert_eq!(run(text, &[5]), vec![5, 7, 5, 1]);
+        }
+
+        #[test]
+        fn call_and_ret_pass_through_frames() {
+            // double(x) overwrites its argument with 2x, using one local for the sum.
+            let text = "rba &stack\nin n\npush n\ncall double\ncall double\npop n\nout n\nhalt\n\
+                        double:\nenter 1\nadd ^-4, ^-4, ^-2\nmov ^-2, ^-4\nleave\nret\n\
+                        n: dd 0\nstack: dd 0";
+            assert_eq!(run(text, &[21]), vec![84]);
+
+            // A frame with no locals, and a routine which calls another. pop's destination
+            // is relative to the stack before the pop, so the argument is one further down.
+            let text = "rba &stack\npush 3\ncall outer\npop n\nout n\nhalt\n\
+                        outer:\nenter 0\npush ^-3\ncall inner\npop ^-4\nleave\nret\n\
+                        inner:\nenter 2\nmul ^-5, 10, ^-3\nmov ^-3, ^-2\ninc ^-2\n\
+                        mov ^-2, ^-5\nleave\nret\n\
+                        n: dd 0\nstack: dd 0";
+            assert_eq!(run(text, &[]), vec![31]);
+        }
+
+        #[test]
+        fn single_instruction_pseudo_ops() {
+            let text = "in a\nmov a, b\nout b\nnot a, c\nout c\nneg a, c\nout c\n\
+                        inc a\nout a\ndec a\ndec a\nout a\njmp &end\nout 99\n\
+                        end: halt\na: dd 0\nb: dd 0\nc: dd 0";
+            assert_eq!(run(text, &[5]), vec![5, 0, -5, 6, 4]);
+            assert_eq!(run(text, &[0]), vec![0, 1, 0, 1, -1]);
+        }
+
+        #[test]
+        fn sub_handles_overlapping_operands() {
+            // With x = 10 and y = 3, the line and what x, y and z hold after it.
+            let cases = [
+                ("sub x, y, z", [10, 3, 7]),
+                ("sub x, y, x", [7, 3, 0]),
+                ("sub x, y, y", [10, 7, 0]),
+                ("sub y, x, y", [10, -7, 0]),
+                ("sub x, x, z", [10, 3, 0]),
+                ("sub x, x, x", [0, 3, 0]),
+                ("sub 20, x, x", [10, 3, 0]),
+                ("sub x, 4, x", [6, 3, 0]),
+                ("sub 1, 2, z", [10, 3, -1]),
+            ];
+
+            for (line, expected) in &cases {
+                let text = format!(
+                    "in x\nin y\n{}\nout x\nout y\nout z\nhalt\nx: dd 0\ny: dd 0\nz: dd 0",
+                    line
+                );
+                assert_eq!(run(&text, &[10, 3]), expected.to_vec(), "{}", line);
+            }
+        }
+
+        #[test]
+        fn pseudo_ops_check_their_operands() {
+            assert_eq!(first_error("push"), "push takes 1 operands");
+            assert_eq!(first_error("ret 1"), "ret takes 0 operands");
+            assert_eq!(first_error("sub a, b"), "sub takes 3 operands");
+            assert_eq!(first_error("enter -1"), "bad frame size '-1'");
+            assert_eq!(first_error("enter x"), "bad frame size 'x'");
+        }
+
+        // The tape without and with the peephole pass.
+        fn both_tapes(text: &str) -> (Vec<i64>, Vec<i64>) {
+            (
+                assemble_program(text, false).unwrap().tape,
+                assemble_program(text, true).unwrap().tape,
+            )
+        }
+
+        #[test]
+        fn optimizer_removes_jumps_to_the_next_instruction() {
+            let text = "in x\njnz x, &next\nnext: out x\njz x, &end\nend: halt\nx: dd 0";
+            let (plain, optimized) = both_tapes(text);
+
+            assert_eq!(optimized.len(), plain.len() - 6);
+            for input in 0..3 {
+                assert_eq!(
+                    IntCodeMachine::run_all(&optimized, &[input]),
+                    IntCodeMachine::run_all(&plain, &[input])
+                );
+            }
+        }
+
+        #[test]
+        fn optimizer_folds_constants() {
+            let (_, optimized) = both_tapes("add 2, 3, x\nhalt\nx: dd 0");
+            assert_eq!(&optimized[..4], &[1101, 5, 0, 5]);
+
+            let (_, optimized) = both_tapes("mul 4, 5, x\nhalt\nx: dd 0");
+            assert_eq!(&optimized[..4], &[1101, 20, 0, 5]);
+
+            let (_, optimized) = both_tapes("less 4, 5, x\ncmp 4, 5, x\nhalt\nx: dd 0");
+            assert_eq!(&optimized[..8], &[1101, 1, 0, 9, 1101, 0, 0, 9]);
+
+            let (_, optimized) = both_tapes("in x\nmul x, 0, x\nout x\nhalt\nx: dd 0");
+            assert_eq!(&optimized[2..6], &[1101, 0, 0, 9]);
+            assert_eq!(IntCodeMachine::run_all(&optimized, &[7]), vec![0]);
+
+            let overflow = "mul 9223372036854775807, 2, x\nhalt\nx: dd 0";
+            let (plain, optimized) = both_tapes(overflow);
+            assert_eq!(optimized, plain);
+        }
+
+        #[test]
+        fn optimizer_removes_self_moves() {
+            let text = "in x\nmov x, x\nadd 0, x, x\nmul x, 1, x\nrba 0\nout x\nhalt\nx: dd 0";
+            let (plain, optimized) = both_tapes(text);
+
+            assert_eq!(optimized.len(), plain.len() - 14);
+            assert_eq!(IntCodeMachine::run_all(&optimized, &[42]), vec![42]);
+            assert_eq!(IntCodeMachine::run_all(&plain, &[42]), vec![42]);
+        }
+
+        #[test]
+        fn optimizer_leaves_data_alone() {
+            // The add carries a $label, so its cells may be rewritten at run time.
+            let (plain, optimized) = both_tapes("add 2, 3, $cell\nmov x, x\nhalt\nx: dd 0");
+            assert_eq!(&optimized[..4], &plain[..4]);
+            assert_eq!(optimized.len(), plain.len() - 4);
+
+            // The instructions at code are read as data, so their cells have to stay put.
+            let text = "code: add 2, 3, x\nmov x, x\nout code\nhalt\nx: dd 0";
+            let (_, optimized) = both_tapes(text);
+            assert_eq!(&optimized[..3], &[1101, 2, 3]);
+            assert_eq!(IntCodeMachine::run_all(&optimized, &[]), vec![1101]);
+
+            // A jump to a numbered address could land anywhere, so nothing may move.
+            let text = "jz 1, 6\njnz x, &next\nnext: halt\nx: dd 0";
+            let (plain, optimized) = both_tapes(text);
+            assert_eq!(optimized.len(), plain.len());
+        }
+
+        #[test]
+        fn optimized_programs_give_the_same_output() {
+            let digits: Vec<i64> = std::fs::read_to_string("data/day8.txt")
+                .unwrap()
+                .trim()
+                .chars()
+                .map(|x| x.to_digit(10).unwrap() as i64)
+                .chain(std::iter::once(99))
+                .collect();
+            let runs: Vec<(&str, Vec<Vec<i64>>)> = vec![
+                ("intcode/day8.asm", vec![digits]),
+                ("intcode/factorial.asm", vec![vec![0], vec![5], vec![12]]),
+                ("intcode/fib.asm", vec![vec![]]),
+            ];
+
+            for (path, inputs) in runs {
+                let (plain, optimized) = both_tapes(&std::fs::read_to_string(path).unwrap());
+                assert!(optimized.len() <= plain.len(), "{}", path);
+
+                for input in inputs {
+                    assert_eq!(
+                        IntCodeMachine::run_all(&optimized, &input),
+                        IntCodeMachine::run_all(&plain, &input),
+                        "{}",
+                        path
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn symbols_round_trip() {
+            for path in &[
+                "intcode/factorial.asm",
+                "intcode/fib.asm",
+                "intcode/day8.asm",
+            ] {
+                let source = std::fs::read_to_string(path).unwrap();
+                let symbols = assemble_program(&source, false).unwrap().symbols;
+                assert!(!symbols.is_empty());
+
+                let text = format_symbols(&symbols);
+                assert_eq!(parse_symbols(&text), Ok(symbols));
+            }
+
+            assert_eq!(parse_symbols("\n  \n"), Ok(HashMap::new()));
+            assert_eq!(
+                parse_symbols("3 loop\nloop 3\n"),
+                Err(String::from("line 2: expected '<address> <name>'"))
+            );
+        }
     }
 }
@@ -0,0 +1,1049 @@
+// A tiny structured language which compiles down to assembler source.
+//
+//   var image[150] = 2;          global array, every cell starts at 2
+//   var total = 0;               global integer
+//
+//   fn fib(n) {
+//       if n < 2 { return 1; }
+//       return fib(n - 1) + fib(n - 2);
+//   }
+//
+//   fn main() {
+//       var i = 0;
+//       while i < 25 { output(fib(i)); i = i + 1; }
+//   }
+//
+// Every value is an integer. Arrays can only be globals, locals and parameters live on the
+// stack so functions may recurse. Expressions support + - * < <= > >= == != && || ! and unary
+// minus, plus the builtins input() and output(x). && and || short-circuit and produce 0 or 1.
+// Execution starts at main(), and functions which fall off the end return 0.
+//
+// Code generation treats the relative base as the top of an evaluation stack and follows
+// the calling convention documented in the assembler. Every expression pushes exactly one
+// value, so the compiler only has to track how deep it is to address locals and parameters.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 21] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "<", ">", "=", "!", "(", ")", "{", "}", "[",
+    "]", ",", ";",
+];
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Var(String),
+    Index(String, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Var(String, Option<Expr>),
+    Assign(String, Expr),
+    AssignIndex(String, Expr, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Output(Expr),
+    Expr(Expr),
+}
+
+#[derive(Debug)]
+struct Function {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+}
+
+#[derive(Debug)]
+struct Global {
+    name: String,
+    size: Option<i64>,
+    value: i64,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut result = Vec::<(Token, usize)>::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_num = line_index + 1;
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0usize;
+
+        while i < chars.len() {
+            let rest: String = chars[i..].iter().collect();
+
+            if chars[i].is_whitespace() {
+                i += 1;
+            } else if rest.starts_with("//") {
+                break;
+            } else if chars[i].is_ascii_digit() {
+                let digits: String = rest.chars().take_while(|x| x.is_ascii_digit()).collect();
+                i += digits.len();
+                let value = digits
+                    .parse::<i64>()
+                    .map_err(|_| format!("line {}: number {} is too large", line_num, digits))?;
+                result.push((Token::Number(value), line_num));
+            } else if chars[i].is_alphabetic() || chars[i] == '_' {
+                let ident: String = rest
+                    .chars()
+                    .take_while(|x| x.is_alphanumeric() || *x == '_')
+                    .collect();
+                i += ident.chars().count();
+                result.push((Token::Ident(ident), line_num));
+            } else if let Some(sym) = SYMBOLS.iter().find(|x| rest.starts_with(*x)) {
+                i += sym.len();
+                result.push((Token::Symbol(sym), line_num));
+            } else {
+                return Err(format!(
+                    "line {}: unexpected character '{}'",
+                    line_num, chars[i]
+                ));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn describe_token(token: Option<&Token>) -> String {
+    match token {
+        Some(Token::Number(x)) => x.to_string(),
+        Some(Token::Ident(x)) => format!("'{}'", x),
+        Some(Token::Symbol(x)) => format!("'{}'", x),
+        None => String::from("end of file"),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|x| &x.0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|x| &x.0)
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some(x) => x.1,
+            None => self.tokens.last().map_or(1, |x| x.1),
+        }
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, String> {
+        Err(format!(
+            "line {}: expected {}, found {}",
+            self.line(),
+            expected,
+            describe_token(self.peek())
+        ))
+    }
+
+    fn at_symbol(&self, sym: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(x)) => *x == sym,
+            _ => false,
+        }
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        self.peek() == Some(&Token::Ident(String::from(keyword)))
+    }
+
+    fn eat_symbol(&mut self, sym: &str) -> Result<(), String> {
+        if self.at_symbol(sym) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("'{}'", sym))
+        }
+    }
+
+    fn eat_ident(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Ident(x)) => {
+                let x = x.clone();
+                self.pos += 1;
+                Ok(x)
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn eat_number(&mut self) -> Result<i64, String> {
+        let negative = self.at_symbol("-");
+        if negative {
+            self.pos += 1;
+        }
+
+        match self.peek() {
+            Some(Token::Number(x)) => {
+                let x = *x;
+                self.pos += 1;
+                Ok(if negative { -x } else { x })
+            }
+            _ => self.error("a number"),
+        }
+    }
+
+    fn parse_program(&mut self) -> Result<(Vec<Global>, Vec<Function>), String> {
+        let mut globals = Vec::<Global>::new();
+        let mut functions = Vec::<Function>::new();
+
+        while self.peek().is_some() {
+            if self.at_keyword("var") {
+                self.pos += 1;
+                let name = self.eat_ident()?;
+                let mut size = None;
+                let mut value = 0;
+
+                if self.at_symbol("[") {
+                    self.pos += 1;
+                    size = Some(self.eat_number()?);
+                    self.eat_symbol("]")?;
+                }
+                if self.at_symbol("=") {
+                    self.pos += 1;
+                    value = self.eat_number()?;
+                }
+                self.eat_symbol(";")?;
+
+                globals.push(Global { name, size, value });
+            } else if self.at_keyword("fn") {
+                self.pos += 1;
+                let name = self.eat_ident()?;
+                let mut params = Vec::<String>::new();
+
+                self.eat_symbol("(")?;
+                while !self.at_symbol(")") {
+                    if !params.is_empty() {
+                        self.eat_symbol(",")?;
+                    }
+                    params.push(self.eat_ident()?);
+                }
+                self.eat_symbol(")")?;
+
+                let body = self.parse_block()?;
+                functions.push(Function { name, params, body });
+            } else {
+                return self.error("'var' or 'fn'");
+            }
+        }
+
+        Ok((globals, functions))
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, String> {
+        let mut result = Vec::<Stmt>::new();
+
+        self.eat_symbol("{")?;
+        while !self.at_symbol("}") {
+            if self.peek().is_none() {
+                return self.error("'}'");
+            }
+            result.push(self.parse_statement()?);
+        }
+        self.eat_symbol("}")?;
+
+        Ok(result)
+    }
+
+    fn parse_statement(&mut self) -> Result<Stmt, String> {
+        if self.at_keyword("var") {
+            self.pos += 1;
+            let name = self.eat_ident()?;
+            let init = if self.at_symbol("=") {
+                self.pos += 1;
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
+            self.eat_symbol(";")?;
+            return Ok(Stmt::Var(name, init));
+        }
+
+        if self.at_keyword("if") {
+            self.pos += 1;
+            let cond = self.parse_expr()?;
+            let then_body = self.parse_block()?;
+            let mut else_body = Vec::<Stmt>::new();
+
+            if self.at_keyword("else") {
+                self.pos += 1;
+                else_body = if self.at_keyword("if") {
+                    vec![self.parse_statement()?]
+                } else {
+                    self.parse_block()?
+                };
+            }
+
+            return Ok(Stmt::If(cond, then_body, else_body));
+        }
+
+        if self.at_keyword("while") {
+            self.pos += 1;
+            let cond = self.parse_expr()?;
+            let body = self.parse_block()?;
+            return Ok(Stmt::While(cond, body));
+        }
+
+        if self.at_keyword("return") {
+            self.pos += 1;
+            let value = if self.at_symbol(";") {
+                None
+            } else {
+                Some(self.parse_expr()?)
+            };
+            self.eat_symbol(";")?;
+            return Ok(Stmt::Return(value));
+        }
+
+        if self.at_keyword("output") {
+            self.pos += 1;
+            self.eat_symbol("(")?;
+            let value = self.parse_expr()?;
+            self.eat_symbol(")")?;
+            self.eat_symbol(";")?;
+            return Ok(Stmt::Output(value));
+        }
+
+        if let Some(Token::Ident(name)) = self.peek() {
+            let name = name.clone();
+
+            if self.peek_at(1) == Some(&Token::Symbol("=")) {
+                self.pos += 2;
+                let value = self.parse_expr()?;
+                self.eat_symbol(";")?;
+                return Ok(Stmt::Assign(name, value));
+            }
+
+            if self.peek_at(1) == Some(&Token::Symbol("[")) {
+                let start = self.pos;
+                self.pos += 2;
+                let index = self.parse_expr()?;
+                self.eat_symbol("]")?;
+
+                if self.at_symbol("=") {
+                    self.pos += 1;
+                    let value = self.parse_expr()?;
+                    self.eat_symbol(";")?;
+                    return Ok(Stmt::AssignIndex(name, index, value));
+                }
+
+                self.pos = start;
+            }
+        }
+
+        let expr = self.parse_expr()?;
+        self.eat_symbol(";")?;
+        Ok(Stmt::Expr(expr))
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, String> {
+        self.parse_binary(0)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: [&[&str]; 6] = [
+            &["||"],
+            &["&&"],
+            &["==", "!="],
+            &["<", "<=", ">", ">="],
+            &["+", "-"],
+            &["*"],
+        ];
+
+        if level == LEVELS.len() {
+            return self.parse_unary();
+        }
+
+        let mut lhs = self.parse_binary(level + 1)?;
+
+        while let Some(Token::Symbol(sym)) = self.peek() {
+            let sym = *sym;
+            if !LEVELS[level].contains(&sym) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Expr::Binary(sym, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.at_symbol("-") {
+            self.pos += 1;
+            return Ok(match self.parse_unary()? {
+                Expr::Number(x) => Expr::Number(-x),
+                x => Expr::Unary("-", Box::new(x)),
+            });
+        }
+
+        if self.at_symbol("!") {
+            self.pos += 1;
+            return Ok(Expr::Unary("!", Box::new(self.parse_unary()?)));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.peek().cloned() {
+            Some(Token::Number(x)) => {
+                self.pos += 1;
+                Ok(Expr::Number(x))
+            }
+            Some(Token::Symbol("(")) => {
+                self.pos += 1;
+                let expr = self.parse_expr()?;
+                self.eat_symbol(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+
+                if self.at_symbol("(") {
+                    self.pos += 1;
+                    let mut args = Vec::<Expr>::new();
+                    while !self.at_symbol(")") {
+                        if !args.is_empty() {
+                            self.eat_symbol(",")?;
+                        }
+                        args.push(self.parse_expr()?);
+                    }
+                    self.eat_symbol(")")?;
+                    Ok(Expr::Call(name, args))
+                } else if self.at_symbol("[") {
+                    self.pos += 1;
+                    let index = self.parse_expr()?;
+                    self.eat_symbol("]")?;
+                    Ok(Expr::Index(name, Box::new(index)))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            }
+            _ => self.error("an expression"),
+        }
+    }
+}
+
+struct Frame {
+    function: String,
+    params: Vec<String>,
+    locals: Vec<String>,
+    depth: i64,
+}
+
+impl Frame {
+    fn collect_locals(stmts: &[Stmt], locals: &mut Vec<String>) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Var(name, _) if !locals.contains(name) => locals.push(name.clone()),
+                Stmt::If(_, a, b) => {
+                    Frame::collect_locals(a, locals);
+                    Frame::collect_locals(b, locals);
+                }
+                Stmt::While(_, a) => Frame::collect_locals(a, locals),
+                _ => (),
+            }
+        }
+    }
+
+    fn stack_operand(&self, name: &str) -> Option<String> {
+        let n = self.locals.len() as i64;
+        let k = self.params.len() as i64;
+
+        if let Some(i) = self.locals.iter().position(|x| x == name) {
+            return Some(format!("^{}", i as i64 - n - 1 - self.depth));
+        }
+
+        let j = self.params.iter().position(|x| x == name)?;
+        Some(format!("^{}", j as i64 - k - n - 2 - self.depth))
+    }
+}
+
+struct Compiler {
+    lines: Vec<String>,
+    label_count: u64,
+    arities: HashMap<String, usize>,
+    arrays: HashMap<String, bool>,
+}
+
+impl Compiler {
+    fn emit(&mut self, line: String) {
+        if line.ends_with(':') {
+            self.lines.push(line);
+        } else {
+            self.lines.push(format!("        {}", line));
+        }
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!("L_{}", self.label_count)
+    }
+
+    fn push(&mut self, frame: &mut Frame, operand: &str) {
+        self.emit(format!("push {}", operand));
+        frame.depth += 1;
+    }
+
+    fn pop(&mut self, frame: &mut Frame, operand: &str) {
+        self.emit(format!("pop {}", operand));
+        frame.depth -= 1;
+    }
+
+    fn drop(&mut self, frame: &mut Frame, count: i64) {
+        if count > 0 {
+            self.emit(format!("rba {}", -count));
+            frame.depth -= count;
+        }
+    }
+
+    fn scalar_operand(&self, frame: &Frame, name: &str) -> Result<String, String> {
+        if let Some(x) = frame.stack_operand(name) {
+            return Ok(x);
+        }
+
+        match self.arrays.get(name) {
+            Some(false) => Ok(format!("g_{}", name)),
+            Some(true) => Err(format!("in {}: '{}' is an array", frame.function, name)),
+            None => Err(format!(
+                "in {}: unknown variable '{}'",
+                frame.function, name
+            )),
+        }
+    }
+
+    fn array_label(&self, frame: &Frame, name: &str) -> Result<String, String> {
+        if frame.stack_operand(name).is_none() && self.arrays.get(name) == Some(&true) {
+            Ok(format!("&g_{}", name))
+        } else {
+            Err(format!("in {}: '{}' is not an array", frame.function, name))
+        }
+    }
+
+    fn gen_expr(&mut self, frame: &mut Frame, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::Number(x) => self.push(frame, &x.to_string()),
+
+            Expr::Var(name) => {
+                let operand = self.scalar_operand(frame, name)?;
+                self.push(frame, &operand);
+            }
+
+            Expr::Index(name, index) => {
+                let array = self.array_label(frame, name)?;
+                let cell = self.new_label();
+                self.gen_expr(frame, index)?;
+                self.emit(format!("add ^-1, {}, {}", array, cell));
+                self.emit(format!("mov ${}, ^-1", cell));
+            }
+
+            Expr::Call(name, args) if name == "input" && args.is_empty() => {
+                self.emit(String::from("in ^0"));
+                self.emit(String::from("rba 1"));
+                frame.depth += 1;
+            }
+
+            Expr::Call(name, args) => {
+                match self.arities.get(name) {
+                    Some(x) if *x == args.len() => (),
+                    Some(x) => {
+                        return Err(format!(
+                            "in {}: {} takes {} arguments but {} were given",
+                            frame.function,
+                            name,
+                            x,
+                            args.len()
+                        ))
+                    }
+                    None => {
+                        return Err(format!(
+                            "in {}: unknown function '{}'",
+                            frame.function, name
+                        ))
+                    }
+                }
+
+                for arg in args {
+                    self.gen_expr(frame, arg)?;
+                }
+                self.emit(format!("call f_{}", name));
+                self.drop(frame, args.len() as i64);
+                self.push(frame, "__rv");
+            }
+
+            Expr::Unary(op, value) => {
+                self.gen_expr(frame, value)?;
+                match *op {
+                    "-" => self.emit(String::from("neg ^-1, ^-1")),
+                    _ => self.emit(String::from("not ^-1, ^-1")),
+                }
+            }
+
+            Expr::Binary("&&", lhs, rhs) => {
+                let end = self.new_label();
+                self.gen_expr(frame, lhs)?;
+                self.emit(format!("jz ^-1, &{}", end));
+                self.drop(frame, 1);
+                self.gen_expr(frame, rhs)?;
+                self.emit(String::from("not ^-1, ^-1"));
+                self.emit(String::from("not ^-1, ^-1"));
+                self.emit(format!("{}:", end));
+            }
+
+            Expr::Binary("||", lhs, rhs) => {
+                let rhs_label = self.new_label();
+                let end = self.new_label();
+                self.gen_expr(frame, lhs)?;
+                self.emit(format!("jz ^-1, &{}", rhs_label));
+                self.emit(String::from("mov 1, ^-1"));
+                self.emit(format!("jmp &{}", end));
+                self.emit(format!("{}:", rhs_label));
+                self.drop(frame, 1);
+                self.gen_expr(frame, rhs)?;
+                self.emit(String::from("not ^-1, ^-1"));
+                self.emit(String::from("not ^-1, ^-1"));
+                self.emit(format!("{}:", end));
+            }
+
+            Expr::Binary(op, lhs, rhs) => {
+                self.gen_expr(frame, lhs)?;
+                self.gen_expr(frame, rhs)?;
+                match *op {
+                    "+" => self.emit(String::from("add ^-2, ^-1, ^-2")),
+                    "-" => self.emit(String::from("sub ^-2, ^-1, ^-2")),
+                    "*" => self.emit(String::from("mul ^-2, ^-1, ^-2")),
+                    "<" => self.emit(String::from("less ^-2, ^-1, ^-2")),
+                    ">" => self.emit(String::from("less ^-1, ^-2, ^-2")),
+                    "==" => self.emit(String::from("cmp ^-2, ^-1, ^-2")),
+                    "!=" => {
+                        self.emit(String::from("cmp ^-2, ^-1, ^-2"));
+                        self.emit(String::from("not ^-2, ^-2"));
+                    }
+                    "<=" => {
+                        self.emit(String::from("less ^-1, ^-2, ^-2"));
+                        self.emit(String::from("not ^-2, ^-2"));
+                    }
+                    _ => {
+                        self.emit(String::from("less ^-2, ^-1, ^-2"));
+                        self.emit(String::from("not ^-2, ^-2"));
+                    }
+                }
+                self.drop(frame, 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn gen_block(&mut self, frame: &mut Frame, stmts: &[Stmt]) -> Result<(), String> {
+        for stmt in stmts {
+            self.gen_statement(frame, stmt)?;
+        }
+        Ok(())
+    }
+
+    fn gen_statement(&mut self, frame: &mut Frame, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Var(name, None) => {
+                let operand = self.scalar_operand(frame, name)?;
+                self.emit(format!("mov 0, {}", operand));
+            }
+
+            Stmt::Var(name, Some(value)) | Stmt::Assign(name, value) => {
+                self.gen_expr(frame, value)?;
+                let operand = self.scalar_operand(frame, name)?;
+                self.pop(frame, &operand);
+            }
+
+            Stmt::AssignIndex(name, index, value) => {
+                let array = self.array_label(frame, name)?;
+                let cell = self.new_label();
+                self.gen_expr(frame, index)?;
+                self.gen_expr(frame, value)?;
+                self.emit(format!("add ^-2, {}, {}", array, cell));
+                self.emit(format!("mov ^-1, ${}", cell));
+                self.drop(frame, 2);
+            }
+
+            Stmt::If(cond, then_body, else_body) => {
+                let else_label = self.new_label();
+                let end = self.new_label();
+                self.gen_expr(frame, cond)?;
+                self.drop(frame, 1);
+                self.emit(format!("jz ^0, &{}", else_label));
+                self.gen_block(frame, then_body)?;
+                self.emit(format!("jmp &{}", end));
+                self.emit(format!("{}:", else_label));
+                self.gen_block(frame, else_body)?;
+                self.emit(format!("{}:", end));
+            }
+
+            Stmt::While(cond, body) => {
+                let top = self.new_label();
+                let end = self.new_label();
+                self.emit(format!("{}:", top));
+                self.gen_expr(frame, cond)?;
+                self.drop(frame, 1);
+                self.emit(format!("jz ^0, &{}", end));
+                self.gen_block(frame, body)?;
+                self.emit(format!("jmp &{}", top));
+                self.emit(format!("{}:", end));
+            }
+
+            Stmt::Return(value) => {
+                match value {
+                    Some(x) => {
+                        self.gen_expr(frame, x)?;
+                        self.pop(frame, "__rv");
+                    }
+                    None => self.emit(String::from("mov 0, __rv")),
+                }
+                self.emit(String::from("leave"));
+                self.emit(String::from("ret"));
+            }
+
+            Stmt::Output(value) => {
+                self.gen_expr(frame, value)?;
+                self.emit(String::from("out ^-1"));
+                self.drop(frame, 1);
+            }
+
+            Stmt::Expr(value) => {
+                self.gen_expr(frame, value)?;
+                self.drop(frame, 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn gen_function(&mut self, function: &Function) -> Result<(), String> {
+        let mut frame = Frame {
+            function: function.name.clone(),
+            params: function.params.clone(),
+            locals: Vec::new(),
+            depth: 0,
+        };
+        Frame::collect_locals(&function.body, &mut frame.locals);
+
+        self.emit(format!("f_{}:", function.name));
+        self.emit(format!("enter {}", frame.locals.len()));
+        self.gen_block(&mut frame, &function.body)?;
+        self.emit(String::from("mov 0, __rv"));
+        self.emit(String::from("leave"));
+        self.emit(String::from("ret"));
+
+        Ok(())
+    }
+}
+
+pub fn compile(source: &str) -> Result<String, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    };
+    let (globals, functions) = parser.parse_program()?;
+
+    let mut compiler = Compiler {
+        lines: Vec::new(),
+        label_count: 0,
+        arities: HashMap::new(),
+        arrays: HashMap::new(),
+    };
+
+    for global in &globals {
+        if compiler.arrays.contains_key(&global.name) {
+            return Err(format!("variable '{}' is declared twice", global.name));
+        }
+        compiler
+            .arrays
+            .insert(global.name.clone(), global.size.is_some());
+    }
+
+    for function in &functions {
+        if compiler.arities.contains_key(&function.name) {
+            return Err(format!("function '{}' is declared twice", function.name));
+        }
+        compiler
+            .arities
+            .insert(function.name.clone(), function.params.len());
+    }
+
+    if compiler.arities.get("main") != Some(&0) {
+        return Err(String::from("program needs a main() with no parameters"));
+    }
+
+    compiler.emit(String::from("rba &__stack"));
+    compiler.emit(String::from("call f_main"));
+    compiler.emit(String::from("halt"));
+
+    for function in &functions {
+        compiler.gen_function(function)?;
+    }
+
+    for global in &globals {
+        match global.size {
+            Some(size) => compiler.lines.push(format!(
+                "g_{}: fill {}, {}",
+                global.name, global.value, size
+            )),
+            None => compiler
+                .lines
+                .push(format!("g_{}: dd {}", global.name, global.value)),
+        }
+    }
+    compiler.lines.push(String::from("__rv: dd 0"));
+    compiler.lines.push(String::from("__stack: dd 0"));
+
+    Ok(compiler.lines.join("\n") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assembler::{assemble, assemble_source};
+    use crate::intcode::vm::IntCodeMachine;
+
+    // Runs the compiled program with and without the optimizer, which have to agree.
+    fn run(source: &str, inputs: &[i64]) -> Vec<i64> {
+        let compiled = compile(source).unwrap();
+        let plain = IntCodeMachine::run_all(&assemble_source(&compiled, false, false), inputs);
+        let optimized = IntCodeMachine::run_all(&assemble_source(&compiled, false, true), inputs);
+        assert_eq!(plain, optimized);
+        plain
+    }
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err()
+    }
+
+    #[test]
+    fn fib_matches_hand_written_assembly() {
+        let expected = IntCodeMachine::run_all(&assemble("intcode/fib.asm", false), &[]);
+        assert_eq!(expected.len(), 25);
+        assert_eq!(&expected[..6], &[1, 1, 2, 3, 5, 8]);
+
+        let source = std::fs::read_to_string("intcode/fib.ic").unwrap();
+        assert_eq!(run(&source, &[]), expected);
+    }
+
+    #[test]
+    fn day8_matches_hand_written_assembly() {
+        let mut digits: Vec<i64> = std::fs::read_to_string("data/day8.txt")
+            .unwrap()
+            .trim()
+            .chars()
+            .map(|x| x.to_digit(10).unwrap() as i64)
+            .collect();
+        digits.push(99);
+
+        let expected = IntCodeMachine::run_all(&assemble("intcode/day8.asm", false), &digits);
+        assert_eq!(expected.len(), 151);
+
+        let source = std::fs::read_to_string("intcode/day8.ic").unwrap();
+        assert_eq!(run(&source, &digits), expected);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            (
+                "fn main() { var x = ; }",
+                "line 1: expected an expression, found ';'",
+            ),
+            (
+                "fn main() {\n    while 1 {\n        output(1);\n",
+                "line 3: expected '}', found end of file",
+            ),
+            ("fn main() { output(1) }", "line 1: expected ';', found '}'"),
+            (
+                "fn main() { x = 1 $ 2; }",
+                "line 1: unexpected character '$'",
+            ),
+        ];
+
+        for (source, message) in &cases {
+            assert_eq!(&error(source), message);
+        }
+    }
+
+    #[test]
+    fn name_and_type_errors() {
+        let cases = [
+            ("fn main() { output(y); }", "in main: unknown variable 'y'"),
+            (
+                "fn f(a, b) { return a; }\nfn main() { output(f(1)); }",
+                "in main: f takes 2 arguments but 1 were given",
+            ),
+            (
+                "fn main() { output(g()); }",
+                "in main: unknown function 'g'",
+            ),
+            (
+                "var a[3] = 0;\nfn main() { output(a); }",
+                "in main: 'a' is an array",
+            ),
+            (
+                "var a = 0;\nfn main() { output(a[1]); }",
+                "in main: 'a' is not an array",
+            ),
+            ("fn f() { }", "program needs a main() with no parameters"),
+            (
+                "fn main(x) { }",
+                "program needs a main() with no parameters",
+            ),
+            (
+                "var a = 0;\nvar a = 1;\nfn main() { }",
+                "variable 'a' is declared twice",
+            ),
+            (
+                "fn main() { }\nfn main() { }",
+                "function 'main' is declared twice",
+            ),
+        ];
+
+        for (source, message) in &cases {
+            assert_eq!(&error(source), message);
+        }
+    }
+
+    #[test]
+    fn while_loops() {
+        let source = "fn main() {
+            var n = input();
+            var total = 0;
+            while n > 0 {
+                total = total + n;
+                n = n - 1;
+            }
+            output(total);
+        }";
+
+        assert_eq!(run(source, &[0]), vec![0]);
+        assert_eq!(run(source, &[10]), vec![55]);
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = "fn main() {
+            var x = input();
+            if x < 0 {
+                output(-1);
+            } else if x == 0 {
+                output(0);
+            } else {
+                output(1);
+            }
+            if x >= 5 { output(5); }
+        }";
+
+        assert_eq!(run(source, &[-7]), vec![-1]);
+        assert_eq!(run(source, &[0]), vec![0]);
+        assert_eq!(run(source, &[3]), vec![1]);
+        assert_eq!(run(source, &[5]), vec![1, 5]);
+    }
+
+    #[test]
+    fn operators() {
+        let source = "fn main() {
+            var a = input();
+            var b = input();
+            output(a + b * 2 - 1);
+            output((a + b) * 2);
+            output(-a);
+            output(a < b);
+            output(a <= b);
+            output(a > b);
+            output(a >= b);
+            output(a == b);
+            output(a != b);
+            output(!a);
+            output(a && b);
+            output(a || b);
+        }";
+
+        assert_eq!(
+            run(source, &[3, 4]),
+            vec![10, 14, -3, 1, 1, 0, 0, 0, 1, 0, 1, 1]
+        );
+        assert_eq!(
+            run(source, &[0, 0]),
+            vec![-1, 0, 0, 0, 1, 0, 1, 1, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn logic_short_circuits() {
+        let source = "fn noisy(x) { output(x); return x; }
+        fn main() {
+            output(noisy(0) && noisy(1));
+            output(noisy(2) || noisy(3));
+            output(noisy(4) && noisy(5));
+        }";
+
+        assert_eq!(run(source, &[]), vec![0, 0, 2, 1, 4, 5, 1]);
+    }
+
+    #[test]
+    fn nested_calls_and_recursion() {
+        let source = "fn add(a, b) { return a + b; }
+        fn twice(x) { return add(x, x); }
+        fn ackermann(m, n) {
+            if m == 0 { return n + 1; }
+            if n == 0 { return ackermann(m - 1, 1); }
+            return ackermann(m - 1, ackermann(m, n - 1));
+        }
+        fn nothing() { }
+        fn main() {
+            output(add(twice(3), add(1, twice(twice(2)))));
+            output(ackermann(2, 3));
+            output(nothing());
+        }";
+
+        assert_eq!(run(source, &[]), vec![15, 9, 0]);
+    }
+
+    #[test]
+    fn globals_and_locals() {
+        let source = "var count = 10;
+        var cells[4] = 7;
+        fn bump() { count = count + 1; }
+        fn shadow(count) { count = count * 100; return count; }
+        fn fill(n) {
+            var i = 0;
+            while i < 4 { cells[i] = n + i; i = i + 1; }
+        }
+        fn main() {
+            output(cells[3]);
+            bump();
+            bump();
+            output(shadow(count));
+            output(count);
+            fill(input());
+            output(cells[0] + cells[3]);
+        }";
+
+        assert_eq!(run(source, &[20]), vec![7, 1200, 12, 43]);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod automaton;
+pub mod expanse;
+pub mod framing;
+pub mod geometry;
+pub mod image;
+pub mod intcode;
+pub mod pathfind;
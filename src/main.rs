@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate glium;
 
+use adventofcode2019::{expanse, geometry, intcode, pathfind};
 use std::process::Command;
 
 mod day1;
@@ -27,8 +28,6 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
-mod expanse;
-mod intcode;
 
 fn day18() {
     let result = String::from_utf8(
@@ -0,0 +1,382 @@
+use crate::expanse::Expanse;
+use crate::geometry::Point2;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::Hash;
+
+// Searches over any graph given as a function from a node to its neighbors. Nodes are
+// usually positions, but anything hashable works, so extra edges like day 20's portals
+// or a third coordinate for its recursion depth just go in the neighbor function.
+
+// Distance from the nearest start to every node reachable from any of them.
+pub fn bfs<N, F, I>(starts: &[N], mut neighbors: F) -> HashMap<N, usize>
+where
+    N: Eq + Hash + Clone,
+    F: FnMut(&N) -> I,
+    I: IntoIterator<Item = N>,
+{
+    let mut distances = HashMap::<N, usize>::new();
+    let mut frontier = VecDeque::<N>::new();
+
+    for start in starts {
+        if !distances.contains_key(start) {
+            distances.insert(start.clone(), 0);
+            frontier.push_back(start.clone());
+        }
+    }
+
+    while let Some(node) = frontier.pop_front() {
+        let next_distance = distances[&node] + 1;
+
+        for next in neighbors(&node) {
+            if !distances.contains_key(&next) {
+                distances.insert(next.clone(), next_distance);
+                frontier.push_back(next);
+            }
+        }
+    }
+
+    distances
+}
+
+// Shortest path from start to the first node satisfying is_goal, with its length in steps.
+// Stops as soon as a goal is reached, so the graph can be infinite.
+pub fn bfs_path<N, G, F, I>(start: N, is_goal: G, mut neighbors: F) -> Option<(Vec<N>, usize)>
+where
+    N: Eq + Hash + Clone,
+    G: Fn(&N) -> bool,
+    F: FnMut(&N) -> I,
+    I: IntoIterator<Item = N>,
+{
+    let mut came_from = HashMap::<N, Option<N>>::new();
+    let mut frontier = VecDeque::<N>::new();
+
+    came_from.insert(start.clone(), None);
+    frontier.push_back(start);
+
+    while let Some(node) = frontier.pop_front() {
+        if is_goal(&node) {
+            let path = trace_path(&came_from, node);
+            let steps = path.len() - 1;
+            return Some((path, steps));
+        }
+
+        for next in neighbors(&node) {
+            if !came_from.contains_key(&next) {
+                came_from.insert(next.clone(), Some(node.clone()));
+                frontier.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+fn trace_path<N>(came_from: &HashMap<N, Option<N>>, end: N) -> Vec<N>
+where
+    N: Eq + Hash + Clone,
+{
+    let mut path = vec![end];
+
+    while let Some(Some(previous)) = came_from.get(path.last().unwrap()) {
+        path.push(previous.clone());
+    }
+
+    path.reverse();
+    path
+}
+
+// Cheapest path from start to the first node satisfying is_goal, where the neighbor function
+// gives each neighbor along with the cost of the edge to it.
+pub fn dijkstra<N, G, F, I>(start: N, is_goal: G, neighbors: F) -> Option<(Vec<N>, usize)>
+where
+    N: Eq + Hash + Clone,
+    G: Fn(&N) -> bool,
+    F: FnMut(&N) -> I,
+    I: IntoIterator<Item = (N, usize)>,
+{
+    search(start, is_goal, neighbors, |_| 0)
+}
+
+// As dijkstra, guided by a heuristic which must never overestimate the remaining cost.
+pub fn astar<N, F, I, H>(start: N, goal: &N, neighbors: F, heuristic: H) -> Option<(Vec<N>, usize)>
+where
+    N: Eq + Hash + Clone,
+    F: FnMut(&N) -> I,
+    I: IntoIterator<Item = (N, usize)>,
+    H: Fn(&N) -> usize,
+{
+    search(start, |x| x == goal, neighbors, heuristic)
+}
+
+pub fn manhattan(a: (i32, i32), b: (i32, i32)) -> usize {
+    Point2::from(a).manhattan(b.into()) as usize
+}
+
+// Nodes are numbered as they're discovered so the queue doesn't need them to be Ord.
+fn search<N, G, F, I, H>(
+    start: N,
+    is_goal: G,
+    mut neighbors: F,
+    heuristic: H,
+) -> Option<(Vec<N>, usize)>
+where
+    N: Eq + Hash + Clone,
+    G: Fn(&N) -> bool,
+    F: FnMut(&N) -> I,
+    I: IntoIterator<Item = (N, usize)>,
+    H: Fn(&N) -> usize,
+{
+    let mut ids = HashMap::<N, usize>::new();
+    let mut nodes = Vec::<N>::new();
+    let mut costs = Vec::<usize>::new();
+    let mut came_from = Vec::<Option<usize>>::new();
+    let mut queue = BinaryHeap::<Reverse<(usize, usize)>>::new();
+
+    ids.insert(start.clone(), 0);
+    queue.push(Reverse((heuristic(&start), 0)));
+    nodes.push(start);
+    costs.push(0);
+    came_from.push(None);
+
+    while let Some(Reverse((estimate, id))) = queue.pop() {
+        let cost = costs[id];
+        if estimate > cost + heuristic(&nodes[id]) {
+            continue;
+        }
+
+        if is_goal(&nodes[id]) {
+            let mut path = vec![nodes[id].clone()];
+            let mut at = id;
+            while let Some(previous) = came_from[at] {
+                path.push(nodes[previous].clone());
+                at = previous;
+            }
+            path.reverse();
+            return Some((path, cost));
+        }
+
+        for (next, weight) in neighbors(&nodes[id]) {
+            let next_cost = cost + weight;
+            let next_id = match ids.get(&next) {
+                Some(&x) if costs[x] <= next_cost => continue,
+                Some(&x) => x,
+                None => {
+                    ids.insert(next.clone(), nodes.len());
+                    nodes.push(next);
+                    costs.push(0);
+                    came_from.push(None);
+                    nodes.len() - 1
+                }
+            };
+
+            costs[next_id] = next_cost;
+            came_from[next_id] = Some(id);
+            queue.push(Reverse((next_cost + heuristic(&nodes[next_id]), next_id)));
+        }
+    }
+
+    None
+}
+
+// The occupied cells orthogonally next to a position, for searching the cells of an Expanse.
+pub fn expanse_neighbors<'a, T, P>(
+    expanse: &'a Expanse<T>,
+    passable: P,
+) -> impl Fn(&(i32, i32)) -> Vec<(i32, i32)> + 'a
+where
+    P: Fn(&T) -> bool + 'a,
+{
+    move |&(x, y)| {
+        expanse
+            .neighbors4(x, y)
+            .filter(|(_, cell)| passable(cell))
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Pos = (i32, i32);
+
+    const MAZE: &str = "\
+#########
+#S..#...#
+#.#.#.#.#
+#.#...#G#
+#########";
+
+    // The top route is shorter in steps, but wading through ~ costs 5 a cell.
+    const SWAMP: &str = "\
+#######
+#S~~~G#
+#.###.#
+#.....#
+#######";
+
+    fn parse(text: &str) -> Expanse<char> {
+        Expanse::from_ascii(text, false, Some)
+    }
+
+    fn open(expanse: &Expanse<char>) -> impl Fn(&(i32, i32)) -> Vec<(i32, i32)> + '_ {
+        expanse_neighbors(expanse, |&x| x != '#')
+    }
+
+    fn weighted(expanse: &Expanse<char>) -> impl Fn(&Pos) -> Vec<(Pos, usize)> + '_ {
+        let neighbors = open(expanse);
+        move |pos| {
+            neighbors(pos)
+                .into_iter()
+                .map(|x| {
+                    (
+                        x,
+                        if expanse.read(x.0, x.1) == Some(&'~') {
+                            5
+                        } else {
+                            1
+                        },
+                    )
+                })
+                .collect()
+        }
+    }
+
+    fn is_step(a: &(i32, i32), b: &(i32, i32)) -> bool {
+        manhattan(*a, *b) == 1
+    }
+
+    #[test]
+    fn expanse_neighbors_skips_walls() {
+        let maze = parse(MAZE);
+        let neighbors = open(&maze);
+
+        let mut found = neighbors(&(1, 1));
+        found.sort_unstable();
+        assert_eq!(found, vec![(1, 2), (2, 1)]);
+        assert_eq!(neighbors(&(3, 1)).len(), 2);
+        assert!(neighbors(&(0, 0)).is_empty());
+        assert_eq!(neighbors(&(4, 4)), vec![(4, 3)]);
+    }
+
+    #[test]
+    fn bfs_gives_distances() {
+        let maze = parse(MAZE);
+        let distances = bfs(&[(1, 1)], open(&maze));
+
+        assert_eq!(distances[&(1, 1)], 0);
+        assert_eq!(distances[&(1, 3)], 2);
+        assert_eq!(distances[&(5, 1)], 8);
+        assert_eq!(distances[&(7, 3)], 12);
+        assert_eq!(distances.len(), 15);
+        assert!(!distances.contains_key(&(2, 2)));
+    }
+
+    #[test]
+    fn bfs_from_many_starts_takes_the_nearest() {
+        let maze = parse(MAZE);
+        let distances = bfs(&[(1, 1), (7, 3), (1, 1)], open(&maze));
+
+        assert_eq!(distances[&(1, 1)], 0);
+        assert_eq!(distances[&(7, 3)], 0);
+        assert_eq!(distances[&(5, 1)], 4);
+        assert_eq!(distances[&(4, 3)], 5);
+        assert_eq!(distances[&(3, 3)], 4);
+        assert_eq!(distances.len(), 15);
+    }
+
+    #[test]
+    fn bfs_path_gives_the_path_and_its_length() {
+        let maze = parse(MAZE);
+        let (path, steps) = bfs_path((1, 1), |&x| x == (7, 3), open(&maze)).unwrap();
+
+        assert_eq!(steps, 12);
+        assert_eq!(path.len(), steps + 1);
+        assert_eq!(path[0], (1, 1));
+        assert_eq!(path[6], (5, 3));
+        assert_eq!(path[12], (7, 3));
+        assert!(path.windows(2).all(|x| is_step(&x[0], &x[1])));
+
+        assert_eq!(
+            bfs_path((1, 1), |&x| x == (1, 1), open(&maze)),
+            Some((vec![(1, 1)], 0))
+        );
+    }
+
+    #[test]
+    fn bfs_path_stops_early_on_infinite_graphs() {
+        let (path, steps) = bfs_path(1u64, |&x| x == 10, |&x| vec![x + 1, x * 2]).unwrap();
+
+        assert_eq!(steps, 4);
+        assert_eq!(path.first(), Some(&1));
+        assert_eq!(path.last(), Some(&10));
+        assert!(path
+            .windows(2)
+            .all(|x| x[1] == x[0] + 1 || x[1] == x[0] * 2));
+    }
+
+    #[test]
+    fn unreachable_goals_give_none() {
+        let maze = parse(MAZE);
+        let mut walled = parse(MAZE);
+        walled.write(6, 1, '#');
+        let wall = (2, 2);
+
+        assert_eq!(bfs_path((1, 1), |&x| x == wall, open(&maze)), None);
+        assert_eq!(bfs_path((1, 1), |&x| x == (7, 3), open(&walled)), None);
+        assert_eq!(dijkstra((1, 1), |&x| x == (7, 3), weighted(&walled)), None);
+        assert_eq!(
+            astar((1, 1), &(7, 3), weighted(&walled), |&x| manhattan(
+                x,
+                (7, 3)
+            )),
+            None
+        );
+        assert_eq!(bfs(&[(1, 1)], open(&walled)).get(&(7, 3)), None);
+    }
+
+    #[test]
+    fn dijkstra_takes_the_cheaper_longer_route() {
+        let swamp = parse(SWAMP);
+        let (start, goal) = ((1, 1), (5, 1));
+
+        let (path, steps) = bfs_path(start, |&x| x == goal, open(&swamp)).unwrap();
+        assert_eq!(steps, 4);
+        assert_eq!(path[1], (2, 1));
+
+        let (path, cost) = dijkstra(start, |&x| x == goal, weighted(&swamp)).unwrap();
+        assert_eq!(cost, 8);
+        assert_eq!(path.len(), 9);
+        assert_eq!(path[1], (1, 2));
+        assert!(path.windows(2).all(|x| is_step(&x[0], &x[1])));
+    }
+
+    #[test]
+    fn astar_matches_dijkstra() {
+        for (text, start, goal) in &[(MAZE, (1, 1), (7, 3)), (SWAMP, (1, 1), (5, 1))] {
+            let expanse = parse(text);
+            let heuristic = |&x: &(i32, i32)| manhattan(x, *goal);
+
+            let expected = dijkstra(*start, |x| x == goal, weighted(&expanse)).unwrap();
+            let found = astar(*start, goal, weighted(&expanse), heuristic).unwrap();
+
+            assert_eq!(found.1, expected.1);
+            assert_eq!(found.0.first(), Some(start));
+            assert_eq!(found.0.last(), Some(goal));
+        }
+
+        // Every reachable cell of the maze, from either end.
+        let maze = parse(MAZE);
+        for (&pos, &distance) in &bfs(&[(1, 1)], open(&maze)) {
+            let heuristic = |&x: &(i32, i32)| manhattan(x, pos);
+            let found = astar((1, 1), &pos, weighted(&maze), heuristic);
+            assert_eq!(found.map(|x| x.1), Some(distance));
+            assert_eq!(
+                dijkstra(pos, |&x| x == (1, 1), weighted(&maze)).map(|x| x.1),
+                Some(distance)
+            );
+        }
+    }
+}